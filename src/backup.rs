@@ -0,0 +1,485 @@
+use crate::database::Database;
+use crate::utils::{copy_file, crc32_extend, link_or_copy, sync_dir};
+use std::fs::{create_dir_all, read_dir, remove_dir_all, remove_file, rename, File, OpenOptions};
+use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Directory of a backup directory that holds the table files shared by backups.
+const SHARED_DIR: &str = "shared";
+/// Directory of a backup directory that holds a directory of the other files of each backup.
+const PRIVATE_DIR: &str = "private";
+/// Directory of a backup directory that holds the metadata file of each backup.
+const META_DIR: &str = "meta";
+
+/// File of a backup, with the name it is restored as and its path within the backup directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct BackupFile {
+  name: String,
+  path: String,
+  size: u64,
+  crc32: u32,
+}
+
+/// Description of a backup in a BackupEngine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackupInfo {
+  pub id: u32,
+  /// Time the backup was created at, in microseconds since the UNIX epoch.
+  pub timestamp: u128,
+  /// Total size of the files of the backup, including the table files shared with other backups.
+  pub size: u64,
+  pub num_files: usize,
+}
+
+/// BackupEngine stores numbered backups of a Database in a backup directory.
+///
+/// A backup is taken from a checkpoint of the Database. Table files are never modified, so they are
+/// stored once in the `shared` directory, named after their number, size and checksum, and every
+/// backup that holds a table file refers to the same copy. The WAL, the MANIFEST, the OPTIONS file
+/// and the blob files are copied into a `private` directory for each backup.
+///
+/// The metadata file of a backup in the `meta` directory lists its files with their sizes and
+/// CRC-32 checksums. It is written last, once the files and their directories are synced to disk,
+/// so a backup that did not finish is not listed, and its files are removed by the next
+/// `purge_old`.
+pub struct BackupEngine {
+  dir: PathBuf,
+}
+
+impl BackupEngine {
+  /// Opens a backup directory, creating it if it does not exist.
+  pub fn open(dir: &str) -> io::Result<BackupEngine> {
+    let dir = PathBuf::from(dir);
+    for sub_dir in [SHARED_DIR, PRIVATE_DIR, META_DIR] {
+      create_dir_all(dir.join(sub_dir))?;
+    }
+    Ok(BackupEngine { dir })
+  }
+
+  /// Creates a new backup of a Database, returning the id of the backup.
+  pub fn create_new_backup(&self, db: &mut Database) -> io::Result<u32> {
+    let id = self.backup_ids()?.last().map_or(1, |id| id + 1);
+    let private_dir = self.dir.join(PRIVATE_DIR).join(id.to_string());
+    if private_dir.exists() {
+      remove_dir_all(&private_dir)?;
+    }
+    db.checkpoint(private_dir.to_str().unwrap())?;
+
+    let mut files = Vec::new();
+    for file in read_dir(&private_dir)? {
+      let path = file?.path();
+      let name = path.file_name().unwrap().to_str().unwrap().to_owned();
+      let (size, crc32) = checksum(&path)?;
+
+      let backup_path = if path.extension().is_some_and(|e| e == "sst") {
+        let shared_name = format!(
+          "{}_{}_{}.sst",
+          path.file_stem().unwrap().to_str().unwrap(),
+          size,
+          crc32
+        );
+        let shared_path = self.dir.join(SHARED_DIR).join(&shared_name);
+        if shared_path.exists() {
+          remove_file(&path)?;
+        } else {
+          rename(&path, &shared_path)?;
+          File::open(&shared_path)?.sync_all()?;
+        }
+        format!("{}/{}", SHARED_DIR, shared_name)
+      } else {
+        File::open(&path)?.sync_all()?;
+        format!("{}/{}/{}", PRIVATE_DIR, id, name)
+      };
+      files.push(BackupFile {
+        name,
+        path: backup_path,
+        size,
+        crc32,
+      });
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    // The files must be durable before the metadata file lists them.
+    sync_dir(&self.dir.join(SHARED_DIR))?;
+    sync_dir(&private_dir)?;
+    sync_dir(&self.dir.join(PRIVATE_DIR))?;
+
+    let timestamp = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap()
+      .as_micros();
+    self.write_meta(id, timestamp, &files)?;
+    Ok(id)
+  }
+
+  /// Gets the backups, oldest first.
+  pub fn backups(&self) -> io::Result<Vec<BackupInfo>> {
+    let mut backups = Vec::new();
+    for id in self.backup_ids()? {
+      let (timestamp, files) = self.read_meta(id)?;
+      backups.push(BackupInfo {
+        id,
+        timestamp,
+        size: files.iter().map(|f| f.size).sum(),
+        num_files: files.len(),
+      });
+    }
+    Ok(backups)
+  }
+
+  /// Restores a backup into a directory, which must not already hold any files.
+  ///
+  /// The checksum of every file is verified as it is restored. The restored directory can be
+  /// opened with `Database::open`.
+  pub fn restore(&self, backup_id: u32, dir: &str) -> io::Result<()> {
+    let dir = PathBuf::from(dir);
+    if dir.exists() && read_dir(&dir)?.next().is_some() {
+      return Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("restore directory {} is not empty", dir.display()),
+      ));
+    }
+    create_dir_all(&dir)?;
+
+    let (_, files) = self.read_meta(backup_id)?;
+    for file in files.iter() {
+      let path = dir.join(&file.name);
+      // Table files are never modified, while the restored Database appends to its WAL and blob
+      // files.
+      if file.path.starts_with(SHARED_DIR) {
+        link_or_copy(&self.dir.join(&file.path), &path)?;
+      } else {
+        copy_file(&self.dir.join(&file.path), &path)?;
+      }
+      verify_file(&path, file)?;
+    }
+    Ok(())
+  }
+
+  /// Verifies the sizes and checksums of the files of a backup.
+  pub fn verify(&self, backup_id: u32) -> io::Result<()> {
+    let (_, files) = self.read_meta(backup_id)?;
+    for file in files.iter() {
+      verify_file(&self.dir.join(&file.path), file)?;
+    }
+    Ok(())
+  }
+
+  /// Deletes every backup but the newest `keep_n`, and the table files no backup refers to.
+  pub fn purge_old(&self, keep_n: usize) -> io::Result<()> {
+    let ids = self.backup_ids()?;
+    for id in ids.iter().take(ids.len().saturating_sub(keep_n)) {
+      self.delete_backup(*id)?;
+    }
+    self.collect_garbage()
+  }
+
+  /// Deletes a backup, leaving the table files it shares with other backups.
+  pub fn delete_backup(&self, backup_id: u32) -> io::Result<()> {
+    remove_file(self.meta_path(backup_id))?;
+    let private_dir = self.dir.join(PRIVATE_DIR).join(backup_id.to_string());
+    if private_dir.exists() {
+      remove_dir_all(private_dir)?;
+    }
+    Ok(())
+  }
+
+  /// Removes the shared table files and private directories that no backup refers to, such as the
+  /// files of deleted backups and of backups that did not finish.
+  fn collect_garbage(&self) -> io::Result<()> {
+    let ids = self.backup_ids()?;
+    let mut live = Vec::new();
+    for id in ids.iter() {
+      live.extend(self.read_meta(*id)?.1.into_iter().map(|f| f.path));
+    }
+
+    for file in read_dir(self.dir.join(SHARED_DIR))? {
+      let path = file?.path();
+      let name = path.file_name().unwrap().to_str().unwrap();
+      if !live.contains(&format!("{}/{}", SHARED_DIR, name)) {
+        remove_file(path)?;
+      }
+    }
+    for file in read_dir(self.dir.join(PRIVATE_DIR))? {
+      let path = file?.path();
+      let id = path.file_name().and_then(|n| n.to_str()?.parse().ok());
+      if !id.is_some_and(|id| ids.contains(&id)) {
+        remove_dir_all(path)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Gets the ids of the backups, in increasing order.
+  fn backup_ids(&self) -> io::Result<Vec<u32>> {
+    let mut ids = Vec::new();
+    for file in read_dir(self.dir.join(META_DIR))? {
+      let path = file?.path();
+      if let Some(id) = path.file_name().and_then(|n| n.to_str()?.parse().ok()) {
+        ids.push(id);
+      }
+    }
+    ids.sort_unstable();
+    Ok(ids)
+  }
+
+  fn meta_path(&self, backup_id: u32) -> PathBuf {
+    self.dir.join(META_DIR).join(backup_id.to_string())
+  }
+
+  /// Writes the metadata file of a backup, as `name=value` lines with a `file=<name>:<path>:<size>:<crc32>`
+  /// line for each file.
+  fn write_meta(&self, backup_id: u32, timestamp: u128, files: &[BackupFile]) -> io::Result<()> {
+    let path = self.meta_path(backup_id);
+    let tmp_path = path.with_extension("tmp");
+    let file = OpenOptions::new()
+      .write(true)
+      .create(true)
+      .truncate(true)
+      .open(&tmp_path)?;
+    let mut file = BufWriter::new(file);
+    writeln!(file, "timestamp={}", timestamp)?;
+    for backup_file in files.iter() {
+      writeln!(
+        file,
+        "file={}:{}:{}:{}",
+        backup_file.name, backup_file.path, backup_file.size, backup_file.crc32
+      )?;
+    }
+    file.flush()?;
+    file.get_ref().sync_all()?;
+
+    rename(tmp_path, path)?;
+    sync_dir(&self.dir.join(META_DIR))
+  }
+
+  /// Reads the metadata file of a backup.
+  fn read_meta(&self, backup_id: u32) -> io::Result<(u128, Vec<BackupFile>)> {
+    let file = match File::open(self.meta_path(backup_id)) {
+      Ok(file) => file,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => {
+        return Err(io::Error::new(
+          io::ErrorKind::NotFound,
+          format!("backup {} does not exist", backup_id),
+        ))
+      }
+      Err(e) => return Err(e),
+    };
+    let corrupted = || {
+      io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("metadata of backup {} is corrupted", backup_id),
+      )
+    };
+
+    let mut timestamp = 0;
+    let mut files = Vec::new();
+    for line in BufReader::new(file).lines() {
+      let line = line?;
+      let (name, value) = match line.find('=') {
+        Some(idx) => (&line[..idx], &line[idx + 1..]),
+        None => continue,
+      };
+      match name {
+        "timestamp" => timestamp = value.parse().map_err(|_| corrupted())?,
+        "file" => {
+          let parts: Vec<&str> = value.split(':').collect();
+          if parts.len() != 4 {
+            return Err(corrupted());
+          }
+          files.push(BackupFile {
+            name: parts[0].to_owned(),
+            path: parts[1].to_owned(),
+            size: parts[2].parse().map_err(|_| corrupted())?,
+            crc32: parts[3].parse().map_err(|_| corrupted())?,
+          });
+        }
+        _ => {}
+      }
+    }
+    Ok((timestamp, files))
+  }
+}
+
+/// Computes the size and CRC-32 checksum of a file.
+fn checksum(path: &Path) -> io::Result<(u64, u32)> {
+  let mut file = File::open(path)?;
+  let mut buf = vec![0; 64 * 1024];
+  let mut size = 0;
+  let mut crc = 0;
+  loop {
+    let n = file.read(&mut buf)?;
+    if n == 0 {
+      return Ok((size, crc));
+    }
+    size += n as u64;
+    crc = crc32_extend(crc, &buf[..n]);
+  }
+}
+
+/// Checks that a file has the size and checksum recorded in the metadata of its backup.
+fn verify_file(path: &Path, file: &BackupFile) -> io::Result<()> {
+  let (size, crc32) = checksum(path)?;
+  if size != file.size || crc32 != file.crc32 {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      format!("backup file {} is corrupted", file.path),
+    ));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::backup::BackupEngine;
+  use crate::database::Database;
+  use crate::options::Options;
+  use rand::Rng;
+  use std::fs::{read_dir, remove_dir_all, OpenOptions};
+  use std::io::prelude::*;
+  use std::io::{ErrorKind, SeekFrom};
+  use std::path::PathBuf;
+
+  #[test]
+  fn test_backup_engine() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let backup_dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let restore_dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let options = Options::new().memtable_size(2048);
+
+    let mut db = Database::open(dir.to_str().unwrap(), options.clone()).unwrap();
+    let engine = BackupEngine::open(backup_dir.to_str().unwrap()).unwrap();
+    for i in 0..300u32 {
+      db.set(&i.to_be_bytes(), b"Lime Smoothie").unwrap();
+    }
+    assert_eq!(engine.create_new_backup(&mut db).unwrap(), 1);
+    for i in 300..600u32 {
+      db.set(&i.to_be_bytes(), b"Apple Smoothie").unwrap();
+    }
+    db.delete(&0u32.to_be_bytes()).unwrap();
+    assert_eq!(engine.create_new_backup(&mut db).unwrap(), 2);
+
+    // The table files of the first backup are shared with the second.
+    let backups = engine.backups().unwrap();
+    assert_eq!(backups.len(), 2);
+    assert_eq!(backups[1].id, 2);
+    let table_files = read_dir(&dir)
+      .unwrap()
+      .filter(|f| {
+        let path = f.as_ref().unwrap().path();
+        path.extension().is_some_and(|e| e == "sst")
+      })
+      .count();
+    assert!(table_files > 1);
+    assert_eq!(
+      read_dir(backup_dir.join("shared")).unwrap().count(),
+      table_files
+    );
+    engine.verify(1).unwrap();
+    engine.verify(2).unwrap();
+
+    engine.restore(1, restore_dir.to_str().unwrap()).unwrap();
+    assert!(engine.restore(2, restore_dir.to_str().unwrap()).is_err());
+    {
+      let restored = Database::open(restore_dir.to_str().unwrap(), options.clone()).unwrap();
+      assert_eq!(
+        restored.get(&0u32.to_be_bytes()).unwrap().unwrap().value(),
+        b"Lime Smoothie"
+      );
+      assert!(restored.get(&300u32.to_be_bytes()).unwrap().is_none());
+    }
+    remove_dir_all(&restore_dir).unwrap();
+
+    engine.purge_old(1).unwrap();
+    assert_eq!(engine.backups().unwrap().len(), 1);
+    assert!(engine.verify(1).is_err());
+    engine.verify(2).unwrap();
+    engine.restore(2, restore_dir.to_str().unwrap()).unwrap();
+    {
+      let restored = Database::open(restore_dir.to_str().unwrap(), options).unwrap();
+      assert!(restored.get(&0u32.to_be_bytes()).unwrap().is_none());
+      assert_eq!(
+        restored
+          .get(&599u32.to_be_bytes())
+          .unwrap()
+          .unwrap()
+          .value(),
+        b"Apple Smoothie"
+      );
+    }
+
+    let private_file = read_dir(backup_dir.join("private").join("2"))
+      .unwrap()
+      .next()
+      .unwrap()
+      .unwrap()
+      .path();
+    let mut file = OpenOptions::new().append(true).open(private_file).unwrap();
+    file.write_all(b"corruption").unwrap();
+    assert!(engine.verify(2).is_err());
+
+    remove_dir_all(&dir).unwrap();
+    remove_dir_all(&backup_dir).unwrap();
+    remove_dir_all(&restore_dir).unwrap();
+  }
+
+  #[test]
+  fn test_backup_corruption() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let backup_dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let restore_dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let options = Options::new().memtable_size(2048);
+
+    let engine = BackupEngine::open(backup_dir.to_str().unwrap()).unwrap();
+    {
+      let mut db = Database::open(dir.to_str().unwrap(), options).unwrap();
+      for i in 0..300u32 {
+        db.set(&i.to_be_bytes(), b"Lime Smoothie").unwrap();
+      }
+      assert_eq!(engine.create_new_backup(&mut db).unwrap(), 1);
+    }
+
+    let err = engine.verify(2).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    let err = engine
+      .restore(2, restore_dir.to_str().unwrap())
+      .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    // A shared table file is overwritten in place, so only its checksum tells it is corrupted.
+    let shared_file = read_dir(backup_dir.join("shared"))
+      .unwrap()
+      .next()
+      .unwrap()
+      .unwrap()
+      .path();
+    let mut file = OpenOptions::new().write(true).open(&shared_file).unwrap();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.write_all(b"Mango").unwrap();
+    drop(file);
+
+    let err = engine.verify(1).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    let err = engine
+      .restore(1, restore_dir.to_str().unwrap())
+      .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    // A metadata file that cannot be parsed fails the backup, and the listing of backups.
+    let mut meta = OpenOptions::new()
+      .append(true)
+      .open(backup_dir.join("meta").join("1"))
+      .unwrap();
+    meta.write_all(b"file=Lime\n").unwrap();
+    drop(meta);
+    assert_eq!(engine.verify(1).unwrap_err().kind(), ErrorKind::InvalidData);
+    assert_eq!(engine.backups().unwrap_err().kind(), ErrorKind::InvalidData);
+
+    remove_dir_all(&dir).unwrap();
+    remove_dir_all(&backup_dir).unwrap();
+    remove_dir_all(&restore_dir).unwrap();
+  }
+}
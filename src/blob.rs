@@ -0,0 +1,321 @@
+use crate::utils::files_with_ext;
+use std::collections::BTreeMap;
+use std::fs::{remove_file, File, OpenOptions};
+use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Size of the header of a record in a blob file: the key length and value length.
+const RECORD_HEADER_SIZE: u64 = 16;
+
+/// Pointer to a value in a blob file, stored in place of the value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlobIndex {
+  pub file_number: u64,
+  /// Offset of the record in the blob file.
+  pub offset: u64,
+  pub key_len: u64,
+  pub value_len: u64,
+}
+
+impl BlobIndex {
+  /// Encodes the BlobIndex as the value of an entry.
+  pub fn encode(&self) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(32);
+    buf.extend_from_slice(&self.file_number.to_le_bytes());
+    buf.extend_from_slice(&self.offset.to_le_bytes());
+    buf.extend_from_slice(&self.key_len.to_le_bytes());
+    buf.extend_from_slice(&self.value_len.to_le_bytes());
+    buf
+  }
+
+  /// Decodes a BlobIndex from the value of an entry.
+  pub fn decode(buf: &[u8]) -> Option<BlobIndex> {
+    if buf.len() != 32 {
+      return None;
+    }
+    let field = |i: usize| {
+      let mut bytes = [0; 8];
+      bytes.copy_from_slice(&buf[i * 8..i * 8 + 8]);
+      u64::from_le_bytes(bytes)
+    };
+    Some(BlobIndex {
+      file_number: field(0),
+      offset: field(1),
+      key_len: field(2),
+      value_len: field(3),
+    })
+  }
+
+  /// Gets the size of the record in the blob file.
+  fn record_size(&self) -> u64 {
+    RECORD_HEADER_SIZE + self.key_len + self.value_len
+  }
+}
+
+/// Live and total bytes of a blob file.
+///
+/// The bytes of a record become garbage once the entry that points to it is overwritten, deleted
+/// or relocated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlobFileStats {
+  pub file_number: u64,
+  pub total_bytes: u64,
+  pub live_bytes: u64,
+}
+
+impl BlobFileStats {
+  /// Gets the number of bytes of the blob file that are garbage.
+  pub fn garbage_bytes(&self) -> u64 {
+    self.total_bytes - self.live_bytes
+  }
+
+  /// Gets the fraction of the blob file that is garbage.
+  pub fn garbage_ratio(&self) -> f64 {
+    if self.total_bytes == 0 {
+      return 0.0;
+    }
+    self.garbage_bytes() as f64 / self.total_bytes as f64
+  }
+}
+
+/// Blob file that is being appended to.
+struct BlobFileWriter {
+  file_number: u64,
+  file: BufWriter<File>,
+  size: u64,
+}
+
+/// Append-only blob files holding large values, separated from their keys (WiscKey).
+///
+/// Each record in a blob file is `[key length][value length][key][value]`, with the lengths as
+/// little-endian u64s. The key is kept so garbage collection can find the entry that points to
+/// the record. A new blob file is started once the current one reaches the maximum file size.
+pub(crate) struct BlobStore {
+  dir: PathBuf,
+  max_file_size: u64,
+  writer: Option<BlobFileWriter>,
+  next_file_number: u64,
+  stats: BTreeMap<u64, BlobFileStats>,
+}
+
+impl BlobStore {
+  /// Opens the blob files within a directory.
+  ///
+  /// Every byte of the existing blob files is counted as garbage until it is marked as live.
+  pub fn open(dir: &Path, max_file_size: u64) -> io::Result<BlobStore> {
+    let mut stats = BTreeMap::new();
+    for path in files_with_ext(dir, "blob") {
+      let file_number = match BlobStore::file_number(&path) {
+        Some(file_number) => file_number,
+        None => continue,
+      };
+      stats.insert(
+        file_number,
+        BlobFileStats {
+          file_number,
+          total_bytes: path.metadata()?.len(),
+          live_bytes: 0,
+        },
+      );
+    }
+    let next_file_number = stats.keys().next_back().map_or(0, |n| n + 1);
+
+    Ok(BlobStore {
+      dir: dir.to_owned(),
+      max_file_size,
+      writer: None,
+      next_file_number,
+      stats,
+    })
+  }
+
+  /// Appends a value to the current blob file, returning the BlobIndex that points to it.
+  ///
+  /// The blob file is flushed, so the value can be read back right away.
+  pub fn put(&mut self, key: &[u8], value: &[u8]) -> io::Result<BlobIndex> {
+    if self
+      .writer
+      .as_ref()
+      .is_some_and(|w| w.size >= self.max_file_size)
+    {
+      self.writer = None;
+    }
+    if self.writer.is_none() {
+      let file_number = self.next_file_number;
+      let file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(self.path(file_number))?;
+      self.next_file_number += 1;
+      self.stats.insert(
+        file_number,
+        BlobFileStats {
+          file_number,
+          total_bytes: 0,
+          live_bytes: 0,
+        },
+      );
+      self.writer = Some(BlobFileWriter {
+        file_number,
+        file: BufWriter::new(file),
+        size: 0,
+      });
+    }
+
+    let writer = self.writer.as_mut().unwrap();
+    let index = BlobIndex {
+      file_number: writer.file_number,
+      offset: writer.size,
+      key_len: key.len() as u64,
+      value_len: value.len() as u64,
+    };
+    writer.file.write_all(&index.key_len.to_le_bytes())?;
+    writer.file.write_all(&index.value_len.to_le_bytes())?;
+    writer.file.write_all(key)?;
+    writer.file.write_all(value)?;
+    writer.file.flush()?;
+    writer.size += index.record_size();
+
+    let stats = self.stats.get_mut(&index.file_number).unwrap();
+    stats.total_bytes += index.record_size();
+    stats.live_bytes += index.record_size();
+
+    Ok(index)
+  }
+
+  /// Syncs the current blob file to disk.
+  pub fn sync(&mut self) -> io::Result<()> {
+    match self.writer.as_mut() {
+      Some(writer) => {
+        writer.file.flush()?;
+        writer.file.get_ref().sync_all()
+      }
+      None => Ok(()),
+    }
+  }
+
+  /// Reads the value a BlobIndex points to.
+  pub fn get(&self, index: &BlobIndex) -> io::Result<Vec<u8>> {
+    let mut file = File::open(self.path(index.file_number))?;
+    file.seek(SeekFrom::Start(
+      index.offset + RECORD_HEADER_SIZE + index.key_len,
+    ))?;
+    let mut value = vec![0; index.value_len as usize];
+    file.read_exact(&mut value)?;
+    Ok(value)
+  }
+
+  /// Reads the key of every record in a blob file, with the BlobIndex of the record.
+  pub fn records(&self, file_number: u64) -> io::Result<Vec<(Vec<u8>, BlobIndex)>> {
+    let mut reader = BufReader::new(File::open(self.path(file_number))?);
+    let mut records = Vec::new();
+    let mut offset = 0;
+    let mut buffer = [0; 8];
+    loop {
+      match reader.read_exact(&mut buffer) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+        Err(e) => return Err(e),
+      }
+      let key_len = u64::from_le_bytes(buffer);
+      reader.read_exact(&mut buffer)?;
+      let value_len = u64::from_le_bytes(buffer);
+      let mut key = vec![0; key_len as usize];
+      reader.read_exact(&mut key)?;
+      reader.seek_relative(value_len as i64)?;
+
+      let index = BlobIndex {
+        file_number,
+        offset,
+        key_len,
+        value_len,
+      };
+      offset += index.record_size();
+      records.push((key, index));
+    }
+
+    Ok(records)
+  }
+
+  /// Marks the record a BlobIndex points to as live.
+  pub fn mark_live(&mut self, index: &BlobIndex) {
+    if let Some(stats) = self.stats.get_mut(&index.file_number) {
+      stats.live_bytes = (stats.live_bytes + index.record_size()).min(stats.total_bytes);
+    }
+  }
+
+  /// Marks the record a BlobIndex points to as garbage.
+  pub fn mark_garbage(&mut self, index: &BlobIndex) {
+    if let Some(stats) = self.stats.get_mut(&index.file_number) {
+      stats.live_bytes = stats.live_bytes.saturating_sub(index.record_size());
+    }
+  }
+
+  /// Gets the blob files, other than the current blob file, with at least a ratio of garbage.
+  pub fn garbage_collection_candidates(&self, garbage_ratio: f64) -> Vec<u64> {
+    let current = self.writer.as_ref().map(|w| w.file_number);
+    self
+      .stats
+      .values()
+      .filter(|s| Some(s.file_number) != current && s.garbage_ratio() >= garbage_ratio)
+      .map(|s| s.file_number)
+      .collect()
+  }
+
+  /// Deletes a blob file.
+  pub fn remove(&mut self, file_number: u64) -> io::Result<()> {
+    self.stats.remove(&file_number);
+    remove_file(self.path(file_number))
+  }
+
+  /// Gets the live and total bytes of every blob file.
+  pub fn stats(&self) -> Vec<BlobFileStats> {
+    self.stats.values().copied().collect()
+  }
+
+  /// Gets the path of a blob file.
+  fn path(&self, file_number: u64) -> PathBuf {
+    self.dir.join(file_number.to_string() + ".blob")
+  }
+
+  /// Gets the number of a blob file from its path.
+  fn file_number(path: &Path) -> Option<u64> {
+    path.file_stem()?.to_str()?.parse().ok()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::blob::{BlobIndex, BlobStore};
+  use rand::Rng;
+  use std::fs::{create_dir, remove_dir_all};
+  use std::path::PathBuf;
+
+  #[test]
+  fn test_blob_store() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let mut store = BlobStore::open(&dir, 100).unwrap();
+    let lime = store.put(b"Lime", &[1; 80]).unwrap();
+    let apple = store.put(b"Apple", &[2; 80]).unwrap();
+    assert_eq!(lime.file_number, 0);
+    assert_eq!(apple.file_number, 1);
+    assert_eq!(BlobIndex::decode(&lime.encode()), Some(lime));
+
+    assert_eq!(store.get(&lime).unwrap(), vec![1; 80]);
+    assert_eq!(store.get(&apple).unwrap(), vec![2; 80]);
+    assert_eq!(store.records(0).unwrap(), vec![(b"Lime".to_vec(), lime)]);
+
+    store.mark_garbage(&lime);
+    assert_eq!(store.stats()[0].garbage_ratio(), 1.0);
+    assert_eq!(store.garbage_collection_candidates(0.5), vec![0]);
+
+    let store = BlobStore::open(&dir, 100).unwrap();
+    assert_eq!(store.garbage_collection_candidates(0.5), vec![0, 1]);
+
+    remove_dir_all(&dir).unwrap();
+  }
+}
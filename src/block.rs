@@ -0,0 +1,386 @@
+use crate::comparator::Comparator;
+use crate::utils::{read_varint, write_varint};
+use std::cmp::Ordering;
+use std::io;
+use std::sync::Arc;
+
+/// Default number of entries between restart points.
+pub const DEFAULT_RESTART_INTERVAL: usize = 16;
+
+/// Builds a data block of sorted Key-Value pairs.
+///
+/// Each key is stored as the number of bytes it shares with the previous key, followed by the rest
+/// of the key, so keys with long common prefixes take little space. Every `restart_interval`
+/// entries, a key is stored in full. These restart points allow binary search within the block.
+///
+/// An entry is `[shared][non_shared][value length][key delta][value]`, with the lengths as varints.
+/// The block ends with the offsets of the restart points and the number of restart points, each as
+/// a little-endian u32.
+pub struct BlockBuilder {
+  buf: Vec<u8>,
+  restarts: Vec<u32>,
+  restart_interval: usize,
+  counter: usize,
+  last_key: Vec<u8>,
+}
+
+impl BlockBuilder {
+  /// Creates a new BlockBuilder with a number of entries between restart points.
+  pub fn new(restart_interval: usize) -> BlockBuilder {
+    BlockBuilder {
+      buf: Vec::new(),
+      restarts: vec![0],
+      restart_interval: restart_interval.max(1),
+      counter: 0,
+      last_key: Vec::new(),
+    }
+  }
+
+  /// Adds a Key-Value pair to the block.
+  ///
+  /// Keys must be added in the order of the table's Comparator.
+  pub fn add(&mut self, key: &[u8], value: &[u8]) {
+    let mut shared = 0;
+    if self.counter < self.restart_interval {
+      shared = self
+        .last_key
+        .iter()
+        .zip(key.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    } else {
+      self.restarts.push(self.buf.len() as u32);
+      self.counter = 0;
+    }
+
+    write_varint(&mut self.buf, shared as u64);
+    write_varint(&mut self.buf, (key.len() - shared) as u64);
+    write_varint(&mut self.buf, value.len() as u64);
+    self.buf.extend_from_slice(&key[shared..]);
+    self.buf.extend_from_slice(value);
+
+    self.last_key.clear();
+    self.last_key.extend_from_slice(key);
+    self.counter += 1;
+  }
+
+  /// Gets the size in bytes the block will have once it is finished.
+  pub fn estimated_size(&self) -> usize {
+    self.buf.len() + self.restarts.len() * 4 + 4
+  }
+
+  /// Checks if no entries were added to the block.
+  pub fn is_empty(&self) -> bool {
+    self.buf.is_empty()
+  }
+
+  /// Finishes the block by appending the restart points.
+  pub fn finish(mut self) -> Vec<u8> {
+    for restart in self.restarts.iter() {
+      self.buf.extend_from_slice(&restart.to_le_bytes());
+    }
+    self
+      .buf
+      .extend_from_slice(&(self.restarts.len() as u32).to_le_bytes());
+    self.buf
+  }
+}
+
+/// Data block of sorted Key-Value pairs, written by a BlockBuilder.
+pub struct Block {
+  data: Arc<Vec<u8>>,
+  restarts_offset: usize,
+  num_restarts: usize,
+}
+
+impl Block {
+  /// Creates a Block from the bytes of a finished block.
+  pub fn new(data: Arc<Vec<u8>>) -> io::Result<Block> {
+    let corrupted = || io::Error::new(io::ErrorKind::InvalidData, "block is corrupted");
+    if data.len() < 4 {
+      return Err(corrupted());
+    }
+    let num_restarts = read_u32(&data, data.len() - 4) as usize;
+    let restarts_offset = (data.len() - 4)
+      .checked_sub(num_restarts * 4)
+      .ok_or_else(corrupted)?;
+    if num_restarts == 0 {
+      return Err(corrupted());
+    }
+
+    Ok(Block {
+      data,
+      restarts_offset,
+      num_restarts,
+    })
+  }
+
+  /// Gets the size of the block in bytes.
+  pub fn size(&self) -> usize {
+    self.data.len()
+  }
+
+  /// Creates an iterator over the entries of the block, which are sorted by a Comparator.
+  pub fn iter(self: &Arc<Self>, comparator: Arc<dyn Comparator>) -> BlockIterator {
+    BlockIterator {
+      block: self.clone(),
+      comparator,
+      current: self.restarts_offset,
+      next: self.restarts_offset,
+      restart_index: self.num_restarts,
+      key: Vec::new(),
+      value: (0, 0),
+    }
+  }
+
+  /// Gets the offset of a restart point.
+  fn restart(&self, index: usize) -> usize {
+    read_u32(&self.data, self.restarts_offset + index * 4) as usize
+  }
+}
+
+/// Reads a little-endian u32 from a buffer at an offset.
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+  let mut bytes = [0; 4];
+  bytes.copy_from_slice(&buf[offset..offset + 4]);
+  u32::from_le_bytes(bytes)
+}
+
+/// Iterator over the entries of a Block that can seek and move forward and backward.
+///
+/// The iterator starts out invalid, and must be positioned with one of the seek methods.
+pub struct BlockIterator {
+  block: Arc<Block>,
+  comparator: Arc<dyn Comparator>,
+  /// Offset of the current entry, which is the end of the entries if the iterator is not valid.
+  current: usize,
+  /// Offset of the entry after the current entry.
+  next: usize,
+  /// Index of the restart point at or before the current entry.
+  restart_index: usize,
+  key: Vec<u8>,
+  /// Start and end offset of the value of the current entry.
+  value: (usize, usize),
+}
+
+impl BlockIterator {
+  /// Checks if the iterator is positioned at an entry.
+  pub fn valid(&self) -> bool {
+    self.current < self.block.restarts_offset
+  }
+
+  /// Gets the key of the current entry.
+  pub fn key(&self) -> &[u8] {
+    &self.key
+  }
+
+  /// Gets the value of the current entry.
+  pub fn value(&self) -> &[u8] {
+    &self.block.data[self.value.0..self.value.1]
+  }
+
+  /// Positions the iterator at the first entry.
+  pub fn seek_to_first(&mut self) {
+    self.seek_to_restart(0);
+    self.parse_next();
+  }
+
+  /// Positions the iterator at the last entry.
+  pub fn seek_to_last(&mut self) {
+    self.seek_to_restart(self.block.num_restarts - 1);
+    while self.parse_next() && self.next < self.block.restarts_offset {}
+  }
+
+  /// Positions the iterator at the first entry with a key at or after the target.
+  pub fn seek(&mut self, target: &[u8]) {
+    // Binary search for the last restart point with a key before the target.
+    let mut left = 0;
+    let mut right = self.block.num_restarts - 1;
+    while left < right {
+      let mid = (left + right).div_ceil(2);
+      self.seek_to_restart(mid);
+      if !self.parse_next() {
+        return;
+      }
+      if self.comparator.compare(&self.key, target) == Ordering::Less {
+        left = mid;
+      } else {
+        right = mid - 1;
+      }
+    }
+
+    self.seek_to_restart(left);
+    while self.parse_next() {
+      if self.comparator.compare(&self.key, target) != Ordering::Less {
+        return;
+      }
+    }
+  }
+
+  /// Positions the iterator at the last entry with a key at or before the target.
+  pub fn seek_for_prev(&mut self, target: &[u8]) {
+    self.seek(target);
+    if !self.valid() {
+      self.seek_to_last();
+    } else if self.comparator.compare(&self.key, target) == Ordering::Greater {
+      self.prev();
+    }
+  }
+
+  /// Moves the iterator to the next entry.
+  pub fn next(&mut self) {
+    if self.valid() {
+      self.parse_next();
+    }
+  }
+
+  /// Moves the iterator to the previous entry, or invalidates it at the first entry.
+  pub fn prev(&mut self) {
+    if !self.valid() {
+      return;
+    }
+
+    let original = self.current;
+    while self.block.restart(self.restart_index) >= original {
+      if self.restart_index == 0 {
+        self.invalidate();
+        return;
+      }
+      self.restart_index -= 1;
+    }
+
+    self.seek_to_restart(self.restart_index);
+    while self.parse_next() && self.next < original {}
+  }
+
+  /// Positions the iterator just before a restart point, so the next parsed entry is the restart.
+  fn seek_to_restart(&mut self, index: usize) {
+    self.key.clear();
+    self.restart_index = index;
+    self.next = self.block.restart(index);
+  }
+
+  /// Parses the entry after the current entry, returning `false` at the end of the block.
+  fn parse_next(&mut self) -> bool {
+    self.current = self.next;
+    if self.current >= self.block.restarts_offset {
+      self.invalidate();
+      return false;
+    }
+
+    let data = &self.block.data[..self.block.restarts_offset];
+    let mut pos = self.current;
+    let entry = (|| {
+      let shared = read_varint(data, &mut pos)? as usize;
+      let non_shared = read_varint(data, &mut pos)? as usize;
+      let value_len = read_varint(data, &mut pos)? as usize;
+      let delta = data.get(pos..pos.checked_add(non_shared)?)?;
+      let value_end = (pos + non_shared).checked_add(value_len)?;
+      if shared > self.key.len() || value_end > data.len() {
+        return None;
+      }
+      Some((shared, delta, value_end))
+    })();
+
+    match entry {
+      Some((shared, delta, value_end)) => {
+        self.key.truncate(shared);
+        self.key.extend_from_slice(delta);
+        self.value = (pos + delta.len(), value_end);
+        self.next = value_end;
+        while self.restart_index + 1 < self.block.num_restarts
+          && self.block.restart(self.restart_index + 1) <= self.current
+        {
+          self.restart_index += 1;
+        }
+        true
+      }
+      None => {
+        self.invalidate();
+        false
+      }
+    }
+  }
+
+  /// Marks the iterator as not positioned at an entry.
+  fn invalidate(&mut self) {
+    self.current = self.block.restarts_offset;
+    self.next = self.block.restarts_offset;
+    self.restart_index = self.block.num_restarts;
+    self.key.clear();
+    self.value = (0, 0);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::block::{Block, BlockBuilder};
+  use crate::comparator::BytewiseComparator;
+  use std::sync::Arc;
+
+  fn build_block() -> (Vec<Vec<u8>>, Arc<Block>, usize) {
+    let keys: Vec<Vec<u8>> = (0..100)
+      .map(|i| format!("org/1234/project/5678/item/{:04}", i * 2).into_bytes())
+      .collect();
+    let mut builder = BlockBuilder::new(16);
+    let mut raw_size = 0;
+    for key in keys.iter() {
+      builder.add(key, b"Smoothie");
+      raw_size += key.len() + 8;
+    }
+    let block = Block::new(Arc::new(builder.finish())).unwrap();
+    (keys, Arc::new(block), raw_size)
+  }
+
+  #[test]
+  fn test_block_prefix_compression() {
+    let (keys, block, raw_size) = build_block();
+    assert!(block.size() < raw_size / 2);
+
+    let mut iter = block.iter(Arc::new(BytewiseComparator));
+    iter.seek_to_first();
+    for key in keys.iter() {
+      assert!(iter.valid());
+      assert_eq!(iter.key(), &key[..]);
+      assert_eq!(iter.value(), b"Smoothie");
+      iter.next();
+    }
+    assert!(!iter.valid());
+
+    iter.seek_to_last();
+    for key in keys.iter().rev() {
+      assert!(iter.valid());
+      assert_eq!(iter.key(), &key[..]);
+      iter.prev();
+    }
+    assert!(!iter.valid());
+  }
+
+  #[test]
+  fn test_block_seek() {
+    let (keys, block, _) = build_block();
+    let mut iter = block.iter(Arc::new(BytewiseComparator));
+
+    iter.seek(b"org/1234/project/5678/item/0100");
+    assert_eq!(iter.key(), &keys[50][..]);
+    iter.seek(b"org/1234/project/5678/item/0101");
+    assert_eq!(iter.key(), &keys[51][..]);
+    iter.prev();
+    assert_eq!(iter.key(), &keys[50][..]);
+    iter.seek(b"a");
+    assert_eq!(iter.key(), &keys[0][..]);
+    iter.seek(b"z");
+    assert!(!iter.valid());
+
+    iter.seek_for_prev(b"org/1234/project/5678/item/0101");
+    assert_eq!(iter.key(), &keys[50][..]);
+    iter.seek_for_prev(b"org/1234/project/5678/item/0064");
+    assert_eq!(iter.key(), &keys[32][..]);
+    iter.next();
+    assert_eq!(iter.key(), &keys[33][..]);
+    iter.seek_for_prev(b"z");
+    assert_eq!(iter.key(), &keys[99][..]);
+    iter.seek_for_prev(b"a");
+    assert!(!iter.valid());
+  }
+}
@@ -0,0 +1,291 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Number of shards of a BlockCache created with `BlockCache::new`.
+const DEFAULT_SHARDS: usize = 16;
+
+/// Identifies a block by the id of its table and its offset within the table file.
+///
+/// Table ids come from `BlockCache::new_id`, so tables of different Databases sharing a BlockCache
+/// never collide.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BlockKey {
+  pub table_id: u64,
+  pub offset: u64,
+}
+
+/// Hit and miss counts of a BlockCache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BlockCacheStats {
+  pub hits: u64,
+  pub misses: u64,
+}
+
+impl BlockCacheStats {
+  /// Gets the fraction of lookups that were hits, or 0 if there were no lookups.
+  pub fn hit_ratio(&self) -> f64 {
+    let lookups = self.hits + self.misses;
+    if lookups == 0 {
+      return 0.0;
+    }
+    self.hits as f64 / lookups as f64
+  }
+}
+
+struct CacheEntry {
+  block: Arc<Vec<u8>>,
+  /// Position of the entry in the LRU order, or `None` if the entry is pinned.
+  tick: Option<u64>,
+}
+
+/// A shard of the BlockCache with its own capacity and LRU order.
+struct Shard {
+  capacity: usize,
+  usage: usize,
+  entries: HashMap<BlockKey, CacheEntry>,
+  lru: BTreeMap<u64, BlockKey>,
+  next_tick: u64,
+}
+
+impl Shard {
+  fn new(capacity: usize) -> Shard {
+    Shard {
+      capacity,
+      usage: 0,
+      entries: HashMap::new(),
+      lru: BTreeMap::new(),
+      next_tick: 0,
+    }
+  }
+
+  fn get(&mut self, key: &BlockKey) -> Option<Arc<Vec<u8>>> {
+    let next_tick = self.next_tick;
+    let entry = self.entries.get_mut(key)?;
+    if let Some(tick) = entry.tick {
+      self.lru.remove(&tick);
+      self.lru.insert(next_tick, *key);
+      entry.tick = Some(next_tick);
+      self.next_tick += 1;
+    }
+    Some(entry.block.clone())
+  }
+
+  fn insert(&mut self, key: BlockKey, block: Arc<Vec<u8>>, pinned: bool) {
+    self.erase(&key);
+    if !pinned && block.len() > self.capacity {
+      return;
+    }
+
+    let tick = if pinned {
+      None
+    } else {
+      self.lru.insert(self.next_tick, key);
+      self.next_tick += 1;
+      Some(self.next_tick - 1)
+    };
+    self.usage += block.len();
+    self.entries.insert(key, CacheEntry { block, tick });
+    self.evict();
+  }
+
+  fn unpin(&mut self, key: &BlockKey) {
+    if let Some(entry) = self.entries.get_mut(key) {
+      if entry.tick.is_none() {
+        self.lru.insert(self.next_tick, *key);
+        entry.tick = Some(self.next_tick);
+        self.next_tick += 1;
+      }
+    }
+    self.evict();
+  }
+
+  fn erase(&mut self, key: &BlockKey) {
+    if let Some(entry) = self.entries.remove(key) {
+      if let Some(tick) = entry.tick {
+        self.lru.remove(&tick);
+      }
+      self.usage -= entry.block.len();
+    }
+  }
+
+  /// Evicts the least recently used blocks until the shard is within its capacity. Pinned blocks
+  /// are never evicted.
+  fn evict(&mut self) {
+    while self.usage > self.capacity {
+      let tick = match self.lru.keys().next() {
+        Some(tick) => *tick,
+        None => break,
+      };
+      let key = self.lru.remove(&tick).unwrap();
+      let entry = self.entries.remove(&key).unwrap();
+      self.usage -= entry.block.len();
+    }
+  }
+}
+
+/// Sharded LRU cache of blocks read from on-disk tables.
+///
+/// The capacity in bytes is split evenly between the shards, and each shard is locked on its own,
+/// so concurrent readers rarely contend. A BlockCache can be shared across tables and across
+/// Databases by wrapping it in an `Arc`.
+///
+/// Pinned blocks, like the index and filter blocks of tables opened with
+/// `Options::pin_index_and_filter_blocks`, stay in the cache until they are unpinned or erased. They count towards the usage of the cache, but are never evicted.
+pub struct BlockCache {
+  capacity: usize,
+  shards: Vec<Mutex<Shard>>,
+  hits: AtomicU64,
+  misses: AtomicU64,
+  next_id: AtomicU64,
+}
+
+impl BlockCache {
+  /// Creates a new BlockCache with a capacity in bytes.
+  pub fn new(capacity: usize) -> BlockCache {
+    BlockCache::with_shards(capacity, DEFAULT_SHARDS)
+  }
+
+  /// Creates a new BlockCache with a capacity in bytes, split between a number of shards.
+  pub fn with_shards(capacity: usize, shards: usize) -> BlockCache {
+    let shards = shards.max(1);
+    BlockCache {
+      capacity,
+      shards: (0..shards)
+        .map(|_| Mutex::new(Shard::new(capacity / shards)))
+        .collect(),
+      hits: AtomicU64::new(0),
+      misses: AtomicU64::new(0),
+      next_id: AtomicU64::new(0),
+    }
+  }
+
+  /// Gets a new id for a table that reads through the cache, unique among every table that shares
+  /// the cache.
+  pub fn new_id(&self) -> u64 {
+    self.next_id.fetch_add(1, Ordering::Relaxed)
+  }
+
+  /// Gets a block from the cache, and marks it as the most recently used block of its shard.
+  pub fn get(&self, key: &BlockKey) -> Option<Arc<Vec<u8>>> {
+    let block = self.shard(key).lock().unwrap().get(key);
+    match block {
+      Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+      None => self.misses.fetch_add(1, Ordering::Relaxed),
+    };
+    block
+  }
+
+  /// Inserts a block into the cache, evicting the least recently used blocks to make room.
+  ///
+  /// A block larger than the capacity of a shard is not cached.
+  pub fn insert(&self, key: BlockKey, block: Arc<Vec<u8>>) {
+    self.shard(&key).lock().unwrap().insert(key, block, false);
+  }
+
+  /// Inserts a block into the cache that is never evicted until it is unpinned or erased.
+  pub fn insert_pinned(&self, key: BlockKey, block: Arc<Vec<u8>>) {
+    self.shard(&key).lock().unwrap().insert(key, block, true);
+  }
+
+  /// Unpins a block, so it can be evicted like any other block.
+  pub fn unpin(&self, key: &BlockKey) {
+    self.shard(key).lock().unwrap().unpin(key);
+  }
+
+  /// Removes a block from the cache.
+  pub fn erase(&self, key: &BlockKey) {
+    self.shard(key).lock().unwrap().erase(key);
+  }
+
+  /// Gets the capacity of the cache in bytes.
+  pub fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  /// Gets the number of bytes of the blocks in the cache.
+  pub fn usage(&self) -> usize {
+    self.shards.iter().map(|s| s.lock().unwrap().usage).sum()
+  }
+
+  /// Gets the hit and miss counts of the cache.
+  pub fn stats(&self) -> BlockCacheStats {
+    BlockCacheStats {
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+    }
+  }
+
+  /// Gets the shard that holds a block.
+  fn shard(&self, key: &BlockKey) -> &Mutex<Shard> {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    &self.shards[hasher.finish() as usize % self.shards.len()]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::block_cache::{BlockCache, BlockCacheStats, BlockKey};
+  use std::sync::Arc;
+
+  fn key(offset: u64) -> BlockKey {
+    BlockKey {
+      table_id: 1,
+      offset,
+    }
+  }
+
+  #[test]
+  fn test_block_cache_lru() {
+    let cache = BlockCache::with_shards(300, 1);
+    cache.insert(key(0), Arc::new(vec![0; 100]));
+    cache.insert(key(1), Arc::new(vec![1; 100]));
+    cache.insert(key(2), Arc::new(vec![2; 100]));
+    assert_eq!(cache.usage(), 300);
+
+    assert!(cache.get(&key(0)).is_some());
+    cache.insert(key(3), Arc::new(vec![3; 100]));
+    assert_eq!(cache.usage(), 300);
+
+    assert!(cache.get(&key(1)).is_none());
+    assert_eq!(cache.get(&key(0)).unwrap()[0], 0);
+    assert_eq!(cache.get(&key(3)).unwrap()[0], 3);
+
+    cache.insert(key(4), Arc::new(vec![4; 400]));
+    assert!(cache.get(&key(4)).is_none());
+
+    assert_eq!(cache.stats(), BlockCacheStats { hits: 3, misses: 2 });
+    assert_eq!(cache.stats().hit_ratio(), 0.6);
+  }
+
+  #[test]
+  fn test_block_cache_pinned() {
+    let cache = BlockCache::with_shards(200, 1);
+    cache.insert_pinned(key(0), Arc::new(vec![0; 100]));
+    cache.insert(key(1), Arc::new(vec![1; 100]));
+    cache.insert(key(2), Arc::new(vec![2; 100]));
+
+    assert!(cache.get(&key(0)).is_some());
+    assert!(cache.get(&key(1)).is_none());
+    assert!(cache.get(&key(2)).is_some());
+
+    cache.unpin(&key(0));
+    cache.insert(key(3), Arc::new(vec![3; 100]));
+    assert!(cache.get(&key(2)).is_none());
+    assert!(cache.get(&key(0)).is_some());
+
+    cache.erase(&key(3));
+    assert_eq!(cache.usage(), 100);
+  }
+
+  #[test]
+  fn test_block_cache_new_id() {
+    let cache = BlockCache::new(100);
+    let a = cache.new_id();
+    let b = cache.new_id();
+    assert_ne!(a, b);
+  }
+}
@@ -0,0 +1,630 @@
+use crate::blob::BlobIndex;
+use crate::block_cache::BlockCache;
+use crate::comparator::Comparator;
+use crate::compression::CompressionStats;
+use crate::manifest::table_path;
+use crate::mem_table::{MemTable, MemTableEntry, RangeTombstone};
+use crate::merge_operator::MergeOperator;
+use crate::merging_iterator::{MergingIterator, Source};
+use crate::options::{Options, ReadOptions};
+use crate::table::{Table, TableBuilder, COMPRESSION_STATS_META_BLOCK};
+use crate::table_entry::{
+  decode_entry, decode_range_tombstones, encode_entry, encode_range_tombstones,
+  RANGE_TOMBSTONES_META_BLOCK,
+};
+use crate::utils::now;
+use crate::wal_iterator::WALRecord;
+use std::cmp::Ordering;
+use std::fs::remove_file;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Id of the default column family, which always exists.
+pub const DEFAULT_COLUMN_FAMILY_ID: u32 = 0;
+/// Name of the default column family, which always exists.
+pub const DEFAULT_COLUMN_FAMILY_NAME: &str = "default";
+
+/// Options for tuning a column family.
+#[derive(Default)]
+pub struct ColumnFamilyOptions {
+  /// MergeOperator used to combine the merge operands written to the column family.
+  pub merge_operator: Option<Box<dyn MergeOperator>>,
+}
+
+/// Table file flushed from the MemTable of a column family, with the range tombstones it holds and
+/// the CompressionStats of its data blocks.
+///
+/// A table ingested from an external file has the timestamp it was ingested at, which replaces the
+/// timestamps written to the file.
+#[derive(Clone)]
+pub(crate) struct TableFile {
+  pub number: u64,
+  pub table: Arc<Table>,
+  pub range_tombstones: Vec<RangeTombstone>,
+  pub compression: CompressionStats,
+  pub timestamp: Option<u128>,
+}
+
+impl TableFile {
+  /// Opens a table file within a directory.
+  pub fn open(
+    dir: &Path,
+    number: u64,
+    options: &Options,
+    block_cache: Arc<BlockCache>,
+  ) -> io::Result<TableFile> {
+    let table = Table::open(&table_path(dir, number), options, block_cache)?;
+    let read_options = ReadOptions::new().verify_checksums(true).fill_cache(false);
+    let range_tombstones = match table.meta_block(RANGE_TOMBSTONES_META_BLOCK, &read_options)? {
+      Some(buf) => decode_range_tombstones(&buf).ok_or_else(corrupted)?,
+      None => Vec::new(),
+    };
+    // Tables written before the stats were recorded have none.
+    let compression = match table.meta_block(COMPRESSION_STATS_META_BLOCK, &read_options)? {
+      Some(buf) => CompressionStats::decode(&buf).ok_or_else(corrupted)?,
+      None => CompressionStats::default(),
+    };
+
+    Ok(TableFile {
+      number,
+      table: Arc::new(table),
+      range_tombstones,
+      compression,
+      timestamp: None,
+    })
+  }
+
+  /// Gets the TableFile that a compaction reads, reopened with direct I/O if the Options use direct
+  /// I/O for flush and compaction and the table is not already read with it.
+  pub fn compaction_input(
+    &self,
+    dir: &Path,
+    options: &Options,
+    block_cache: Arc<BlockCache>,
+  ) -> io::Result<TableFile> {
+    let table = if options.use_direct_io_for_flush_and_compaction && !self.table.is_direct() {
+      let options = options
+        .clone()
+        .use_mmap_reads(false)
+        .use_direct_reads(true)
+        .pin_index_and_filter_blocks(false);
+      Arc::new(Table::open(
+        &table_path(dir, self.number),
+        &options,
+        block_cache,
+      )?)
+    } else {
+      self.table.clone()
+    };
+    Ok(TableFile {
+      number: self.number,
+      table,
+      range_tombstones: self.range_tombstones.clone(),
+      compression: self.compression,
+      timestamp: self.timestamp,
+    })
+  }
+
+  /// Decodes an entry read from the table.
+  pub fn decode_entry(&self, key: &[u8], value: &[u8]) -> io::Result<MemTableEntry> {
+    let mut entry = decode_entry(key, value).ok_or_else(corrupted)?;
+    if let Some(timestamp) = self.timestamp {
+      entry.timestamp = timestamp;
+    }
+    Ok(entry)
+  }
+}
+
+/// Gets the error of a table entry that cannot be decoded.
+fn corrupted() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, "table entry is corrupted")
+}
+
+/// Gets the BlobIndex of an entry whose value was separated into a blob file.
+fn blob_index(entry: &MemTableEntry) -> Option<BlobIndex> {
+  if !entry.blob_index {
+    return None;
+  }
+  BlobIndex::decode(entry.value.as_deref()?)
+}
+
+/// Collapses the entries of a key, newest first, into one entry that reads the same: the oldest
+/// entry, with the merge operands of the newer entries stacked on it and the newest timestamp.
+fn collapse_entries(entries: Vec<MemTableEntry>) -> MemTableEntry {
+  let timestamp = entries[0].timestamp;
+  let mut entries = entries.into_iter().rev();
+  let mut entry = entries.next().unwrap();
+  for newer in entries {
+    entry.operands.extend(newer.operands);
+  }
+  entry.timestamp = timestamp;
+  entry
+}
+
+/// Column family is a named keyspace in the Database.
+///
+/// Each column family has its own MemTable, tables and options, while the operations on every
+/// column family are written to the same WAL. Records in the WAL carry the id of their column
+/// family. Every column family orders its keys with the Database's Comparator.
+///
+/// The tables of a column family are ordered newest first. Like the MemTable, a table holds the
+/// newest entry of each key it has, and its range tombstones only delete entries of older tables.
+pub(crate) struct ColumnFamily {
+  pub id: u32,
+  pub name: String,
+  pub mem_table: Arc<MemTable>,
+  pub tables: Vec<TableFile>,
+  pub options: ColumnFamilyOptions,
+  comparator: Arc<dyn Comparator>,
+}
+
+impl ColumnFamily {
+  /// Creates a new empty column family.
+  pub fn new(
+    id: u32,
+    name: &str,
+    options: ColumnFamilyOptions,
+    comparator: Arc<dyn Comparator>,
+  ) -> ColumnFamily {
+    ColumnFamily {
+      id,
+      name: name.to_owned(),
+      mem_table: Arc::new(MemTable::with_comparator(comparator.clone())),
+      tables: Vec::new(),
+      options,
+      comparator,
+    }
+  }
+
+  /// Gets a copy of the column family for a Snapshot, without its options.
+  ///
+  /// The copy shares the MemTable and the tables of the column family. The next write to the
+  /// MemTable copies it, so the writes made after the copy are not seen through it.
+  pub fn snapshot(&self) -> ColumnFamily {
+    ColumnFamily {
+      id: self.id,
+      name: self.name.clone(),
+      mem_table: self.mem_table.clone(),
+      tables: self.tables.clone(),
+      options: ColumnFamilyOptions::default(),
+      comparator: self.comparator.clone(),
+    }
+  }
+
+  /// Gets the entries of a key from the MemTable and then the tables of the column family, newest
+  /// first.
+  ///
+  /// The lookup stops at the first entry that holds a value or a tombstone, as it hides every older
+  /// entry. A key deleted by a range tombstone gets a tombstone entry. Entries that only hold merge
+  /// operands are returned together with the older entries they apply to.
+  pub fn get_entries(
+    &self,
+    key: &[u8],
+    read_options: &ReadOptions,
+  ) -> io::Result<Vec<MemTableEntry>> {
+    let mut entries = Vec::new();
+
+    if let Some(entry) = self.mem_table.get(key) {
+      entries.push(entry.clone());
+      if !entry.only_operands() {
+        return Ok(entries);
+      }
+    }
+    if let Some(tombstone) = self
+      .mem_table
+      .range_tombstones()
+      .iter()
+      .find(|t| t.covers(key, self.comparator.as_ref()))
+    {
+      entries.push(MemTableEntry::tombstone(key, tombstone.timestamp));
+      return Ok(entries);
+    }
+
+    for table_file in self.tables.iter() {
+      if let Some(value) = table_file.table.get(key, read_options)? {
+        let entry = table_file.decode_entry(key, &value)?;
+        let only_operands = entry.only_operands();
+        entries.push(entry);
+        if !only_operands {
+          return Ok(entries);
+        }
+      }
+      if let Some(tombstone) = table_file
+        .range_tombstones
+        .iter()
+        .find(|t| t.covers(key, self.comparator.as_ref()))
+      {
+        entries.push(MemTableEntry::tombstone(key, tombstone.timestamp));
+        return Ok(entries);
+      }
+    }
+
+    Ok(entries)
+  }
+
+  /// Gets the entries of keys, like `get_entries`, in one pass over the MemTable and each table.
+  ///
+  /// The keys are looked up in sorted order, and the keys still unresolved after the MemTable and
+  /// each newer table are read from the next table together with `Table::multi_get`.
+  pub fn multi_get_entries(
+    &self,
+    keys: &[&[u8]],
+    read_options: &ReadOptions,
+  ) -> Vec<io::Result<Vec<MemTableEntry>>> {
+    let mut order: Vec<usize> = (0..keys.len()).collect();
+    order.sort_by(|a, b| self.comparator.compare(keys[*a], keys[*b]));
+
+    let mut results: Vec<io::Result<Vec<MemTableEntry>>> =
+      keys.iter().map(|_| Ok(Vec::new())).collect();
+    let mut pending = Vec::with_capacity(keys.len());
+    for i in order.into_iter() {
+      let key = keys[i];
+      let entries = results[i].as_mut().unwrap();
+      if let Some(entry) = self.mem_table.get(key) {
+        entries.push(entry.clone());
+        if !entry.only_operands() {
+          continue;
+        }
+      }
+      if let Some(tombstone) = self
+        .mem_table
+        .range_tombstones()
+        .iter()
+        .find(|t| t.covers(key, self.comparator.as_ref()))
+      {
+        entries.push(MemTableEntry::tombstone(key, tombstone.timestamp));
+        continue;
+      }
+      pending.push(i);
+    }
+
+    for table_file in self.tables.iter() {
+      if pending.is_empty() {
+        break;
+      }
+      let pending_keys: Vec<&[u8]> = pending.iter().map(|i| keys[*i]).collect();
+      let values = match table_file.table.multi_get(&pending_keys, read_options) {
+        Ok(values) => values,
+        Err(e) => {
+          for i in pending.drain(..) {
+            results[i] = Err(io::Error::new(e.kind(), e.to_string()));
+          }
+          break;
+        }
+      };
+
+      let mut still_pending = Vec::with_capacity(pending.len());
+      for (i, value) in pending.into_iter().zip(values) {
+        let key = keys[i];
+        if let Some(value) = value {
+          let entry = match table_file.decode_entry(key, &value) {
+            Ok(entry) => entry,
+            Err(e) => {
+              results[i] = Err(e);
+              continue;
+            }
+          };
+          let only_operands = entry.only_operands();
+          results[i].as_mut().unwrap().push(entry);
+          if !only_operands {
+            continue;
+          }
+        }
+        if let Some(tombstone) = table_file
+          .range_tombstones
+          .iter()
+          .find(|t| t.covers(key, self.comparator.as_ref()))
+        {
+          let entry = MemTableEntry::tombstone(key, tombstone.timestamp);
+          results[i].as_mut().unwrap().push(entry);
+          continue;
+        }
+        still_pending.push(i);
+      }
+      pending = still_pending;
+    }
+
+    results
+  }
+
+  /// Combines the merge operands of an entry with the MergeOperator of the column family, if it
+  /// has one.
+  ///
+  /// The operands are merged into a value with `full_merge` if the entry holds a value, a tombstone
+  /// or an expired value, or if it only holds operands and `oldest` is set, as no older entry of
+  /// the key is read under it. Otherwise they are combined with `partial_merge`. The operands on a
+  /// value that expires or is in a blob file are kept apart from it, as the value they apply to
+  /// changes when it expires and is not at hand in a blob file.
+  fn merge_operands(&self, entry: &mut MemTableEntry, oldest: bool, now: u128) {
+    let merge_operator = match self.options.merge_operator.as_ref() {
+      Some(merge_operator) => merge_operator,
+      None => return,
+    };
+    if entry.operands.is_empty() {
+      return;
+    }
+
+    let existing = if entry.deleted || entry.is_expired(now) || (oldest && entry.only_operands()) {
+      None
+    } else if entry.value.is_some() && entry.expires_at.is_none() && !entry.blob_index {
+      entry.value.take()
+    } else {
+      if entry.operands.len() > 1 {
+        if let Some(operand) = merge_operator.partial_merge(&entry.key, &entry.operands) {
+          entry.operands = vec![operand];
+        }
+      }
+      return;
+    };
+    let value = merge_operator.full_merge(&entry.key, existing.as_deref(), &entry.operands);
+    entry.value = Some(value);
+    entry.expires_at = None;
+    entry.deleted = false;
+    entry.operands.clear();
+    entry.blob_index = false;
+  }
+
+  /// Writes the MemTable of the column family to a new table file within a directory.
+  ///
+  /// The merge operands of each entry are combined, and merged into a value if nothing older than
+  /// the entry is read under it. Expired values without merge operands are dropped, or written as
+  /// tombstones if an older table may have the key. A flush drops no blob values: the values the
+  /// MemTable replaced were marked as garbage when they were replaced, values in blob files do not
+  /// expire, and operands are not merged into a value in a blob file. Returns `None` without
+  /// writing a table file if nothing is left.
+  pub fn write_table(
+    &self,
+    dir: &Path,
+    number: u64,
+    options: &Options,
+    block_cache: Arc<BlockCache>,
+  ) -> io::Result<Option<TableFile>> {
+    let range_tombstones = self.mem_table.range_tombstones();
+    if self.mem_table.len() == 0 && range_tombstones.is_empty() {
+      return Ok(None);
+    }
+
+    let now = now();
+    let path = table_path(dir, number);
+    let mut builder = TableBuilder::new(&path, options)?;
+    for entry in self.mem_table.entries().iter() {
+      // The entries under a range tombstone of the MemTable are not read, like those of a key in
+      // no table.
+      let oldest = self.tables.is_empty()
+        || range_tombstones
+          .iter()
+          .any(|t| t.covers(&entry.key, self.comparator.as_ref()));
+      if entry.operands.is_empty() {
+        if !entry.is_expired(now) {
+          builder.add(&entry.key, &encode_entry(entry))?;
+        } else if !oldest {
+          let tombstone = MemTableEntry::tombstone(&entry.key, entry.timestamp);
+          builder.add(&entry.key, &encode_entry(&tombstone))?;
+        }
+        continue;
+      }
+      let mut entry = entry.clone();
+      self.merge_operands(&mut entry, oldest, now);
+      builder.add(&entry.key, &encode_entry(&entry))?;
+    }
+    if builder.num_entries() == 0 && range_tombstones.is_empty() {
+      drop(builder);
+      remove_file(path)?;
+      return Ok(None);
+    }
+    if !range_tombstones.is_empty() {
+      builder.add_meta_block(
+        RANGE_TOMBSTONES_META_BLOCK,
+        encode_range_tombstones(range_tombstones),
+      );
+    }
+    builder.finish()?;
+
+    TableFile::open(dir, number, options, block_cache).map(Some)
+  }
+
+  /// Gets if the MemTable of the column family has a key or a range tombstone from `smallest` to
+  /// `largest`, both inclusive.
+  pub fn mem_table_overlaps(&self, smallest: &[u8], largest: &[u8]) -> bool {
+    let comparator = self.comparator.as_ref();
+    !self.mem_table.range(smallest, largest).is_empty()
+      || self.mem_table.get(largest).is_some()
+      || self.mem_table.range_tombstones().iter().any(|t| {
+        comparator.compare(&t.start, largest) != Ordering::Greater
+          && comparator.compare(&t.end, smallest) == Ordering::Greater
+      })
+  }
+
+  /// Gets the number of newest tables that a compaction of the keys from `start` (inclusive) to
+  /// `end` (exclusive) merges: every table down to the oldest table with a key in the range.
+  pub fn tables_to_compact(&self, start: &[u8], end: &[u8]) -> io::Result<usize> {
+    let mut count = 0;
+    for (i, table_file) in self.tables.iter().enumerate() {
+      let mut iter = table_file.table.iter(ReadOptions::new().fill_cache(false));
+      iter.seek(start);
+      if let Some(e) = iter.status() {
+        return Err(io::Error::new(e.kind(), e.to_string()));
+      }
+      if iter.valid() && self.comparator.compare(iter.key(), end) == Ordering::Less {
+        count = i + 1;
+      }
+    }
+    Ok(count)
+  }
+
+  /// Merges the newest `count` tables into a new table file within a directory, which replaces
+  /// them as the newest table.
+  ///
+  /// The entries of each key are collapsed into one entry, and their merge operands are combined,
+  /// and merged into the oldest value or tombstone, or into no value if every table is merged and
+  /// none has a value of the key. No older table has a key from `start` (inclusive) to `end`
+  /// (exclusive), so tombstones and expired values without merge operands are dropped in the
+  /// range, as are the range tombstones within it. Outside of the range, they are dropped too if
+  /// every table is merged, and otherwise expired values are replaced by tombstones.
+  ///
+  /// The tables are read with direct I/O if the Options use direct I/O for flush and compaction.
+  /// Returns `None` without writing a table file if nothing is left, together with the
+  /// BlobIndexes of the entries that the new table no longer holds.
+  #[allow(clippy::too_many_arguments)]
+  pub fn compact_tables(
+    &self,
+    count: usize,
+    start: &[u8],
+    end: &[u8],
+    dir: &Path,
+    number: u64,
+    options: &Options,
+    block_cache: Arc<BlockCache>,
+  ) -> io::Result<(Option<TableFile>, Vec<BlobIndex>)> {
+    let comparator = self.comparator.as_ref();
+    let in_range = |key: &[u8]| {
+      comparator.compare(start, key) != Ordering::Greater
+        && comparator.compare(key, end) == Ordering::Less
+    };
+
+    let tables = self.tables[..count]
+      .iter()
+      .map(|table_file| table_file.compaction_input(dir, options, block_cache.clone()))
+      .collect::<io::Result<Vec<TableFile>>>()?;
+    let sources = tables
+      .iter()
+      .map(|table_file| Source::Table {
+        table_file: table_file.clone(),
+        iter: Box::new(table_file.table.iter(ReadOptions::new().fill_cache(false))),
+      })
+      .collect();
+    let mut merging = MergingIterator::new(sources, comparator);
+    let now = now();
+    // No table is older than the merged tables if every table is merged.
+    let oldest = count == self.tables.len();
+
+    let path = table_path(dir, number);
+    let mut builder =
+      TableBuilder::with_compressor(&path, options, options.compaction_compressor())?;
+    let mut dropped_blob_indexes = Vec::new();
+    merging.seek_to_first();
+    while let Some(key) = merging.pop() {
+      if let Some(e) = merging.status() {
+        return Err(io::Error::new(e.kind(), e.to_string()));
+      }
+      let mut entry = collapse_entries(merging.entries(&key)?);
+      self.merge_operands(&mut entry, oldest, now);
+      let mut kept = None;
+      if entry.operands.is_empty() && entry.is_expired(now) {
+        entry = MemTableEntry::tombstone(&entry.key, entry.timestamp);
+      }
+      if !(entry.operands.is_empty() && entry.deleted && (oldest || in_range(&key))) {
+        builder.add(&entry.key, &encode_entry(&entry))?;
+        kept = blob_index(&entry);
+      }
+      // The entries hidden by the collapsed entry are dropped, and their blob values with them.
+      for blob_index in merging.all_entries()?.iter().filter_map(blob_index) {
+        if Some(blob_index) != kept {
+          dropped_blob_indexes.push(blob_index);
+        }
+      }
+    }
+    if let Some(e) = merging.status() {
+      return Err(io::Error::new(e.kind(), e.to_string()));
+    }
+
+    let range_tombstones: Vec<RangeTombstone> = tables
+      .iter()
+      .flat_map(|t| t.range_tombstones.iter())
+      .filter(|t| !in_range(&t.start) || comparator.compare(&t.end, end) == Ordering::Greater)
+      .cloned()
+      .collect();
+    if builder.num_entries() == 0 && range_tombstones.is_empty() {
+      drop(builder);
+      remove_file(path)?;
+      return Ok((None, dropped_blob_indexes));
+    }
+    if !range_tombstones.is_empty() {
+      builder.add_meta_block(
+        RANGE_TOMBSTONES_META_BLOCK,
+        encode_range_tombstones(&range_tombstones),
+      );
+    }
+    builder.finish()?;
+
+    let table_file = TableFile::open(dir, number, options, block_cache)?;
+    Ok((Some(table_file), dropped_blob_indexes))
+  }
+
+  /// Gets the BlobIndexes of the entries that a Key-Value operation would replace or delete.
+  pub fn replaced_blob_indexes(&self, record: &WALRecord) -> Vec<BlobIndex> {
+    let entries = match record {
+      WALRecord::Entry(entry) | WALRecord::BlobIndex(entry) => {
+        self.mem_table.get(&entry.key).into_iter().collect()
+      }
+      WALRecord::RangeDelete { start, end, .. } => {
+        self.mem_table.range(start, end).iter().collect()
+      }
+      _ => Vec::new(),
+    };
+    entries
+      .into_iter()
+      .filter(|e| e.blob_index)
+      .filter_map(|e| BlobIndex::decode(e.value.as_deref()?))
+      .collect()
+  }
+
+  /// Gets the BlobIndexes of every entry in the MemTable and the tables of the column family.
+  pub fn blob_indexes(&self) -> io::Result<Vec<BlobIndex>> {
+    let mut blob_indexes: Vec<BlobIndex> = self
+      .mem_table
+      .entries()
+      .iter()
+      .filter(|e| e.blob_index)
+      .filter_map(|e| BlobIndex::decode(e.value.as_deref()?))
+      .collect();
+
+    for table_file in self.tables.iter() {
+      let mut iter = table_file.table.iter(ReadOptions::new().fill_cache(false));
+      iter.seek_to_first();
+      while iter.valid() {
+        let entry = table_file.decode_entry(iter.key(), iter.value())?;
+        if entry.blob_index {
+          blob_indexes.extend(entry.value.as_deref().and_then(BlobIndex::decode));
+        }
+        iter.next();
+      }
+      if let Some(e) = iter.status() {
+        return Err(io::Error::new(e.kind(), e.to_string()));
+      }
+    }
+
+    Ok(blob_indexes)
+  }
+
+  /// Applies a Key-Value operation from the WAL to the MemTable of the column family.
+  ///
+  /// The MemTable is copied first if a Snapshot shares it.
+  pub fn apply(&mut self, record: &WALRecord) {
+    let mem_table = Arc::make_mut(&mut self.mem_table);
+    match record {
+      WALRecord::Entry(entry) => match entry.value.as_ref() {
+        Some(value) if !entry.deleted => {
+          mem_table.set_with_expiry(&entry.key, value, entry.timestamp, entry.expires_at)
+        }
+        _ => mem_table.delete(&entry.key, entry.timestamp),
+      },
+      WALRecord::Merge(entry) => {
+        let operand = entry.value.as_deref().unwrap_or_default();
+        mem_table.merge(&entry.key, operand, entry.timestamp);
+      }
+      WALRecord::BlobIndex(entry) => {
+        let blob_index = entry.value.as_deref().unwrap_or_default();
+        mem_table.set_blob_index(&entry.key, blob_index, entry.timestamp);
+      }
+      WALRecord::RangeDelete {
+        start,
+        end,
+        timestamp,
+        ..
+      } => mem_table.delete_range(start, end, *timestamp),
+      _ => {}
+    }
+  }
+}
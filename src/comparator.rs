@@ -0,0 +1,142 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+/// Comparator defines the order of keys in the Database.
+///
+/// Keys that compare as equal are the same key. The name of the Comparator is persisted in the
+/// Database's directory, and a Database cannot be opened with a Comparator of a different name.
+pub trait Comparator: Send + Sync {
+  /// Gets the name of the Comparator.
+  fn name(&self) -> &str;
+
+  /// Compares two keys.
+  fn compare(&self, a: &[u8], b: &[u8]) -> Ordering;
+
+  /// Gets the bytes of a key that are added to bloom filters.
+  ///
+  /// Keys that compare as equal must have the same filter key. By default, the filter key is the key
+  /// itself.
+  fn filter_key<'a>(&self, key: &'a [u8]) -> Cow<'a, [u8]> {
+    Cow::Borrowed(key)
+  }
+}
+
+/// Orders keys by lexicographic byte ordering. This is the default Comparator.
+pub struct BytewiseComparator;
+
+impl Comparator for BytewiseComparator {
+  fn name(&self) -> &str {
+    "BytewiseComparator"
+  }
+
+  fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+    a.cmp(b)
+  }
+}
+
+/// Orders keys by reverse lexicographic byte ordering.
+pub struct ReverseBytewiseComparator;
+
+impl Comparator for ReverseBytewiseComparator {
+  fn name(&self) -> &str {
+    "ReverseBytewiseComparator"
+  }
+
+  fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+    b.cmp(a)
+  }
+}
+
+/// Orders keys as big-endian unsigned integers of any length.
+///
+/// Leading zero bytes are ignored, so `[0, 1]` and `[1]` are the same key.
+pub struct BigEndianIntegerComparator;
+
+impl BigEndianIntegerComparator {
+  fn strip_leading_zeros(key: &[u8]) -> &[u8] {
+    let start = key.iter().position(|b| *b != 0).unwrap_or(key.len());
+    &key[start..]
+  }
+}
+
+impl Comparator for BigEndianIntegerComparator {
+  fn name(&self) -> &str {
+    "BigEndianIntegerComparator"
+  }
+
+  fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+    let a = BigEndianIntegerComparator::strip_leading_zeros(a);
+    let b = BigEndianIntegerComparator::strip_leading_zeros(b);
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+  }
+
+  fn filter_key<'a>(&self, key: &'a [u8]) -> Cow<'a, [u8]> {
+    Cow::Borrowed(BigEndianIntegerComparator::strip_leading_zeros(key))
+  }
+}
+
+/// Orders keys by lexicographic byte ordering, ignoring ASCII case.
+///
+/// Keys that only differ by case, like `Apple` and `apple`, are the same key.
+pub struct CaseInsensitiveComparator;
+
+impl Comparator for CaseInsensitiveComparator {
+  fn name(&self) -> &str {
+    "CaseInsensitiveComparator"
+  }
+
+  fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+    let a = a.iter().map(|c| c.to_ascii_lowercase());
+    let b = b.iter().map(|c| c.to_ascii_lowercase());
+    a.cmp(b)
+  }
+
+  fn filter_key<'a>(&self, key: &'a [u8]) -> Cow<'a, [u8]> {
+    Cow::Owned(key.to_ascii_lowercase())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::comparator::{
+    BigEndianIntegerComparator, BytewiseComparator, CaseInsensitiveComparator, Comparator,
+    ReverseBytewiseComparator,
+  };
+  use std::cmp::Ordering;
+
+  #[test]
+  fn test_bytewise() {
+    let cmp = BytewiseComparator;
+    assert_eq!(cmp.compare(b"Apple", b"Lime"), Ordering::Less);
+    assert_eq!(cmp.compare(b"Lime", b"Lime"), Ordering::Equal);
+    assert_eq!(cmp.compare(b"Lime", b"Apple"), Ordering::Greater);
+  }
+
+  #[test]
+  fn test_reverse_bytewise() {
+    let cmp = ReverseBytewiseComparator;
+    assert_eq!(cmp.compare(b"Apple", b"Lime"), Ordering::Greater);
+    assert_eq!(cmp.compare(b"Lime", b"Lime"), Ordering::Equal);
+    assert_eq!(cmp.compare(b"Lime", b"Apple"), Ordering::Less);
+  }
+
+  #[test]
+  fn test_big_endian_integer() {
+    let cmp = BigEndianIntegerComparator;
+    assert_eq!(cmp.compare(&[2], &[1, 0]), Ordering::Less);
+    assert_eq!(cmp.compare(&[0, 0, 1], &[1]), Ordering::Equal);
+    assert_eq!(cmp.compare(&[1, 2], &[1, 1]), Ordering::Greater);
+    assert_eq!(
+      cmp.compare(&1000u64.to_be_bytes(), &[0xff]),
+      Ordering::Greater
+    );
+  }
+
+  #[test]
+  fn test_case_insensitive() {
+    let cmp = CaseInsensitiveComparator;
+    assert_eq!(cmp.compare(b"apple", b"Lime"), Ordering::Less);
+    assert_eq!(cmp.compare(b"LIME", b"lime"), Ordering::Equal);
+    assert_eq!(cmp.compare(b"lime", b"Apple"), Ordering::Greater);
+  }
+}
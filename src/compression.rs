@@ -0,0 +1,610 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+
+/// Id of the NoCompressor codec.
+pub const NO_COMPRESSION_ID: u8 = 0;
+/// Id of the LzCompressor codec.
+pub const LZ_COMPRESSION_ID: u8 = 1;
+/// Flag set on the codec id of a block that was compressed with the dictionary of its table.
+const DICTIONARY_FLAG: u8 = 0x80;
+
+/// Compressor is a codec for the blocks of on-disk tables.
+///
+/// Every compressed block starts with the id of its codec, so tables written with different codecs
+/// stay readable. The id of a Compressor must be below 128, must never change, and must not be
+/// shared with another Compressor.
+pub trait Compressor: Send + Sync {
+  /// Gets the id of the codec that is stored in every block it compresses.
+  fn id(&self) -> u8;
+
+  /// Gets the name of the Compressor.
+  fn name(&self) -> &str;
+
+  /// Compresses a block.
+  fn compress(&self, data: &[u8]) -> Vec<u8>;
+
+  /// Decompresses a block that was compressed by this codec.
+  fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>>;
+
+  /// Compresses a block with a dictionary of content that is common to the blocks of a table.
+  ///
+  /// Codecs that cannot make use of a dictionary ignore it.
+  fn compress_with_dictionary(&self, data: &[u8], _dictionary: &[u8]) -> Vec<u8> {
+    self.compress(data)
+  }
+
+  /// Decompresses a block that was compressed with a dictionary.
+  fn decompress_with_dictionary(&self, data: &[u8], _dictionary: &[u8]) -> io::Result<Vec<u8>> {
+    self.decompress(data)
+  }
+}
+
+/// Stores blocks as is.
+pub struct NoCompressor;
+
+impl Compressor for NoCompressor {
+  fn id(&self) -> u8 {
+    NO_COMPRESSION_ID
+  }
+
+  fn name(&self) -> &str {
+    "NoCompressor"
+  }
+
+  fn compress(&self, data: &[u8]) -> Vec<u8> {
+    data.to_vec()
+  }
+
+  fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+    Ok(data.to_vec())
+  }
+}
+
+/// Minimum length of a match that is worth encoding.
+const MIN_MATCH: usize = 4;
+/// Maximum distance to a match, which must fit in the 2 byte offset of a sequence.
+const MAX_OFFSET: usize = u16::MAX as usize;
+/// Number of bits of the hash of 4 bytes, used to find matches.
+const HASH_BITS: u32 = 12;
+
+/// LZ77 codec in the style of LZ4, written in plain Rust.
+///
+/// A compressed block is the length of the uncompressed block as a little-endian u32, followed by
+/// sequences. Each sequence is a token byte holding the number of literals and the length of the
+/// match in its upper and lower 4 bits, any extra length bytes of the literals, the literals, a
+/// little-endian u16 offset back to the match and any extra length bytes of the match. The last
+/// sequence only has literals.
+pub struct LzCompressor;
+
+impl LzCompressor {
+  /// Hashes the 4 bytes at the start of a slice.
+  fn hash(data: &[u8]) -> usize {
+    let sequence = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    (sequence.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+  }
+
+  /// Writes a length that did not fit in the 4 bits of a token as a run of bytes.
+  fn write_length(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+      out.push(255);
+      len -= 255;
+    }
+    out.push(len as u8);
+  }
+
+  /// Writes a sequence of literals, followed by a match if it is not the last sequence.
+  fn write_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push(((literals.len().min(15) as u8) << 4) | match_len.min(15) as u8);
+    if literals.len() >= 15 {
+      LzCompressor::write_length(out, literals.len() - 15);
+    }
+    out.extend_from_slice(literals);
+
+    if let Some((offset, _)) = matched {
+      out.extend_from_slice(&(offset as u16).to_le_bytes());
+      if match_len >= 15 {
+        LzCompressor::write_length(out, match_len - 15);
+      }
+    }
+  }
+
+  /// Reads a length that did not fit in the 4 bits of a token.
+  fn read_length(data: &[u8], pos: &mut usize) -> io::Result<usize> {
+    let mut len = 0;
+    loop {
+      let byte = *data.get(*pos).ok_or_else(corrupted)?;
+      *pos += 1;
+      len += byte as usize;
+      if byte != 255 {
+        return Ok(len);
+      }
+    }
+  }
+
+  /// Compresses a block as if it followed the history, so matches may reach back into the history.
+  fn compress_with_history(history: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() / 2 + 8);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+
+    let history = &history[history.len().saturating_sub(MAX_OFFSET)..];
+    let mut buf = Vec::with_capacity(history.len() + data.len());
+    buf.extend_from_slice(history);
+    buf.extend_from_slice(data);
+
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    for i in 0..(history.len() + 1).saturating_sub(MIN_MATCH) {
+      table[LzCompressor::hash(&buf[i..])] = i;
+    }
+
+    let mut anchor = history.len();
+    let mut i = history.len();
+    while i + MIN_MATCH <= buf.len() {
+      let hash = LzCompressor::hash(&buf[i..]);
+      let candidate = table[hash];
+      table[hash] = i;
+
+      if candidate != usize::MAX
+        && i - candidate <= MAX_OFFSET
+        && buf[candidate..candidate + MIN_MATCH] == buf[i..i + MIN_MATCH]
+      {
+        let mut len = MIN_MATCH;
+        while i + len < buf.len() && buf[candidate + len] == buf[i + len] {
+          len += 1;
+        }
+        LzCompressor::write_sequence(&mut out, &buf[anchor..i], Some((i - candidate, len)));
+        i += len;
+        anchor = i;
+      } else {
+        i += 1;
+      }
+    }
+    LzCompressor::write_sequence(&mut out, &buf[anchor..], None);
+
+    out
+  }
+
+  /// Decompresses a block that was compressed with a history.
+  fn decompress_with_history(history: &[u8], data: &[u8]) -> io::Result<Vec<u8>> {
+    if data.len() < 4 {
+      return Err(corrupted());
+    }
+    let len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    // Every byte of the compressed block expands to at most 255 bytes, so a corrupted length
+    // cannot ask for a huge allocation.
+    if len > (data.len() - 4).saturating_mul(255) {
+      return Err(corrupted());
+    }
+    let history = &history[history.len().saturating_sub(MAX_OFFSET)..];
+    let mut out = Vec::with_capacity(history.len() + len);
+    out.extend_from_slice(history);
+
+    let mut pos = 4;
+    while pos < data.len() {
+      let token = data[pos];
+      pos += 1;
+
+      let mut literals = (token >> 4) as usize;
+      if literals == 15 {
+        literals += LzCompressor::read_length(data, &mut pos)?;
+      }
+      let end = pos.checked_add(literals).ok_or_else(corrupted)?;
+      out.extend_from_slice(data.get(pos..end).ok_or_else(corrupted)?);
+      pos = end;
+      if pos == data.len() {
+        break;
+      }
+
+      let offset = u16::from_le_bytes([data[pos], *data.get(pos + 1).ok_or_else(corrupted)?]);
+      pos += 2;
+      let mut match_len = (token & 0xf) as usize;
+      if match_len == 15 {
+        match_len += LzCompressor::read_length(data, &mut pos)?;
+      }
+      match_len += MIN_MATCH;
+
+      let offset = offset as usize;
+      if offset == 0 || offset > out.len() || out.len() + match_len > history.len() + len {
+        return Err(corrupted());
+      }
+      let start = out.len() - offset;
+      for i in 0..match_len {
+        out.push(out[start + i]);
+      }
+    }
+
+    if out.len() != history.len() + len {
+      return Err(corrupted());
+    }
+    Ok(out.split_off(history.len()))
+  }
+}
+
+impl Compressor for LzCompressor {
+  fn id(&self) -> u8 {
+    LZ_COMPRESSION_ID
+  }
+
+  fn name(&self) -> &str {
+    "LzCompressor"
+  }
+
+  fn compress(&self, data: &[u8]) -> Vec<u8> {
+    LzCompressor::compress_with_history(&[], data)
+  }
+
+  fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+    LzCompressor::decompress_with_history(&[], data)
+  }
+
+  fn compress_with_dictionary(&self, data: &[u8], dictionary: &[u8]) -> Vec<u8> {
+    LzCompressor::compress_with_history(dictionary, data)
+  }
+
+  fn decompress_with_dictionary(&self, data: &[u8], dictionary: &[u8]) -> io::Result<Vec<u8>> {
+    LzCompressor::decompress_with_history(dictionary, data)
+  }
+}
+
+/// Gets the error of a block that cannot be decompressed.
+fn corrupted() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, "compressed block is corrupted")
+}
+
+/// Gets the built-in Compressors.
+pub fn builtin_compressors() -> Vec<Arc<dyn Compressor>> {
+  vec![Arc::new(NoCompressor), Arc::new(LzCompressor)]
+}
+
+/// Compresses a block with a Compressor, prefixed by the id of the codec.
+///
+/// If the Compressor does not make the block smaller, the block is stored as is.
+pub fn compress_block(
+  compressor: &dyn Compressor,
+  dictionary: Option<&[u8]>,
+  block: &[u8],
+) -> Vec<u8> {
+  let compressed = match dictionary {
+    Some(dictionary) => compressor.compress_with_dictionary(block, dictionary),
+    None => compressor.compress(block),
+  };
+  let (id, payload) = if compressed.len() < block.len() {
+    match dictionary {
+      Some(_) => (compressor.id() | DICTIONARY_FLAG, compressed),
+      None => (compressor.id(), compressed),
+    }
+  } else {
+    (NO_COMPRESSION_ID, block.to_vec())
+  };
+
+  let mut out = Vec::with_capacity(payload.len() + 1);
+  out.push(id);
+  out.extend_from_slice(&payload);
+  out
+}
+
+/// Decompresses a block with the Compressor whose id prefixes the block.
+///
+/// Blocks that were compressed with a dictionary need the dictionary of their table.
+pub fn decompress_block(
+  block: &[u8],
+  compressors: &[Arc<dyn Compressor>],
+  dictionary: Option<&[u8]>,
+) -> io::Result<Vec<u8>> {
+  let (id, payload) = block.split_first().ok_or_else(corrupted)?;
+  let compressor = match compressors.iter().find(|c| c.id() == id & !DICTIONARY_FLAG) {
+    Some(compressor) => compressor,
+    None => {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("block was compressed by unknown codec {}", id),
+      ))
+    }
+  };
+
+  if id & DICTIONARY_FLAG == 0 {
+    return compressor.decompress(payload);
+  }
+  match dictionary {
+    Some(dictionary) => compressor.decompress_with_dictionary(payload, dictionary),
+    None => Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      "block was compressed with a dictionary, but the table has none",
+    )),
+  }
+}
+
+/// Length of the segments a dictionary is built from.
+const SEGMENT_LEN: usize = 8;
+
+/// Trains a dictionary of up to `max_size` bytes from sample values.
+///
+/// The dictionary is made of the segments of bytes that occur in the most samples. Segments that
+/// occur in a single sample are left out, because they are unlikely to show up in other values.
+/// The most common segments are placed at the end of the dictionary, closest to the compressed data.
+pub fn train_dictionary(samples: &[&[u8]], max_size: usize) -> Vec<u8> {
+  let mut counts: HashMap<&[u8], usize> = HashMap::new();
+  for sample in samples.iter() {
+    let mut segments: Vec<&[u8]> = sample.windows(SEGMENT_LEN).collect();
+    segments.sort_unstable();
+    segments.dedup();
+    for segment in segments {
+      *counts.entry(segment).or_insert(0) += 1;
+    }
+  }
+
+  let mut segments: Vec<(&[u8], usize)> = counts.into_iter().filter(|(_, c)| *c > 1).collect();
+  segments.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+  // Segments are appended in order of how common they are, overlapping the end of the dictionary
+  // where they can, and the dictionary is reversed segment by segment at the end.
+  let mut chunks: Vec<Vec<u8>> = Vec::new();
+  let mut size = 0;
+  for (segment, _) in segments {
+    if chunks
+      .iter()
+      .any(|c| c.windows(SEGMENT_LEN).any(|w| w == segment))
+    {
+      continue;
+    }
+    let extended = chunks.iter_mut().find_map(|c| {
+      (MIN_MATCH..SEGMENT_LEN)
+        .rev()
+        .find(|n| c.ends_with(&segment[..*n]))
+        .map(|n| (c, n))
+    });
+    let added = match extended {
+      Some((chunk, n)) => {
+        chunk.extend_from_slice(&segment[n..]);
+        SEGMENT_LEN - n
+      }
+      None => {
+        chunks.push(segment.to_vec());
+        SEGMENT_LEN
+      }
+    };
+    size += added;
+    if size >= max_size {
+      break;
+    }
+  }
+
+  let mut dictionary: Vec<u8> = chunks.into_iter().rev().flatten().collect();
+  let start = dictionary.len().saturating_sub(max_size);
+  dictionary.split_off(start)
+}
+
+/// Blocks compressed with a dictionary are also compressed without it once every this many blocks,
+/// to estimate what the dictionary saves.
+const DICTIONARY_SAMPLE_INTERVAL: u64 = 16;
+
+/// Sizes of the blocks compressed by a BlockCompressor.
+///
+/// The sampled blocks are measured both with and without the dictionary. Without a dictionary,
+/// every block is a sample.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+  pub uncompressed_bytes: u64,
+  pub compressed_bytes: u64,
+  pub sampled_bytes: u64,
+  pub sampled_compressed_bytes_without_dictionary: u64,
+}
+
+impl CompressionStats {
+  /// Gets the ratio of uncompressed to compressed bytes.
+  pub fn ratio(&self) -> f64 {
+    CompressionStats::ratio_of(self.uncompressed_bytes, self.compressed_bytes)
+  }
+
+  /// Gets the estimated ratio of uncompressed to compressed bytes, had the blocks been compressed
+  /// without the dictionary.
+  pub fn ratio_without_dictionary(&self) -> f64 {
+    CompressionStats::ratio_of(
+      self.sampled_bytes,
+      self.sampled_compressed_bytes_without_dictionary,
+    )
+  }
+
+  /// Adds the sizes of other compressed blocks.
+  pub fn add(&mut self, other: &CompressionStats) {
+    self.uncompressed_bytes += other.uncompressed_bytes;
+    self.compressed_bytes += other.compressed_bytes;
+    self.sampled_bytes += other.sampled_bytes;
+    self.sampled_compressed_bytes_without_dictionary +=
+      other.sampled_compressed_bytes_without_dictionary;
+  }
+
+  /// Encodes the CompressionStats as little-endian u64s.
+  pub(crate) fn encode(&self) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(32);
+    buf.extend_from_slice(&self.uncompressed_bytes.to_le_bytes());
+    buf.extend_from_slice(&self.compressed_bytes.to_le_bytes());
+    buf.extend_from_slice(&self.sampled_bytes.to_le_bytes());
+    buf.extend_from_slice(
+      &self
+        .sampled_compressed_bytes_without_dictionary
+        .to_le_bytes(),
+    );
+    buf
+  }
+
+  /// Decodes CompressionStats encoded by `encode`.
+  pub(crate) fn decode(buf: &[u8]) -> Option<CompressionStats> {
+    if buf.len() != 32 {
+      return None;
+    }
+    let field = |i: usize| {
+      let mut bytes = [0; 8];
+      bytes.copy_from_slice(&buf[i * 8..i * 8 + 8]);
+      u64::from_le_bytes(bytes)
+    };
+    Some(CompressionStats {
+      uncompressed_bytes: field(0),
+      compressed_bytes: field(1),
+      sampled_bytes: field(2),
+      sampled_compressed_bytes_without_dictionary: field(3),
+    })
+  }
+
+  fn ratio_of(uncompressed: u64, compressed: u64) -> f64 {
+    if compressed == 0 {
+      return 1.0;
+    }
+    uncompressed as f64 / compressed as f64
+  }
+}
+
+/// Compresses the blocks of a table with a Compressor and an optional dictionary.
+///
+/// With a dictionary, one in every 16 blocks is also compressed without it, to estimate what the
+/// dictionary saves without compressing every block twice.
+pub struct BlockCompressor {
+  compressor: Arc<dyn Compressor>,
+  dictionary: Option<Vec<u8>>,
+  blocks: u64,
+  stats: CompressionStats,
+}
+
+impl BlockCompressor {
+  /// Creates a new BlockCompressor.
+  pub fn new(compressor: Arc<dyn Compressor>, dictionary: Option<Vec<u8>>) -> BlockCompressor {
+    BlockCompressor {
+      compressor,
+      dictionary,
+      blocks: 0,
+      stats: CompressionStats::default(),
+    }
+  }
+
+  /// Compresses a block, prefixed by the id of the codec.
+  pub fn compress(&mut self, block: &[u8]) -> Vec<u8> {
+    let compressed = compress_block(self.compressor.as_ref(), self.dictionary(), block);
+    let without_dictionary = match self.dictionary {
+      Some(_) if self.blocks.is_multiple_of(DICTIONARY_SAMPLE_INTERVAL) => {
+        Some(compress_block(self.compressor.as_ref(), None, block).len())
+      }
+      Some(_) => None,
+      None => Some(compressed.len()),
+    };
+    self.blocks += 1;
+
+    self.stats.uncompressed_bytes += block.len() as u64;
+    self.stats.compressed_bytes += compressed.len() as u64;
+    if let Some(without_dictionary) = without_dictionary {
+      self.stats.sampled_bytes += block.len() as u64;
+      self.stats.sampled_compressed_bytes_without_dictionary += without_dictionary as u64;
+    }
+    compressed
+  }
+
+  /// Gets the dictionary the blocks are compressed with.
+  pub fn dictionary(&self) -> Option<&[u8]> {
+    self.dictionary.as_deref()
+  }
+
+  /// Gets the sizes of the blocks compressed so far.
+  pub fn stats(&self) -> CompressionStats {
+    self.stats
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::compression::{
+    builtin_compressors, compress_block, decompress_block, train_dictionary, BlockCompressor,
+    CompressionStats, Compressor, LzCompressor, LZ_COMPRESSION_ID, NO_COMPRESSION_ID,
+  };
+  use std::sync::Arc;
+
+  #[test]
+  fn test_lz_round_trip() {
+    let mut data = Vec::new();
+    for i in 0..200 {
+      data.extend_from_slice(format!("{{\"id\":{},\"name\":\"Lime Smoothie\"}},", i).as_bytes());
+    }
+    data.extend_from_slice(&[7; 1000]);
+
+    let lz = LzCompressor;
+    let compressed = lz.compress(&data);
+    assert!(compressed.len() < data.len() / 3);
+    assert_eq!(lz.decompress(&compressed).unwrap(), data);
+
+    for data in [&b""[..], b"abc", b"abcdabcdabcd", &[0; 300]].iter() {
+      assert_eq!(lz.decompress(&lz.compress(data)).unwrap(), *data);
+    }
+
+    assert!(lz.decompress(&compressed[..compressed.len() / 2]).is_err());
+
+    // A corrupted length larger than the block could expand to is rejected up front.
+    let mut corrupted = lz.compress(&data);
+    corrupted[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(lz.decompress(&corrupted).is_err());
+    assert!(lz.decompress_with_dictionary(&corrupted, b"Lime").is_err());
+  }
+
+  #[test]
+  fn test_compress_block() {
+    let compressors = builtin_compressors();
+    let data = b"Lime Smoothie, Lime Smoothie, Lime Smoothie".to_vec();
+
+    let block = compress_block(&LzCompressor, None, &data);
+    assert_eq!(block[0], LZ_COMPRESSION_ID);
+    assert_eq!(decompress_block(&block, &compressors, None).unwrap(), data);
+
+    let block = compress_block(&LzCompressor, None, b"Lime");
+    assert_eq!(block[0], NO_COMPRESSION_ID);
+    assert_eq!(
+      decompress_block(&block, &compressors, None).unwrap(),
+      b"Lime"
+    );
+
+    assert!(decompress_block(&[42, 1, 2], &compressors, None).is_err());
+  }
+
+  #[test]
+  fn test_dictionary() {
+    let values: Vec<Vec<u8>> = (0..500)
+      .map(|i| {
+        format!(
+          "{{\"id\":{},\"name\":\"Smoothie {}\",\"fruit\":\"Lime\",\"size\":\"large\"}}",
+          i,
+          i * 7
+        )
+        .into_bytes()
+      })
+      .collect();
+    let samples: Vec<&[u8]> = values.iter().step_by(5).map(|v| v.as_slice()).collect();
+
+    let dictionary = train_dictionary(&samples, 256);
+    assert!(!dictionary.is_empty() && dictionary.len() <= 256);
+
+    let compressors = builtin_compressors();
+    let mut compressor = BlockCompressor::new(Arc::new(LzCompressor), Some(dictionary.clone()));
+    for value in values.iter() {
+      let block = compressor.compress(value);
+      assert_eq!(
+        decompress_block(&block, &compressors, Some(&dictionary)).unwrap(),
+        *value
+      );
+    }
+
+    let stats = compressor.stats();
+    assert!(stats.ratio() > 1.5);
+    assert!(stats.ratio() > stats.ratio_without_dictionary());
+    // Only every 16th block is compressed a second time, without the dictionary.
+    let sampled: u64 = values.iter().step_by(16).map(|v| v.len() as u64).sum();
+    assert_eq!(stats.sampled_bytes, sampled);
+    assert_eq!(CompressionStats::decode(&stats.encode()), Some(stats));
+
+    let mut plain = BlockCompressor::new(Arc::new(LzCompressor), None);
+    plain.compress(&values[0]);
+    assert_eq!(
+      plain.stats().ratio(),
+      plain.stats().ratio_without_dictionary()
+    );
+
+    let block = compressor.compress(&values[0]);
+    assert!(decompress_block(&block, &compressors, None).is_err());
+  }
+}
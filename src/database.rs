@@ -1,7 +1,32 @@
-use crate::mem_table::MemTable;
-use crate::wal::WAL;
-use std::path::PathBuf;
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::blob::{BlobFileStats, BlobIndex, BlobStore};
+use crate::block_cache::BlockCache;
+use crate::column_family::{
+  ColumnFamily, ColumnFamilyOptions, TableFile, DEFAULT_COLUMN_FAMILY_ID,
+  DEFAULT_COLUMN_FAMILY_NAME,
+};
+use crate::comparator::Comparator;
+use crate::compression::CompressionStats;
+use crate::db_iterator::DBIterator;
+use crate::manifest::{table_path, Manifest, MANIFEST_FILE};
+use crate::mem_table::{MemTable, MemTableEntry};
+use crate::merge_operator::MergeOperator;
+use crate::options::{IngestOptions, Options, ReadOptions, SyncMode, WriteOptions};
+use crate::options_file::{read_options_file, write_options_file, OPTIONS_FILE};
+use crate::snapshot::Snapshot;
+use crate::table::Table;
+use crate::table_file_writer::COMPARATOR_META_BLOCK;
+use crate::transaction::Transaction;
+use crate::utils::{copy_file, files_with_ext, link_or_copy, now};
+use crate::wal::{wal_number, WAL};
+use crate::wal_iterator::{WALEntry, WALRecord};
+use crate::write_batch::WriteBatch;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, remove_file};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct DatabaseEntry {
@@ -26,70 +51,2338 @@ impl DatabaseEntry {
 
 pub struct Database {
   dir: PathBuf,
-  mem_table: MemTable,
+  column_families: Vec<ColumnFamily>,
   wal: WAL,
+  options: Options,
+  block_cache: Arc<BlockCache>,
+  blob_store: BlobStore,
+  prepared_transactions: Vec<Transaction>,
+  undecided_transactions: BTreeMap<u64, Vec<WALEntry>>,
+  next_transaction_id: u64,
+  next_column_family_id: u32,
+  next_table_number: u64,
+  /// Shared with every Snapshot, to count the live Snapshots.
+  snapshots: Arc<()>,
 }
 
 impl Database {
   pub fn new(dir: &str) -> Database {
+    Database::open(dir, Options::default()).unwrap()
+  }
+
+  /// Opens a Database that orders its keys with a Comparator.
+  ///
+  /// The name of the Comparator is persisted in the Database's OPTIONS file when the Database is
+  /// created. Opening the Database again with a Comparator of a different name fails.
+  pub fn with_comparator(dir: &str, comparator: Arc<dyn Comparator>) -> io::Result<Database> {
+    Database::open(dir, Options::default().comparator(comparator))
+  }
+
+  /// Opens a Database with Options.
+  ///
+  /// A Database exists once its directory has an OPTIONS file. The Options are validated against
+  /// the OPTIONS file of an existing Database, and persisted to it once the Database is open, so
+  /// a failed open leaves the OPTIONS file as it was.
+  ///
+  /// The tables listed in the MANIFEST are opened, and the WALs that were not made obsolete by a
+  /// flush are replayed into the MemTables.
+  pub fn open(dir: &str, options: Options) -> io::Result<Database> {
     let dir = PathBuf::from(dir);
 
-    let (wal, mem_table) = WAL::load_from_dir(&dir).unwrap();
+    if options.use_mmap_reads && options.use_direct_reads {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "use_mmap_reads and use_direct_reads cannot both be set",
+      ));
+    }
+
+    if !dir.is_dir() {
+      if !options.create_if_missing {
+        return Err(io::Error::new(
+          io::ErrorKind::NotFound,
+          format!("Database {} does not exist", dir.display()),
+        ));
+      }
+      create_dir_all(&dir)?;
+    }
+
+    match read_options_file(&dir)? {
+      Some(_) if options.error_if_exists => {
+        return Err(io::Error::new(
+          io::ErrorKind::AlreadyExists,
+          format!("Database {} already exists", dir.display()),
+        ));
+      }
+      Some(persisted) => options.validate(&persisted)?,
+      None if !options.create_if_missing => {
+        return Err(io::Error::new(
+          io::ErrorKind::NotFound,
+          format!("Database {} does not exist", dir.display()),
+        ));
+      }
+      None => {}
+    }
+
+    let block_cache = options
+      .block_cache
+      .clone()
+      .unwrap_or_else(|| Arc::new(BlockCache::new(options.block_cache_size)));
+
+    let manifest = Manifest::read(&dir)?.unwrap_or_default();
+    for wal_file in files_with_ext(&dir, "wal") {
+      if wal_number(&wal_file).is_some_and(|n| n < manifest.wal_number) {
+        remove_file(wal_file)?;
+      }
+    }
+
+    let (wal, mut column_families, prepared_transactions) = WAL::load_from_dir(&dir, &options)?;
 
-    Database {
-      dir: dir,
-      mem_table,
+    let mut table_numbers = Vec::new();
+    for (id, number) in manifest.tables.iter() {
+      if let Some(column_family) = column_families.iter_mut().find(|cf| cf.id == *id) {
+        let mut table_file = TableFile::open(&dir, *number, &options, block_cache.clone())?;
+        table_file.timestamp = manifest
+          .ingested
+          .iter()
+          .find(|(n, _)| n == number)
+          .map(|(_, timestamp)| *timestamp);
+        column_family.tables.insert(0, table_file);
+        table_numbers.push(*number);
+      }
+    }
+    // Tables of dropped column families, and tables of flushes that did not finish.
+    for path in files_with_ext(&dir, "sst") {
+      let number = path.file_stem().and_then(|s| s.to_str()?.parse().ok());
+      if !number.is_some_and(|n| table_numbers.contains(&n)) {
+        remove_file(path)?;
+      }
+    }
+
+    let mut blob_store = BlobStore::open(&dir, options.blob_file_size)?;
+    for column_family in column_families.iter() {
+      for blob_index in column_family.blob_indexes()? {
+        blob_store.mark_live(&blob_index);
+      }
+    }
+    let undecided_transactions = prepared_transactions
+      .iter()
+      .map(|t| (t.id(), t.entries().to_vec()))
+      .collect();
+    let next_transaction_id = prepared_transactions
+      .iter()
+      .map(|t| t.id() + 1)
+      .max()
+      .unwrap_or(0);
+    let next_column_family_id = column_families.iter().map(|cf| cf.id + 1).max().unwrap();
+
+    let db = Database {
+      dir,
+      column_families,
       wal,
+      options,
+      block_cache,
+      blob_store,
+      prepared_transactions,
+      undecided_transactions,
+      next_transaction_id,
+      next_column_family_id,
+      next_table_number: manifest.next_table_number,
+      snapshots: Arc::new(()),
+    };
+    db.write_manifest()?;
+    write_options_file(&db.dir, &db.options.to_options_file())?;
+
+    Ok(db)
+  }
+
+  pub fn get(&self, key: &[u8]) -> io::Result<Option<DatabaseEntry>> {
+    self.get_cf(DEFAULT_COLUMN_FAMILY_NAME, key)
+  }
+
+  /// Gets a Key-Value pair from a column family.
+  ///
+  /// Returns `None` if the column family does not exist.
+  pub fn get_cf(&self, column_family: &str, key: &[u8]) -> io::Result<Option<DatabaseEntry>> {
+    self.get_cf_opt(&ReadOptions::default(), column_family, key)
+  }
+
+  /// Gets a Key-Value pair with ReadOptions.
+  pub fn get_opt(
+    &self,
+    read_options: &ReadOptions,
+    key: &[u8],
+  ) -> io::Result<Option<DatabaseEntry>> {
+    self.get_cf_opt(read_options, DEFAULT_COLUMN_FAMILY_NAME, key)
+  }
+
+  /// Gets a Key-Value pair from a column family with ReadOptions.
+  ///
+  /// The MemTable is checked first, and then the tables from newest to oldest. Merge operands are
+  /// collected until an entry with a value or a tombstone is found. With a Snapshot in the
+  /// ReadOptions, the MemTable and tables of the Snapshot are read instead.
+  ///
+  /// Fails if a table cannot be read, or if the key has merge operands and the column family has no
+  /// MergeOperator, for example when the Database is reopened and `set_merge_operator` has not been
+  /// called yet.
+  pub fn get_cf_opt(
+    &self,
+    read_options: &ReadOptions,
+    column_family: &str,
+    key: &[u8],
+  ) -> io::Result<Option<DatabaseEntry>> {
+    let (column_family, source) = match self.read_column_family(read_options, column_family) {
+      Some(column_families) => column_families,
+      None => return Ok(None),
+    };
+    let entries = source.get_entries(key, read_options)?;
+    self.resolve_entries(column_family, key, &entries)
+  }
+
+  /// Creates an iterator over the Key-Value pairs of the default column family.
+  pub fn iter(&self) -> DBIterator<'_> {
+    self
+      .iter_cf(DEFAULT_COLUMN_FAMILY_NAME)
+      .expect("the default column family always exists")
+  }
+
+  /// Creates an iterator over the Key-Value pairs of a column family.
+  ///
+  /// Returns `None` if the column family does not exist.
+  pub fn iter_cf(&self, column_family: &str) -> Option<DBIterator<'_>> {
+    self.iter_cf_opt(&ReadOptions::default(), column_family)
+  }
+
+  /// Creates an iterator over the Key-Value pairs of a column family with ReadOptions.
+  ///
+  /// Returns `None` if the column family does not exist, or did not exist when the Snapshot of the
+  /// ReadOptions was taken.
+  pub fn iter_cf_opt(
+    &self,
+    read_options: &ReadOptions,
+    column_family: &str,
+  ) -> Option<DBIterator<'_>> {
+    let (column_family, source) = self.read_column_family(read_options, column_family)?;
+    Some(DBIterator::new(
+      self,
+      column_family,
+      source,
+      self.options.comparator.as_ref(),
+      read_options,
+    ))
+  }
+
+  /// Takes a Snapshot of every column family, to read with `ReadOptions::snapshot`.
+  pub fn snapshot(&self) -> Snapshot {
+    Snapshot::new(&self.column_families, self.snapshots.clone())
+  }
+
+  /// Gets Key-Value pairs, in the order of the keys.
+  pub fn multi_get(&self, keys: &[&[u8]]) -> Vec<io::Result<Option<DatabaseEntry>>> {
+    self.multi_get_cf_opt(&ReadOptions::default(), DEFAULT_COLUMN_FAMILY_NAME, keys)
+  }
+
+  /// Gets Key-Value pairs from a column family with ReadOptions, in the order of the keys.
+  ///
+  /// The keys are looked up together in one pass over the MemTable and each table, so the filter,
+  /// index and data blocks they share are only read once. Every key gets `None` if the column
+  /// family does not exist, or did not exist when the Snapshot of the ReadOptions was taken.
+  pub fn multi_get_cf_opt(
+    &self,
+    read_options: &ReadOptions,
+    column_family: &str,
+    keys: &[&[u8]],
+  ) -> Vec<io::Result<Option<DatabaseEntry>>> {
+    let (column_family, source) = match self.read_column_family(read_options, column_family) {
+      Some(column_families) => column_families,
+      None => return keys.iter().map(|_| Ok(None)).collect(),
+    };
+    source
+      .multi_get_entries(keys, read_options)
+      .into_iter()
+      .zip(keys.iter())
+      .map(|(entries, key)| self.resolve_entries(column_family, key, &entries?))
+      .collect()
+  }
+
+  /// Gets the Key-Value pair of a key from its entries, newest first, merging the operands.
+  ///
+  /// Fails if there are merge operands, but the column family has no MergeOperator.
+  pub(crate) fn resolve_entries(
+    &self,
+    column_family: &ColumnFamily,
+    key: &[u8],
+    entries: &[MemTableEntry],
+  ) -> io::Result<Option<DatabaseEntry>> {
+    let newest = match entries.first() {
+      Some(newest) => newest,
+      None => return Ok(None),
+    };
+    let base = entries.last().unwrap();
+
+    let value = if base.deleted || base.is_expired(now()) || base.value.is_none() {
+      None
+    } else {
+      Some(self.entry_value(base)?)
+    };
+    let operands: Vec<Vec<u8>> = entries
+      .iter()
+      .rev()
+      .flat_map(|e| e.operands.iter().cloned())
+      .collect();
+    if !operands.is_empty() {
+      let merge_operator = column_family
+        .options
+        .merge_operator
+        .as_ref()
+        .ok_or_else(|| {
+          io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+              "column family {} has merge operands, but no MergeOperator",
+              column_family.name
+            ),
+          )
+        })?;
+      return Ok(Some(DatabaseEntry {
+        key: newest.key.clone(),
+        value: merge_operator.full_merge(key, value.as_deref(), &operands),
+        timestamp: newest.timestamp,
+      }));
+    }
+    Ok(value.map(|value| DatabaseEntry {
+      key: newest.key.clone(),
+      value,
+      timestamp: newest.timestamp,
+    }))
+  }
+
+  pub fn set(&mut self, key: &[u8], value: &[u8]) -> Result<usize, usize> {
+    self.set_cf(DEFAULT_COLUMN_FAMILY_NAME, key, value)
+  }
+
+  /// Sets a Key-Value pair in a column family.
+  pub fn set_cf(&mut self, column_family: &str, key: &[u8], value: &[u8]) -> Result<usize, usize> {
+    let id = self.column_family(column_family).ok_or(0usize)?.id;
+    self.write_record(WALRecord::Entry(WALEntry {
+      key: key.to_owned(),
+      value: Some(value.to_owned()),
+      timestamp: now(),
+      expires_at: None,
+      deleted: false,
+      column_family: id,
+    }))
+  }
+
+  /// Sets a Key-Value pair that expires after a time to live.
+  ///
+  /// Once expired, the Key-Value pair is hidden from reads and is dropped when the WAL is recovered.
+  pub fn set_with_ttl(&mut self, key: &[u8], value: &[u8], ttl: Duration) -> Result<usize, usize> {
+    self.set_with_ttl_cf(DEFAULT_COLUMN_FAMILY_NAME, key, value, ttl)
+  }
+
+  /// Sets a Key-Value pair in a column family that expires after a time to live.
+  pub fn set_with_ttl_cf(
+    &mut self,
+    column_family: &str,
+    key: &[u8],
+    value: &[u8],
+    ttl: Duration,
+  ) -> Result<usize, usize> {
+    let id = self.column_family(column_family).ok_or(0usize)?.id;
+    let timestamp = now();
+    self.write_record(WALRecord::Entry(WALEntry {
+      key: key.to_owned(),
+      value: Some(value.to_owned()),
+      timestamp,
+      expires_at: Some(timestamp + ttl.as_micros()),
+      deleted: false,
+      column_family: id,
+    }))
+  }
+
+  pub fn delete(&mut self, key: &[u8]) -> Result<usize, usize> {
+    self.delete_cf(DEFAULT_COLUMN_FAMILY_NAME, key)
+  }
+
+  /// Deletes a Key-Value pair in a column family.
+  pub fn delete_cf(&mut self, column_family: &str, key: &[u8]) -> Result<usize, usize> {
+    let id = self.column_family(column_family).ok_or(0usize)?.id;
+    self.write_record(WALRecord::Entry(WALEntry {
+      key: key.to_owned(),
+      value: None,
+      timestamp: now(),
+      expires_at: None,
+      deleted: true,
+      column_family: id,
+    }))
+  }
+
+  /// Deletes every Key-Value pair from `start` (inclusive) to `end` (exclusive).
+  ///
+  /// A single range tombstone is written, no matter how many keys are in the range.
+  pub fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<usize, usize> {
+    self.delete_range_cf(DEFAULT_COLUMN_FAMILY_NAME, start, end)
+  }
+
+  /// Deletes every Key-Value pair from `start` (inclusive) to `end` (exclusive) in a column family.
+  pub fn delete_range_cf(
+    &mut self,
+    column_family: &str,
+    start: &[u8],
+    end: &[u8],
+  ) -> Result<usize, usize> {
+    let id = self.column_family(column_family).ok_or(0usize)?.id;
+    if self.options.comparator.compare(start, end) != Ordering::Less {
+      return Ok(0);
     }
+
+    self.write_record(WALRecord::RangeDelete {
+      start: start.to_owned(),
+      end: end.to_owned(),
+      timestamp: now(),
+      column_family: id,
+    })
+  }
+
+  /// Sets the MergeOperator used to combine merge operands in the default column family.
+  ///
+  /// The MergeOperator must be set before merging or reading merged keys, including merges
+  /// recovered from the WAL.
+  pub fn set_merge_operator(&mut self, merge_operator: Box<dyn MergeOperator>) {
+    self.column_families[0].options.merge_operator = Some(merge_operator);
+  }
+
+  /// Merges an operand into a Key-Value pair without reading the current value.
+  ///
+  /// The operand is combined with the current value by the MergeOperator when the key is read.
+  pub fn merge(&mut self, key: &[u8], operand: &[u8]) -> Result<usize, usize> {
+    self.merge_cf(DEFAULT_COLUMN_FAMILY_NAME, key, operand)
   }
 
-  pub fn get(&self, key: &[u8]) -> Option<DatabaseEntry> {
-    if let Some(mem_entry) = self.mem_table.get(key) {
-      return Some(DatabaseEntry {
-        key: mem_entry.key.clone(),
-        value: mem_entry.value.as_ref().unwrap().clone(),
-        timestamp: mem_entry.timestamp,
-      });
+  /// Merges an operand into a Key-Value pair in a column family.
+  ///
+  /// The column family must have a MergeOperator.
+  pub fn merge_cf(
+    &mut self,
+    column_family: &str,
+    key: &[u8],
+    operand: &[u8],
+  ) -> Result<usize, usize> {
+    let column_family = self.column_family(column_family).ok_or(0usize)?;
+    if column_family.options.merge_operator.is_none() {
+      return Err(0);
     }
 
-    None
+    self.write_record(WALRecord::Merge(WALEntry {
+      key: key.to_owned(),
+      value: Some(operand.to_owned()),
+      timestamp: now(),
+      expires_at: None,
+      deleted: false,
+      column_family: column_family.id,
+    }))
   }
 
-  pub fn set(&mut self, key: &[u8], value: &[u8]) -> Result<usize, usize> {
-    let timestamp = SystemTime::now()
-      .duration_since(UNIX_EPOCH)
-      .unwrap()
-      .as_micros();
+  /// Applies a WriteBatch atomically.
+  ///
+  /// The operations in the WriteBatch may span column families. Either all of the operations are
+  /// applied or, if a column family does not exist or cannot merge, none of them are.
+  pub fn write(&mut self, batch: WriteBatch) -> Result<usize, usize> {
+    self.write_opt(batch, &WriteOptions::default())
+  }
+
+  /// Applies a WriteBatch atomically with WriteOptions.
+  pub fn write_opt(
+    &mut self,
+    batch: WriteBatch,
+    write_options: &WriteOptions,
+  ) -> Result<usize, usize> {
+    let mut records = Vec::new();
+    for (name, record) in batch.into_records() {
+      let column_family = self.column_family(&name).ok_or(0usize)?;
+      if let WALRecord::Merge(_) = record {
+        if column_family.options.merge_operator.is_none() {
+          return Err(0);
+        }
+      }
+      records.push(record.with_column_family(column_family.id).unwrap());
+    }
+    if records.is_empty() {
+      return Ok(0);
+    }
+
+    let mut separated = Vec::with_capacity(records.len());
+    for record in records {
+      separated.push(self.separate_value(record).map_err(|_| 0usize)?);
+    }
+    let records = separated;
+
+    if self.wal.write_batch(&records, now()).is_err() {
+      return Err(0);
+    }
+    if self.sync_wal(write_options).is_err() {
+      return Err(0);
+    }
 
-    let wal_res = self.wal.set(key, value, timestamp);
-    if wal_res.is_err() {
+    for record in records.iter() {
+      self.apply(record);
+    }
+    if self.flush_if_full().is_err() {
+      return Err(0);
+    }
+
+    Ok(records.len())
+  }
+
+  /// Creates a column family with options.
+  ///
+  /// Fails if the column family already exists. The options of a column family recovered from the
+  /// WAL are set with `set_column_family_options`.
+  pub fn create_column_family(
+    &mut self,
+    name: &str,
+    options: ColumnFamilyOptions,
+  ) -> Result<usize, usize> {
+    if self.column_family(name).is_some() {
+      return Err(0);
+    }
+
+    let id = self.next_column_family_id;
+    if self.wal.create_column_family(id, name).is_err() {
       return Err(0);
     }
     if self.wal.flush().is_err() {
       return Err(0);
     }
 
-    self.mem_table.set(key, value, timestamp);
+    self.next_column_family_id += 1;
+    self.column_families.push(ColumnFamily::new(
+      id,
+      name,
+      options,
+      self.options.comparator.clone(),
+    ));
 
     Ok(1)
   }
 
-  pub fn delete(&mut self, key: &[u8]) -> Result<usize, usize> {
-    let timestamp = SystemTime::now()
-      .duration_since(UNIX_EPOCH)
-      .unwrap()
-      .as_micros();
+  /// Sets the options of an existing column family, such as one recovered from the WAL.
+  ///
+  /// Fails if the column family does not exist.
+  pub fn set_column_family_options(
+    &mut self,
+    name: &str,
+    options: ColumnFamilyOptions,
+  ) -> Result<usize, usize> {
+    let column_family = self
+      .column_families
+      .iter_mut()
+      .find(|cf| cf.name == name)
+      .ok_or(0usize)?;
+    column_family.options = options;
+    Ok(1)
+  }
 
-    let wal_res = self.wal.delete(key, timestamp);
-    if wal_res.is_err() {
+  /// Drops a column family and all of its Key-Value pairs.
+  ///
+  /// The default column family cannot be dropped.
+  pub fn drop_column_family(&mut self, name: &str) -> Result<usize, usize> {
+    if name == DEFAULT_COLUMN_FAMILY_NAME {
+      return Err(0);
+    }
+    let id = match self.column_family(name) {
+      Some(column_family) => column_family.id,
+      None => return Ok(0),
+    };
+
+    if self.wal.drop_column_family(id).is_err() {
       return Err(0);
     }
     if self.wal.flush().is_err() {
       return Err(0);
     }
 
-    self.mem_table.delete(key, timestamp);
+    let idx = self.column_families.iter().position(|cf| cf.id == id);
+    let column_family = self.column_families.remove(idx.unwrap());
+    if self.write_manifest().is_err() {
+      return Err(0);
+    }
+    for table_file in column_family.tables.iter() {
+      if remove_file(table_path(&self.dir, table_file.number)).is_err() {
+        return Err(0);
+      }
+    }
 
     Ok(1)
   }
+
+  /// Gets the names of the column families in the Database.
+  pub fn column_families(&self) -> Vec<&str> {
+    self
+      .column_families
+      .iter()
+      .map(|cf| cf.name.as_str())
+      .collect()
+  }
+
+  /// Sets or deletes a Key-Value pair only if its current value matches `expected`.
+  ///
+  /// An `expected` of `None` matches a key that does not exist, and a `new` value of `None`
+  /// deletes the key. The comparison and the write are atomic with respect to other writers,
+  /// because every write to the Database requires exclusive access to it.
+  ///
+  /// Returns `Ok(true)` if the write was applied, and `Ok(false)` if the current value did not match.
+  pub fn compare_and_set(
+    &mut self,
+    key: &[u8],
+    expected: Option<&[u8]>,
+    new: Option<&[u8]>,
+  ) -> Result<bool, usize> {
+    let current = self.get(key).map_err(|_| 0usize)?;
+    if current.as_ref().map(|e| e.value()) != expected {
+      return Ok(false);
+    }
+
+    match new {
+      Some(value) => self.set(key, value)?,
+      None => self.delete(key)?,
+    };
+
+    Ok(true)
+  }
+
+  /// Sets a Key-Value pair only if the key does not exist.
+  ///
+  /// Returns `Ok(true)` if the Key-Value pair was set, and `Ok(false)` if the key already existed.
+  pub fn put_if_absent(&mut self, key: &[u8], value: &[u8]) -> Result<bool, usize> {
+    self.compare_and_set(key, None, Some(value))
+  }
+
+  /// Begins a new Transaction.
+  ///
+  /// Writes to the Transaction are not visible until it is committed.
+  pub fn begin_transaction(&mut self) -> Transaction {
+    let transaction = Transaction::new(self.next_transaction_id);
+    self.next_transaction_id += 1;
+    transaction
+  }
+
+  /// Takes the Transactions that were prepared, but not committed or rolled back, before the
+  /// Database was last shutdown.
+  ///
+  /// The application is responsible for deciding the fate of each recovered Transaction.
+  pub fn prepared_transactions(&mut self) -> Vec<Transaction> {
+    std::mem::take(&mut self.prepared_transactions)
+  }
+
+  /// Gets the BlockCache of the Database, which may be shared with other Databases.
+  pub fn block_cache(&self) -> &Arc<BlockCache> {
+    &self.block_cache
+  }
+
+  /// Creates a consistent copy of the Database in a new directory, which `Database::open` can open.
+  ///
+  /// The tables, the MANIFEST and the OPTIONS file are hard linked into the directory, as they are
+  /// never modified in place, falling back to copies across file systems. The WAL and the blob
+  /// files are appended to, so they are synced and copied.
+  pub fn checkpoint(&mut self, target_dir: &str) -> io::Result<()> {
+    let target_dir = PathBuf::from(target_dir);
+    if target_dir.exists() {
+      return Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!(
+          "checkpoint directory {} already exists",
+          target_dir.display()
+        ),
+      ));
+    }
+    create_dir_all(&target_dir)?;
+
+    self.blob_store.sync()?;
+    self.wal.sync()?;
+    self.write_manifest()?;
+
+    for column_family in self.column_families.iter() {
+      for table_file in column_family.tables.iter() {
+        link_or_copy(
+          &table_path(&self.dir, table_file.number),
+          &table_path(&target_dir, table_file.number),
+        )?;
+      }
+    }
+    for file in [MANIFEST_FILE, OPTIONS_FILE] {
+      link_or_copy(&self.dir.join(file), &target_dir.join(file))?;
+    }
+    let wal_path = self.wal.path();
+    copy_file(wal_path, &target_dir.join(wal_path.file_name().unwrap()))?;
+    for blob_file in files_with_ext(&self.dir, "blob") {
+      copy_file(&blob_file, &target_dir.join(blob_file.file_name().unwrap()))?;
+    }
+
+    Ok(())
+  }
+
+  /// Ingests table files written by a TableFileWriter into the default column family.
+  pub fn ingest_external_files(&mut self, paths: &[&str]) -> io::Result<()> {
+    self.ingest_external_files_cf(DEFAULT_COLUMN_FAMILY_NAME, paths)
+  }
+
+  /// Ingests table files written by a TableFileWriter into a column family.
+  pub fn ingest_external_files_cf(
+    &mut self,
+    column_family: &str,
+    paths: &[&str],
+  ) -> io::Result<()> {
+    self.ingest_external_files_cf_opt(&IngestOptions::new(), column_family, paths)
+  }
+
+  /// Ingests table files written by a TableFileWriter into a column family, without writing their
+  /// entries to the WAL or the MemTable.
+  ///
+  /// The files are copied into the Database directory, or moved if the IngestOptions ask for it,
+  /// and become the newest tables of the column family. Each file is given the current time as the
+  /// timestamp of its entries, so they are newer than every existing write, and replace the values
+  /// the column family already has for their keys. The MemTable is read before the tables, so the
+  /// MemTables are flushed first if a file overlaps a key or a range tombstone of the MemTable.
+  ///
+  /// Fails without ingesting any file if a file was not written with the Comparator of the
+  /// Database, or if the key ranges of two files overlap.
+  pub fn ingest_external_files_cf_opt(
+    &mut self,
+    ingest_options: &IngestOptions,
+    column_family: &str,
+    paths: &[&str],
+  ) -> io::Result<()> {
+    let idx = self
+      .column_families
+      .iter()
+      .position(|cf| cf.name == column_family)
+      .ok_or_else(|| {
+        io::Error::new(
+          io::ErrorKind::NotFound,
+          format!("column family {} does not exist", column_family),
+        )
+      })?;
+
+    let mut ranges = Vec::new();
+    for path in paths.iter() {
+      let (smallest, largest) = self.external_file_range(Path::new(path))?;
+      ranges.push((*path, smallest, largest));
+    }
+    let comparator = self.options.comparator.as_ref();
+    ranges.sort_by(|a, b| comparator.compare(&a.1, &b.1));
+    for pair in ranges.windows(2) {
+      if comparator.compare(&pair[0].2, &pair[1].1) != Ordering::Less {
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidInput,
+          format!("external files {} and {} overlap", pair[0].0, pair[1].0),
+        ));
+      }
+    }
+
+    let column_family = &self.column_families[idx];
+    if ranges
+      .iter()
+      .any(|(_, smallest, largest)| column_family.mem_table_overlaps(smallest, largest))
+    {
+      self.flush_mem_tables()?;
+    }
+
+    let mut table_files = Vec::new();
+    for path in paths.iter() {
+      let number = self.next_table_number;
+      self.next_table_number += 1;
+      let target = table_path(&self.dir, number);
+      let table_file = if ingest_options.move_files {
+        link_or_copy(Path::new(path), &target)
+      } else {
+        copy_file(Path::new(path), &target)
+      }
+      .and_then(|_| TableFile::open(&self.dir, number, &self.options, self.block_cache.clone()));
+      match table_file {
+        Ok(mut table_file) => {
+          table_file.timestamp = Some(now());
+          table_files.push(table_file);
+        }
+        Err(e) => {
+          for table_file in table_files.iter() {
+            remove_file(table_path(&self.dir, table_file.number))?;
+          }
+          return Err(e);
+        }
+      }
+    }
+
+    for table_file in table_files {
+      self.column_families[idx].tables.insert(0, table_file);
+    }
+    self.write_manifest()?;
+    if ingest_options.move_files {
+      for path in paths.iter() {
+        remove_file(path)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Flushes the MemTable of every column family to disk.
+  ///
+  /// Flushes always run on the calling thread, so `wait = false` currently behaves like
+  /// `wait = true`: the flush has finished when this returns. Nothing is written if every MemTable
+  /// is empty.
+  pub fn flush(&mut self, wait: bool) -> io::Result<()> {
+    let _ = wait;
+    let empty = self
+      .column_families
+      .iter()
+      .all(|cf| cf.mem_table.len() == 0 && cf.mem_table.range_tombstones().is_empty());
+    if empty {
+      return Ok(());
+    }
+    self.flush_mem_tables()
+  }
+
+  /// Compacts the keys from `start` (inclusive) to `end` (exclusive) of the default column family.
+  pub fn compact_range(&mut self, start: &[u8], end: &[u8]) -> io::Result<()> {
+    self.compact_range_cf(DEFAULT_COLUMN_FAMILY_NAME, start, end)
+  }
+
+  /// Compacts the keys from `start` (inclusive) to `end` (exclusive) of a column family.
+  ///
+  /// Tables are not organized into levels, so there is no bottom level to compact into. Instead, if
+  /// the MemTable of the column family is not empty, the MemTables of every column family are
+  /// flushed, and then the newest tables, down to the oldest table with a key in the range, are
+  /// merged into a single table that replaces them. Older tables are left as they are.
+  ///
+  /// No older table has a key in the range, so once this returns every key in the range is in the
+  /// merged table, without the deleted and expired keys that have no merge operands, and without
+  /// the range tombstones inside of the range. Keys outside of the range that were in the merged
+  /// tables are rewritten into the new table, tombstones included, with their expired values
+  /// replaced by tombstones, unless every table was merged. The compaction runs on the calling
+  /// thread.
+  pub fn compact_range_cf(
+    &mut self,
+    column_family: &str,
+    start: &[u8],
+    end: &[u8],
+  ) -> io::Result<()> {
+    let idx = self
+      .column_families
+      .iter()
+      .position(|cf| cf.name == column_family)
+      .ok_or_else(|| {
+        io::Error::new(
+          io::ErrorKind::NotFound,
+          format!("column family {} does not exist", column_family),
+        )
+      })?;
+    if self.options.comparator.compare(start, end) != Ordering::Less {
+      return Ok(());
+    }
+
+    let mem_table = &self.column_families[idx].mem_table;
+    if mem_table.len() > 0 || !mem_table.range_tombstones().is_empty() {
+      self.flush_mem_tables()?;
+    }
+
+    let column_family = &self.column_families[idx];
+    let count = column_family.tables_to_compact(start, end)?;
+    if count == 0 {
+      return Ok(());
+    }
+    let number = self.next_table_number;
+    let (table_file, dropped_blob_indexes) = column_family.compact_tables(
+      count,
+      start,
+      end,
+      &self.dir,
+      number,
+      &self.options,
+      self.block_cache.clone(),
+    )?;
+    if table_file.is_some() {
+      self.next_table_number += 1;
+    }
+
+    let column_family = &mut self.column_families[idx];
+    let old_tables: Vec<TableFile> = column_family.tables.drain(..count).collect();
+    if let Some(table_file) = table_file {
+      column_family.tables.insert(0, table_file);
+    }
+    self.write_manifest()?;
+    for table_file in old_tables {
+      remove_file(table_path(&self.dir, table_file.number))?;
+    }
+    for blob_index in dropped_blob_indexes.iter() {
+      self.blob_store.mark_garbage(blob_index);
+    }
+    Ok(())
+  }
+
+  /// Gets the CompressionStats of the data blocks of every table in a column family, summed.
+  pub fn compression_stats(&self, cf: &str) -> Option<CompressionStats> {
+    let column_family = self.column_family(cf)?;
+    let mut stats = CompressionStats::default();
+    for table_file in column_family.tables.iter() {
+      stats.add(&table_file.compression);
+    }
+    Some(stats)
+  }
+
+  /// Gets the live and total bytes of every blob file.
+  pub fn blob_file_stats(&self) -> Vec<BlobFileStats> {
+    self.blob_store.stats()
+  }
+
+  /// Garbage collects the blob files whose fraction of garbage is at least the blob garbage
+  /// collection ratio of the Options.
+  ///
+  /// A value is live if the newest version of its key reads it, either directly or as the base of
+  /// newer merge operands. The live values of a blob file are written to the current blob file,
+  /// with their merge operands folded in, and the blob file is deleted. Returns the number of blob
+  /// files deleted.
+  ///
+  /// Fails without deleting the blob file if a live value cannot be read or merged. Nothing is
+  /// collected while a Snapshot is alive, as it may read any blob file.
+  pub fn collect_blob_garbage(&mut self) -> Result<usize, usize> {
+    if Arc::strong_count(&self.snapshots) > 1 {
+      return Ok(0);
+    }
+    let candidates = self
+      .blob_store
+      .garbage_collection_candidates(self.options.blob_garbage_collection_ratio);
+
+    for file_number in candidates.iter() {
+      let records = self.blob_store.records(*file_number).map_err(|_| 0usize)?;
+      for (key, blob_index) in records {
+        let read_options = ReadOptions::new();
+        let mut column_family = None;
+        for cf in self.column_families.iter() {
+          let entries = cf.get_entries(&key, &read_options).map_err(|_| 0usize)?;
+          let live = entries.last().is_some_and(|e| {
+            e.blob_index && e.value.as_deref().and_then(BlobIndex::decode) == Some(blob_index)
+          });
+          if live {
+            column_family = Some(cf);
+            break;
+          }
+        }
+        let (id, name) = match column_family {
+          Some(cf) => (cf.id, cf.name.clone()),
+          None => continue,
+        };
+
+        let entry = match self.get_cf(&name, &key).map_err(|_| 0usize)? {
+          Some(entry) => entry,
+          None => continue,
+        };
+        let relocated = self
+          .blob_store
+          .put(&key, entry.value())
+          .map_err(|_| 0usize)?;
+        let record = WALRecord::BlobIndex(WALEntry {
+          key,
+          value: Some(relocated.encode()),
+          timestamp: entry.timestamp(),
+          expires_at: None,
+          deleted: false,
+          column_family: id,
+        });
+        if self.wal.write_record(&record).is_err() {
+          return Err(0);
+        }
+        self.apply(&record);
+      }
+
+      if self.sync_wal(&WriteOptions::default()).is_err() {
+        return Err(0);
+      }
+      if self.blob_store.remove(*file_number).is_err() {
+        return Err(0);
+      }
+    }
+
+    Ok(candidates.len())
+  }
+
+  pub(crate) fn wal_mut(&mut self) -> &mut WAL {
+    &mut self.wal
+  }
+
+  /// Keeps the writes of a prepared Transaction, so they are carried over to the next WAL.
+  pub(crate) fn track_prepared(&mut self, id: u64, entries: &[WALEntry]) {
+    self.undecided_transactions.insert(id, entries.to_vec());
+  }
+
+  /// Forgets the writes of a Transaction that was committed or rolled back.
+  pub(crate) fn untrack_prepared(&mut self, id: u64) {
+    self.undecided_transactions.remove(&id);
+  }
+
+  /// Flushes the MemTables to tables once their total size reaches the MemTable size of the
+  /// Options.
+  pub(crate) fn flush_if_full(&mut self) -> io::Result<()> {
+    let size: usize = self
+      .column_families
+      .iter()
+      .map(|cf| cf.mem_table.size())
+      .sum();
+    if size < self.options.memtable_size {
+      return Ok(());
+    }
+    self.flush_mem_tables()
+  }
+
+  /// Flushes the MemTable of every column family to a new table, and starts a new WAL.
+  ///
+  /// The new WAL is written first, with the column families and the undecided Transactions of the
+  /// old WAL. The tables are then written and listed in the MANIFEST along with the new WAL, which
+  /// makes the old WAL obsolete. A crash at any point leaves either the old WAL or the new tables
+  /// in charge of the flushed writes.
+  fn flush_mem_tables(&mut self) -> io::Result<()> {
+    self.blob_store.sync()?;
+
+    let mut wal = WAL::new(&self.dir)?;
+    for column_family in self.column_families.iter() {
+      if column_family.id != DEFAULT_COLUMN_FAMILY_ID {
+        wal.create_column_family(column_family.id, &column_family.name)?;
+      }
+    }
+    for (id, entries) in self.undecided_transactions.iter() {
+      wal.prepare(*id, entries, now())?;
+    }
+    wal.sync()?;
+
+    let mut table_files = Vec::new();
+    for column_family in self.column_families.iter() {
+      let number = self.next_table_number;
+      let table_file =
+        column_family.write_table(&self.dir, number, &self.options, self.block_cache.clone())?;
+      if table_file.is_some() {
+        self.next_table_number += 1;
+      }
+      table_files.push(table_file);
+    }
+
+    // A MemTable that only held expired values leaves no table file, but is flushed all the same.
+    for (column_family, table_file) in self.column_families.iter_mut().zip(table_files) {
+      if let Some(table_file) = table_file {
+        column_family.tables.insert(0, table_file);
+      }
+      column_family.mem_table =
+        Arc::new(MemTable::with_comparator(self.options.comparator.clone()));
+    }
+
+    let old_wal = std::mem::replace(&mut self.wal, wal);
+    self.write_manifest()?;
+    let old_path = old_wal.path().to_owned();
+    drop(old_wal);
+    remove_file(old_path)
+  }
+
+  /// Checks that an external table file was written with the Comparator of the Database, and gets
+  /// its smallest and largest keys.
+  fn external_file_range(&self, path: &Path) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let invalid = |message: &str| {
+      io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("external file {} {}", path.display(), message),
+      )
+    };
+
+    let table = Arc::new(Table::open(path, &self.options, self.block_cache.clone())?);
+    let read_options = ReadOptions::new().verify_checksums(true).fill_cache(false);
+    let comparator = table.meta_block(COMPARATOR_META_BLOCK, &read_options)?;
+    if comparator.as_deref() != Some(self.options.comparator.name().as_bytes()) {
+      return Err(invalid(
+        "was not written with the Comparator of the Database",
+      ));
+    }
+
+    let mut iter = table.iter(read_options);
+    iter.seek_to_first();
+    if !iter.valid() {
+      return Err(iter.status().map_or_else(
+        || invalid("is empty"),
+        |e| io::Error::new(e.kind(), e.to_string()),
+      ));
+    }
+    let smallest = iter.key().to_vec();
+    iter.seek_to_last();
+    if !iter.valid() {
+      return Err(invalid("is corrupted"));
+    }
+    Ok((smallest, iter.key().to_vec()))
+  }
+
+  /// Writes the MANIFEST with the tables of every column family and the current WAL.
+  fn write_manifest(&self) -> io::Result<()> {
+    let mut tables = Vec::new();
+    let mut ingested = Vec::new();
+    for column_family in self.column_families.iter() {
+      for table_file in column_family.tables.iter().rev() {
+        tables.push((column_family.id, table_file.number));
+        if let Some(timestamp) = table_file.timestamp {
+          ingested.push((table_file.number, timestamp));
+        }
+      }
+    }
+    tables.sort_by_key(|(_, number)| *number);
+    ingested.sort_by_key(|(number, _)| *number);
+
+    Manifest {
+      next_table_number: self.next_table_number,
+      wal_number: self.wal.number(),
+      tables,
+      ingested,
+    }
+    .write(&self.dir)
+  }
+
+  /// Applies a Key-Value operation to the MemTable of its column family.
+  pub(crate) fn apply(&mut self, record: &WALRecord) {
+    let id = record.column_family();
+    if let Some(column_family) = self.column_families.iter_mut().find(|cf| Some(cf.id) == id) {
+      for blob_index in column_family.replaced_blob_indexes(record) {
+        self.blob_store.mark_garbage(&blob_index);
+      }
+      column_family.apply(record);
+    }
+  }
+
+  /// Makes the writes to the WAL durable according to the SyncMode, or syncs them to disk if the
+  /// WriteOptions ask for it.
+  fn sync_wal(&mut self, write_options: &WriteOptions) -> io::Result<()> {
+    let sync_mode = if write_options.sync {
+      SyncMode::Fsync
+    } else {
+      self.options.sync_mode
+    };
+
+    match sync_mode {
+      SyncMode::Buffered => Ok(()),
+      SyncMode::Flush => self.wal.flush(),
+      SyncMode::Fsync => {
+        self.blob_store.sync()?;
+        self.wal.sync()
+      }
+    }
+  }
+
+  /// Writes a large value to a blob file, replacing the Key-Value operation with a blob index
+  /// operation that points to the value.
+  ///
+  /// Only plain sets of values of at least the minimum blob size are separated, and only if blob
+  /// files are enabled.
+  fn separate_value(&mut self, record: WALRecord) -> io::Result<WALRecord> {
+    match record {
+      WALRecord::Entry(mut entry)
+        if self.options.enable_blob_files
+          && !entry.deleted
+          && entry.expires_at.is_none()
+          && entry
+            .value
+            .as_ref()
+            .is_some_and(|v| v.len() >= self.options.min_blob_size) =>
+      {
+        let blob_index = self
+          .blob_store
+          .put(&entry.key, entry.value.as_ref().unwrap())?;
+        entry.value = Some(blob_index.encode());
+        Ok(WALRecord::BlobIndex(entry))
+      }
+      record => Ok(record),
+    }
+  }
+
+  /// Gets the value of a MemTable entry, reading it from its blob file if it was separated.
+  fn entry_value(&self, entry: &MemTableEntry) -> io::Result<Vec<u8>> {
+    let value = entry.value.as_ref().unwrap();
+    if !entry.blob_index {
+      return Ok(value.clone());
+    }
+    let blob_index = BlobIndex::decode(value)
+      .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "blob index is corrupted"))?;
+    self.blob_store.get(&blob_index)
+  }
+
+  /// Gets a column family by name.
+  fn column_family(&self, name: &str) -> Option<&ColumnFamily> {
+    self.column_families.iter().find(|cf| cf.name == name)
+  }
+
+  /// Gets a column family by name, with the column family that reads with ReadOptions see: its copy
+  /// in the Snapshot of the ReadOptions, or else the column family itself.
+  ///
+  /// Returns `None` if the column family does not exist, or did not exist when the Snapshot was
+  /// taken.
+  fn read_column_family<'a: 'b, 'b>(
+    &'a self,
+    read_options: &'b ReadOptions,
+    name: &str,
+  ) -> Option<(&'a ColumnFamily, &'b ColumnFamily)> {
+    let column_family = self.column_family(name)?;
+    match read_options.snapshot.as_ref() {
+      Some(snapshot) => Some((column_family, snapshot.column_family(column_family.id)?)),
+      None => Some((column_family, column_family)),
+    }
+  }
+
+  /// Appends a Key-Value operation to the WAL and applies it to the MemTable of its column family.
+  fn write_record(&mut self, record: WALRecord) -> Result<usize, usize> {
+    let record = self.separate_value(record).map_err(|_| 0usize)?;
+    if self.wal.write_record(&record).is_err() {
+      return Err(0);
+    }
+    if self.sync_wal(&WriteOptions::default()).is_err() {
+      return Err(0);
+    }
+
+    self.apply(&record);
+    if self.flush_if_full().is_err() {
+      return Err(0);
+    }
+
+    Ok(1)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::block_cache::BlockCache;
+  use crate::column_family::ColumnFamilyOptions;
+  use crate::comparator::{BytewiseComparator, ReverseBytewiseComparator};
+  use crate::compression::LzCompressor;
+  use crate::database::Database;
+  use crate::merge_operator::{StringAppendOperator, U64AddOperator};
+  use crate::options::{IngestOptions, MmapAdvice, Options, ReadOptions, SyncMode, WriteOptions};
+  use crate::options_file::read_options_file;
+  use crate::table::{Table, COMPRESSION_META_BLOCK};
+  use crate::table_file_writer::TableFileWriter;
+  use crate::write_batch::WriteBatch;
+  use rand::Rng;
+  use std::fs::{create_dir, read_dir, remove_dir_all, remove_file, OpenOptions};
+  use std::io::prelude::*;
+  use std::io::ErrorKind;
+  use std::path::PathBuf;
+  use std::sync::Arc;
+  use std::thread::sleep;
+  use std::time::Duration;
+
+  #[test]
+  fn test_compare_and_set() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let mut db = Database::new(dir.to_str().unwrap());
+
+    assert!(db.put_if_absent(b"Apple", b"Apple Smoothie").unwrap());
+    assert!(!db.put_if_absent(b"Apple", b"Apple Pie").unwrap());
+    assert_eq!(
+      db.get(b"Apple").unwrap().unwrap().value(),
+      b"Apple Smoothie"
+    );
+
+    let res = db.compare_and_set(b"Apple", Some(b"Apple Pie"), Some(b"Apple Juice"));
+    assert!(!res.unwrap());
+    let res = db.compare_and_set(b"Apple", Some(b"Apple Smoothie"), Some(b"Apple Juice"));
+    assert!(res.unwrap());
+    assert_eq!(db.get(b"Apple").unwrap().unwrap().value(), b"Apple Juice");
+
+    assert!(db
+      .compare_and_set(b"Apple", Some(b"Apple Juice"), None)
+      .unwrap());
+    assert!(db.get(b"Apple").unwrap().is_none());
+    assert!(db.put_if_absent(b"Apple", b"Apple Cider").unwrap());
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_merge() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let mut db = Database::new(dir.to_str().unwrap());
+    assert!(db.merge(b"Count", &1u64.to_le_bytes()).is_err());
+
+    db.set_merge_operator(Box::new(U64AddOperator));
+    db.merge(b"Count", &1u64.to_le_bytes()).unwrap();
+    db.merge(b"Count", &2u64.to_le_bytes()).unwrap();
+    assert_eq!(
+      db.get(b"Count").unwrap().unwrap().value(),
+      3u64.to_le_bytes()
+    );
+
+    db.delete(b"Count").unwrap();
+    db.merge(b"Count", &4u64.to_le_bytes()).unwrap();
+    assert_eq!(
+      db.get(b"Count").unwrap().unwrap().value(),
+      4u64.to_le_bytes()
+    );
+    db.set(b"Apple", b"Apple Smoothie").unwrap();
+    drop(db);
+
+    // Reading merge operands fails until the MergeOperator is set again.
+    let mut db = Database::new(dir.to_str().unwrap());
+    assert!(db.get(b"Count").is_err());
+    assert!(db.multi_get(&[b"Count"])[0].is_err());
+    let mut iter = db.iter();
+    iter.seek(b"Count");
+    assert!(!iter.valid());
+    assert!(iter.status().is_some());
+    drop(iter);
+    assert_eq!(
+      db.get(b"Apple").unwrap().unwrap().value(),
+      b"Apple Smoothie"
+    );
+
+    db.set_merge_operator(Box::new(U64AddOperator));
+    assert_eq!(
+      db.get(b"Count").unwrap().unwrap().value(),
+      4u64.to_le_bytes()
+    );
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_set_with_ttl() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let mut db = Database::new(dir.to_str().unwrap());
+    db.set(b"Apple", b"Apple Smoothie").unwrap();
+    db.set_with_ttl(b"Apple", b"Apple Pie", Duration::from_millis(10))
+      .unwrap();
+    db.set_with_ttl(b"Lime", b"Lime Smoothie", Duration::from_secs(3600))
+      .unwrap();
+    assert_eq!(db.get(b"Apple").unwrap().unwrap().value(), b"Apple Pie");
+
+    let ttl = Duration::from_millis(10);
+    assert!(db
+      .set_with_ttl_cf("sessions", b"Adam", b"Token", ttl)
+      .is_err());
+    db.create_column_family("sessions", ColumnFamilyOptions::default())
+      .unwrap();
+    db.set_with_ttl_cf("sessions", b"Adam", b"Token", ttl)
+      .unwrap();
+    db.set_with_ttl_cf("sessions", b"Eve", b"Token", Duration::from_secs(3600))
+      .unwrap();
+    assert_eq!(
+      db.get_cf("sessions", b"Adam").unwrap().unwrap().value(),
+      b"Token"
+    );
+    assert!(db.get(b"Adam").unwrap().is_none());
+
+    sleep(Duration::from_millis(20));
+    assert!(db.get(b"Apple").unwrap().is_none());
+    assert!(db.get_cf("sessions", b"Adam").unwrap().is_none());
+    assert!(db.put_if_absent(b"Apple", b"Apple Juice").unwrap());
+    assert_eq!(db.get(b"Lime").unwrap().unwrap().value(), b"Lime Smoothie");
+    drop(db);
+
+    let db = Database::new(dir.to_str().unwrap());
+    assert!(db.get_cf("sessions", b"Adam").unwrap().is_none());
+    assert_eq!(
+      db.get_cf("sessions", b"Eve").unwrap().unwrap().value(),
+      b"Token"
+    );
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_column_families() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let mut db = Database::new(dir.to_str().unwrap());
+    assert!(db.set_cf("users", b"Adam", b"Admin").is_err());
+
+    let options = ColumnFamilyOptions {
+      merge_operator: Some(Box::new(U64AddOperator)),
+    };
+    assert_eq!(db.create_column_family("users", options), Ok(1));
+    assert_eq!(
+      db.create_column_family("sessions", ColumnFamilyOptions::default()),
+      Ok(1)
+    );
+    assert_eq!(db.column_families(), vec!["default", "users", "sessions"]);
+    assert!(db
+      .create_column_family("users", ColumnFamilyOptions::default())
+      .is_err());
+    assert_eq!(db.merge_cf("users", b"Visits", &1u64.to_le_bytes()), Ok(1));
+
+    let mut batch = WriteBatch::new();
+    batch.set(b"Adam", b"Default");
+    batch.set_cf("users", b"Adam", b"Admin");
+    batch.merge_cf("users", b"Logins", &1u64.to_le_bytes());
+    batch.set_cf("sessions", b"Adam", b"Session");
+    assert_eq!(db.write(batch), Ok(4));
+
+    let mut batch = WriteBatch::new();
+    batch.set_cf("sessions", b"Lime", b"Session");
+    batch.merge_cf("sessions", b"Logins", &1u64.to_le_bytes());
+    assert!(db.write(batch).is_err());
+    assert!(db.get_cf("sessions", b"Lime").unwrap().is_none());
+
+    assert_eq!(db.get(b"Adam").unwrap().unwrap().value(), b"Default");
+    assert_eq!(
+      db.get_cf("users", b"Adam").unwrap().unwrap().value(),
+      b"Admin"
+    );
+    assert_eq!(db.drop_column_family("sessions"), Ok(1));
+    assert!(db.get_cf("sessions", b"Adam").unwrap().is_none());
+    drop(db);
+
+    let mut db = Database::new(dir.to_str().unwrap());
+    assert_eq!(db.column_families(), vec!["default", "users"]);
+    let options = ColumnFamilyOptions {
+      merge_operator: Some(Box::new(U64AddOperator)),
+    };
+    assert!(db
+      .set_column_family_options("sessions", ColumnFamilyOptions::default())
+      .is_err());
+    assert_eq!(db.set_column_family_options("users", options), Ok(1));
+    assert_eq!(
+      db.get_cf("users", b"Adam").unwrap().unwrap().value(),
+      b"Admin"
+    );
+    assert_eq!(
+      db.get_cf("users", b"Logins").unwrap().unwrap().value(),
+      1u64.to_le_bytes()
+    );
+    assert!(db.drop_column_family("default").is_err());
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_comparator() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    {
+      let mut db =
+        Database::with_comparator(dir.to_str().unwrap(), Arc::new(ReverseBytewiseComparator))
+          .unwrap();
+      db.set(b"Apple", b"Apple Smoothie").unwrap();
+      db.set(b"Lime", b"Lime Smoothie").unwrap();
+      assert_eq!(db.delete_range(b"Apple", b"Lime"), Ok(0));
+      assert_eq!(db.delete_range(b"Lime", b"Apple"), Ok(1));
+      assert!(db.get(b"Lime").unwrap().is_none());
+      assert!(db.get(b"Apple").unwrap().is_some());
+    }
+
+    assert!(
+      Database::with_comparator(dir.to_str().unwrap(), Arc::new(BytewiseComparator)).is_err()
+    );
+
+    let db = Database::with_comparator(dir.to_str().unwrap(), Arc::new(ReverseBytewiseComparator))
+      .unwrap();
+    assert!(db.get(b"Lime").unwrap().is_none());
+    assert_eq!(
+      db.get(b"Apple").unwrap().unwrap().value(),
+      b"Apple Smoothie"
+    );
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_open_options() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+
+    assert!(Database::open(path, Options::new().create_if_missing(false)).is_err());
+
+    {
+      let options = Options::new().sync_mode(SyncMode::Fsync);
+      let mut db = Database::open(path, options).unwrap();
+      db.set(b"Lime", b"Lime Smoothie").unwrap();
+
+      let mut batch = WriteBatch::new();
+      batch.set(b"Apple", b"Apple Smoothie");
+      db.write_opt(batch, &WriteOptions::new().sync(true))
+        .unwrap();
+    }
+
+    assert!(Database::open(path, Options::new().error_if_exists(true)).is_err());
+
+    let db = Database::open(path, Options::new().create_if_missing(false)).unwrap();
+    let read_options = ReadOptions::new().fill_cache(false);
+    assert_eq!(
+      db.get_opt(&read_options, b"Lime").unwrap().unwrap().value(),
+      b"Lime Smoothie"
+    );
+    assert_eq!(
+      db.get(b"Apple").unwrap().unwrap().value(),
+      b"Apple Smoothie"
+    );
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_paranoid_checks() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+    let path = dir.to_str().unwrap();
+
+    {
+      let mut db = Database::new(path);
+      db.set(b"Lime", b"Lime Smoothie").unwrap();
+    }
+
+    let wal_path = read_dir(&dir)
+      .unwrap()
+      .map(|f| f.unwrap().path())
+      .find(|p| p.extension().is_some_and(|e| e == "wal"))
+      .unwrap();
+    let mut file = OpenOptions::new().append(true).open(&wal_path).unwrap();
+    file.write_all(&[4, 0, 0]).unwrap();
+
+    let options = Options::new().paranoid_checks(true).memtable_size(1024);
+    assert!(Database::open(path, options).is_err());
+    // The failed open did not persist its Options.
+    let persisted = read_options_file(&dir).unwrap().unwrap();
+    assert_eq!(persisted["paranoid_checks"], "false");
+    assert_eq!(persisted["memtable_size"], (4 * 1024 * 1024).to_string());
+
+    let db = Database::open(path, Options::new()).unwrap();
+    assert_eq!(db.get(b"Lime").unwrap().unwrap().value(), b"Lime Smoothie");
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_blob_files() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+    let options = Options::new()
+      .enable_blob_files(true)
+      .min_blob_size(100)
+      .blob_file_size(1000);
+
+    {
+      let mut db = Database::open(path, options.clone()).unwrap();
+      db.set_merge_operator(Box::new(U64AddOperator));
+      db.set(b"Lime", b"Lime Smoothie").unwrap();
+      db.set(b"Counter", &[7; 200]).unwrap();
+      db.merge(b"Counter", &1u64.to_le_bytes()).unwrap();
+      for i in 0..10u8 {
+        db.set(&[i], &[i; 300]).unwrap();
+      }
+      assert_eq!(db.get(b"Lime").unwrap().unwrap().value(), b"Lime Smoothie");
+      assert_eq!(db.get(&[3]).unwrap().unwrap().value(), &[3; 300][..]);
+      assert_eq!(db.blob_file_stats().len(), 3);
+
+      for i in 0..6u8 {
+        db.delete(&[i]).unwrap();
+      }
+      db.set(&[6], &[6; 300]).unwrap();
+      let stats = db.blob_file_stats();
+      assert!(stats[0].garbage_ratio() > 0.5);
+      assert_eq!(stats[1].garbage_ratio(), 1.0);
+      assert_eq!(stats[2].garbage_ratio(), 0.0);
+    }
+
+    let mut db = Database::open(path, options).unwrap();
+    db.set_merge_operator(Box::new(U64AddOperator));
+    let counter = db.get(b"Counter").unwrap().unwrap().value().to_vec();
+    assert_eq!(db.collect_blob_garbage(), Ok(2));
+    assert!(db.blob_file_stats().iter().all(|s| s.garbage_ratio() < 0.5));
+
+    assert!(db.get(&[0]).unwrap().is_none());
+    assert_eq!(db.get(&[6]).unwrap().unwrap().value(), &[6; 300][..]);
+    assert_eq!(db.get(&[9]).unwrap().unwrap().value(), &[9; 300][..]);
+    assert_eq!(db.get(b"Counter").unwrap().unwrap().value(), &counter[..]);
+    drop(db);
+
+    // Values in blob files stay readable once blob files are disabled.
+    let db = Database::open(path, Options::new()).unwrap();
+    assert_eq!(db.get(&[9]).unwrap().unwrap().value(), &[9; 300][..]);
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_table_reads() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+
+    let options = Options::new()
+      .memtable_size(2048)
+      .use_mmap_reads(true)
+      .mmap_advice(MmapAdvice::Random);
+    let mut db = Database::open(path, options.clone()).unwrap();
+    db.set_merge_operator(Box::new(U64AddOperator));
+    for i in 0..500u32 {
+      db.set(&i.to_be_bytes(), format!("Smoothie {}", i).as_bytes())
+        .unwrap();
+    }
+    db.merge(b"Count", &1u64.to_le_bytes()).unwrap();
+    db.delete(&7u32.to_be_bytes()).unwrap();
+    db.delete_range(&100u32.to_be_bytes(), &200u32.to_be_bytes())
+      .unwrap();
+    for i in 500..1000u32 {
+      db.set(&i.to_be_bytes(), format!("Smoothie {}", i).as_bytes())
+        .unwrap();
+    }
+    db.merge(b"Count", &2u64.to_le_bytes()).unwrap();
+
+    let tables = db.column_families[0].tables.len();
+    assert!(tables > 1);
+    assert!(db.column_families[0]
+      .tables
+      .iter()
+      .all(|t| t.table.is_mmap()));
+    let wal_files = read_dir(&dir)
+      .unwrap()
+      .filter(|f| {
+        let path = f.as_ref().unwrap().path();
+        path.extension().is_some_and(|e| e == "wal")
+      })
+      .count();
+    assert_eq!(wal_files, 1);
+
+    let check = |db: &Database| {
+      assert_eq!(
+        db.get(&0u32.to_be_bytes()).unwrap().unwrap().value(),
+        b"Smoothie 0"
+      );
+      assert!(db.get(&7u32.to_be_bytes()).unwrap().is_none());
+      assert!(db.get(&150u32.to_be_bytes()).unwrap().is_none());
+      assert_eq!(
+        db.get(&999u32.to_be_bytes()).unwrap().unwrap().value(),
+        b"Smoothie 999"
+      );
+      assert_eq!(
+        db.get(b"Count").unwrap().unwrap().value(),
+        3u64.to_le_bytes()
+      );
+    };
+    check(&db);
+    drop(db);
+
+    let mut db = Database::open(path, options.use_mmap_reads(false)).unwrap();
+    db.set_merge_operator(Box::new(U64AddOperator));
+    assert_eq!(db.column_families[0].tables.len(), tables);
+    assert!(!db.column_families[0].tables[0].table.is_mmap());
+    check(&db);
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_multi_get() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+
+    let mut db = Database::open(path, Options::new().memtable_size(2048)).unwrap();
+    db.set_merge_operator(Box::new(U64AddOperator));
+    for i in 0..1000u32 {
+      db.set(&i.to_be_bytes(), format!("Smoothie {}", i).as_bytes())
+        .unwrap();
+    }
+    db.merge(b"Count", &1u64.to_le_bytes()).unwrap();
+    db.delete(&7u32.to_be_bytes()).unwrap();
+    db.delete_range(&100u32.to_be_bytes(), &200u32.to_be_bytes())
+      .unwrap();
+    for i in 0..1000u32 {
+      db.set(&(i * 2).to_be_bytes(), b"Lime").unwrap();
+    }
+    db.merge(b"Count", &2u64.to_le_bytes()).unwrap();
+    db.set(&3u32.to_be_bytes(), b"Mango").unwrap();
+    assert!(db.column_families[0].tables.len() > 1);
+
+    let mut keys: Vec<Vec<u8>> = vec![
+      b"Count".to_vec(),
+      999u32.to_be_bytes().to_vec(),
+      7u32.to_be_bytes().to_vec(),
+      150u32.to_be_bytes().to_vec(),
+      3u32.to_be_bytes().to_vec(),
+      2000u32.to_be_bytes().to_vec(),
+    ];
+    keys.extend((0..1000u32).step_by(9).map(|i| i.to_be_bytes().to_vec()));
+    let keys: Vec<&[u8]> = keys.iter().map(|k| &k[..]).collect();
+    let entries = db.multi_get(&keys);
+    assert_eq!(entries.len(), keys.len());
+    for (key, entry) in keys.iter().zip(entries) {
+      let entry = entry.unwrap();
+      let expected = db.get(key).unwrap();
+      assert_eq!(
+        entry.as_ref().map(|e| e.value()),
+        expected.as_ref().map(|e| e.value())
+      );
+    }
+
+    let entries = db.multi_get(&keys[..5]);
+    assert_eq!(
+      entries[0].as_ref().unwrap().as_ref().unwrap().value(),
+      3u64.to_le_bytes()
+    );
+    assert_eq!(
+      entries[1].as_ref().unwrap().as_ref().unwrap().value(),
+      b"Smoothie 999"
+    );
+    assert!(entries[2].as_ref().unwrap().is_none());
+    assert_eq!(
+      entries[3].as_ref().unwrap().as_ref().unwrap().value(),
+      b"Lime"
+    );
+    assert_eq!(
+      entries[4].as_ref().unwrap().as_ref().unwrap().value(),
+      b"Mango"
+    );
+    assert!(db
+      .multi_get_cf_opt(&ReadOptions::new(), "Missing", &keys)
+      .iter()
+      .all(|e| e.as_ref().unwrap().is_none()));
+    drop(db);
+
+    // A corrupted data block fails the reads of its keys, instead of panicking.
+    let mut db = Database::open(path, Options::new()).unwrap();
+    db.set(b"Kiwi", b"Kiwi Smoothie").unwrap();
+    db.flush(true).unwrap();
+    let number = db.column_families[0].tables[0].number;
+    let mut file = OpenOptions::new()
+      .write(true)
+      .open(dir.join(format!("{}.sst", number)))
+      .unwrap();
+    file.write_all(b"Lemon").unwrap();
+    file.sync_all().unwrap();
+    let read_options = ReadOptions::new().verify_checksums(true);
+    assert!(db.get_opt(&read_options, b"Kiwi").is_err());
+    assert!(db
+      .multi_get_cf_opt(&read_options, "default", &[b"Kiwi"])
+      .remove(0)
+      .is_err());
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_checkpoint() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let checkpoint_dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+    let checkpoint_path = checkpoint_dir.to_str().unwrap();
+
+    let options = Options::new()
+      .memtable_size(2048)
+      .enable_blob_files(true)
+      .min_blob_size(100);
+    let mut db = Database::open(path, options.clone()).unwrap();
+    for i in 0..300u32 {
+      db.set(&i.to_be_bytes(), format!("Smoothie {}", i).as_bytes())
+        .unwrap();
+    }
+    db.set(b"Lime", &[7; 200]).unwrap();
+    db.set(b"Apple", b"Apple Smoothie").unwrap();
+
+    db.checkpoint(checkpoint_path).unwrap();
+    assert!(db.checkpoint(checkpoint_path).is_err());
+
+    db.set(b"Apple", b"Apple Pie").unwrap();
+    db.delete(&0u32.to_be_bytes()).unwrap();
+    for i in 300..600u32 {
+      db.set(&i.to_be_bytes(), b"Mango").unwrap();
+    }
+
+    let checkpoint = Database::open(checkpoint_path, options).unwrap();
+    assert_eq!(
+      checkpoint
+        .get(&0u32.to_be_bytes())
+        .unwrap()
+        .unwrap()
+        .value(),
+      b"Smoothie 0"
+    );
+    assert_eq!(
+      checkpoint
+        .get(&299u32.to_be_bytes())
+        .unwrap()
+        .unwrap()
+        .value(),
+      b"Smoothie 299"
+    );
+    assert!(checkpoint.get(&300u32.to_be_bytes()).unwrap().is_none());
+    assert_eq!(checkpoint.get(b"Lime").unwrap().unwrap().value(), [7; 200]);
+    assert_eq!(
+      checkpoint.get(b"Apple").unwrap().unwrap().value(),
+      b"Apple Smoothie"
+    );
+    assert!(!checkpoint.column_families[0].tables.is_empty());
+
+    assert_eq!(db.get(b"Apple").unwrap().unwrap().value(), b"Apple Pie");
+    assert!(db.get(&0u32.to_be_bytes()).unwrap().is_none());
+
+    remove_dir_all(&dir).unwrap();
+    remove_dir_all(&checkpoint_dir).unwrap();
+  }
+
+  #[test]
+  fn test_ingest_external_files() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+    create_dir(&dir).unwrap();
+    let external_path = format!("./{}.sst", rng.gen::<u32>());
+    let reversed_path = format!("./{}.sst", rng.gen::<u32>());
+
+    let options = Options::new();
+    let mut writer = TableFileWriter::create(&external_path, &options).unwrap();
+    writer.put(b"Apple", b"Apple Pie").unwrap();
+    writer.merge(b"Count", &2u64.to_le_bytes()).unwrap();
+    writer.delete(b"Lime").unwrap();
+    for i in 0..1000u32 {
+      writer
+        .put(format!("Row {:04}", i).as_bytes(), b"Mango")
+        .unwrap();
+    }
+    assert!(writer.put(b"Apple", b"Apple Juice").is_err());
+    assert_eq!(writer.num_entries(), 1003);
+    writer.finish().unwrap();
+
+    let reversed = Options::new().comparator(Arc::new(ReverseBytewiseComparator));
+    let mut writer = TableFileWriter::create(&reversed_path, &reversed).unwrap();
+    writer.put(b"Cherry", b"Cherry Pie").unwrap();
+    writer.finish().unwrap();
+
+    let mut db = Database::open(path, options.clone()).unwrap();
+    db.set_merge_operator(Box::new(U64AddOperator));
+    db.set(b"Apple", b"Apple Smoothie").unwrap();
+    db.set(b"Zucchini", b"Zucchini Bread").unwrap();
+
+    assert!(db.ingest_external_files(&[reversed_path.as_str()]).is_err());
+    assert!(db
+      .ingest_external_files_cf("Missing", &[external_path.as_str()])
+      .is_err());
+
+    // Files that overlap each other are rejected, and nothing is ingested.
+    let err = db
+      .ingest_external_files(&[external_path.as_str(), external_path.as_str()])
+      .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert!(db.column_families[0].tables.is_empty());
+    assert!(!read_dir(&dir).unwrap().any(|f| {
+      let path = f.unwrap().path();
+      path.extension().is_some_and(|e| e == "sst")
+    }));
+
+    // The file overlaps Apple in the MemTable, which is flushed so the file is newer than it.
+    db.ingest_external_files(&[external_path.as_str()]).unwrap();
+    assert_eq!(db.column_families[0].mem_table.len(), 0);
+    assert_eq!(db.column_families[0].tables.len(), 2);
+    assert_eq!(db.get(b"Apple").unwrap().unwrap().value(), b"Apple Pie");
+    assert!(db.get(b"Lime").unwrap().is_none());
+    assert_eq!(
+      db.get(b"Count").unwrap().unwrap().value(),
+      2u64.to_le_bytes()
+    );
+    assert_eq!(db.get(b"Row 0999").unwrap().unwrap().value(), b"Mango");
+    assert_eq!(
+      db.get(b"Zucchini").unwrap().unwrap().value(),
+      b"Zucchini Bread"
+    );
+    assert!(db.get(b"Apple").unwrap().unwrap().timestamp() > 0);
+    let timestamp = db.get(b"Row 0000").unwrap().unwrap().timestamp();
+
+    // The file was copied, so the next file can be written at the same path, and overwrites the
+    // keys of the tables when it is ingested.
+    let mut writer = TableFileWriter::create(&external_path, &options).unwrap();
+    writer.put(b"Apple", b"Apple Tart").unwrap();
+    writer.merge(b"Count", &3u64.to_le_bytes()).unwrap();
+    writer.finish().unwrap();
+    assert_eq!(db.get(b"Apple").unwrap().unwrap().value(), b"Apple Pie");
+    let ingest_options = IngestOptions::new().move_files(true);
+    db.ingest_external_files_cf_opt(&ingest_options, "default", &[external_path.as_str()])
+      .unwrap();
+    assert!(!PathBuf::from(&external_path).exists());
+    assert_eq!(db.get(b"Apple").unwrap().unwrap().value(), b"Apple Tart");
+    assert_eq!(
+      db.get(b"Count").unwrap().unwrap().value(),
+      5u64.to_le_bytes()
+    );
+
+    db.set(b"Apple", b"Apple Juice").unwrap();
+    drop(db);
+    let mut db = Database::open(path, options).unwrap();
+    db.set_merge_operator(Box::new(U64AddOperator));
+    assert_eq!(db.get(b"Apple").unwrap().unwrap().value(), b"Apple Juice");
+    assert_eq!(db.get(b"Row 0000").unwrap().unwrap().timestamp(), timestamp);
+    let mut iter = db.iter();
+    iter.seek(b"Row");
+    assert_eq!(iter.key(), b"Row 0000");
+
+    drop(iter);
+    remove_file(&reversed_path).unwrap();
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_compact_range() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+    let table_bytes = |dir: &PathBuf| -> u64 {
+      read_dir(dir)
+        .unwrap()
+        .map(|f| f.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "sst"))
+        .map(|p| p.metadata().unwrap().len())
+        .sum()
+    };
+
+    let options = Options::new().memtable_size(2048);
+    let mut db = Database::open(path, options.clone()).unwrap();
+    db.set_merge_operator(Box::new(U64AddOperator));
+    db.merge(b"Count", &1u64.to_le_bytes()).unwrap();
+    for i in 0..500u32 {
+      db.set(format!("Row {:04}", i).as_bytes(), b"Mango")
+        .unwrap();
+    }
+    for i in 100..400u32 {
+      db.delete(format!("Row {:04}", i).as_bytes()).unwrap();
+    }
+    db.delete_range(b"Row 0450", b"Row 0470").unwrap();
+    db.merge(b"Count", &2u64.to_le_bytes()).unwrap();
+
+    db.flush(true).unwrap();
+    assert_eq!(db.column_families[0].mem_table.len(), 0);
+    let tables = db.column_families[0].tables.len();
+    assert!(tables > 1);
+    db.flush(false).unwrap();
+    assert_eq!(db.column_families[0].tables.len(), tables);
+
+    let bytes = table_bytes(&dir);
+    assert!(db.compact_range_cf("Missing", b"A", b"Z").is_err());
+    db.compact_range(b"Z", b"A").unwrap();
+    assert_eq!(db.column_families[0].tables.len(), tables);
+    db.compact_range(b"A", b"Row 0460").unwrap();
+    assert_eq!(db.column_families[0].tables.len(), 1);
+    assert!(table_bytes(&dir) < bytes);
+
+    let check = |db: &Database| {
+      assert_eq!(
+        db.get(b"Count").unwrap().unwrap().value(),
+        3u64.to_le_bytes()
+      );
+      assert_eq!(db.get(b"Row 0099").unwrap().unwrap().value(), b"Mango");
+      assert!(db.get(b"Row 0100").unwrap().is_none());
+      assert!(db.get(b"Row 0399").unwrap().is_none());
+      assert_eq!(db.get(b"Row 0400").unwrap().unwrap().value(), b"Mango");
+      // The range tombstone reaches past the compacted range, so it is kept.
+      assert!(db.get(b"Row 0465").unwrap().is_none());
+      assert_eq!(db.get(b"Row 0499").unwrap().unwrap().value(), b"Mango");
+      let mut iter = db.iter();
+      iter.seek(b"Row 0100");
+      assert_eq!(iter.key(), b"Row 0400");
+    };
+    check(&db);
+
+    drop(db);
+    let mut db = Database::open(path, options).unwrap();
+    db.set_merge_operator(Box::new(U64AddOperator));
+    check(&db);
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_shared_block_cache() {
+    let mut rng = rand::thread_rng();
+    let dir_a = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let dir_b = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+
+    let block_cache = Arc::new(BlockCache::new(1024 * 1024));
+    let options = Options::new().block_cache(block_cache.clone());
+    let mut a = Database::open(dir_a.to_str().unwrap(), options.clone()).unwrap();
+    let mut b = Database::open(dir_b.to_str().unwrap(), options).unwrap();
+    assert!(Arc::ptr_eq(a.block_cache(), b.block_cache()));
+
+    // Both Databases flush their first table with the same table number.
+    a.set(b"Lime", b"Lime from a").unwrap();
+    b.set(b"Lime", b"Lime from b").unwrap();
+    a.flush(true).unwrap();
+    b.flush(true).unwrap();
+    assert_eq!(a.get(b"Lime").unwrap().unwrap().value(), b"Lime from a");
+    assert_eq!(b.get(b"Lime").unwrap().unwrap().value(), b"Lime from b");
+    assert!(block_cache.usage() > 0);
+
+    remove_dir_all(&dir_a).unwrap();
+    remove_dir_all(&dir_b).unwrap();
+  }
+
+  #[test]
+  fn test_compression_stats() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+
+    let options = Options::new()
+      .compression(Arc::new(LzCompressor))
+      .compression_dictionary_size(1024);
+    let mut db = Database::open(path, options.clone()).unwrap();
+    assert_eq!(
+      db.compression_stats("default").unwrap().uncompressed_bytes,
+      0
+    );
+    assert!(db.compression_stats("Fruits").is_none());
+
+    for i in 0..2000u32 {
+      db.set(
+        &i.to_be_bytes(),
+        format!("Smoothie of mango, banana and strawberry {}", i % 37).as_bytes(),
+      )
+      .unwrap();
+    }
+    db.flush(true).unwrap();
+    let stats = db.compression_stats("default").unwrap();
+    assert!(stats.uncompressed_bytes > 0);
+    assert!(stats.ratio() > 1.0);
+    assert!(stats.sampled_bytes > 0);
+    assert!(stats.sampled_bytes < stats.uncompressed_bytes);
+    drop(db);
+
+    let db = Database::open(path, options).unwrap();
+    assert_eq!(db.compression_stats("default").unwrap(), stats);
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_compaction_compression() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+    let compressor = |db: &Database| {
+      let table = &db.column_families[0].tables[0].table;
+      let read_options = ReadOptions::new();
+      table
+        .meta_block(COMPRESSION_META_BLOCK, &read_options)
+        .unwrap()
+    };
+
+    let options = Options::new().compaction_compression(Arc::new(LzCompressor));
+    let mut db = Database::open(path, options).unwrap();
+    for i in 0..2000u32 {
+      db.set(
+        &i.to_be_bytes(),
+        format!("Smoothie of mango, banana and strawberry {}", i % 37).as_bytes(),
+      )
+      .unwrap();
+    }
+    db.flush(true).unwrap();
+    assert_eq!(compressor(&db).unwrap(), b"NoCompressor");
+    assert!(db.compression_stats("default").unwrap().ratio() <= 1.0);
+
+    db.compact_range(&0u32.to_be_bytes(), &2000u32.to_be_bytes())
+      .unwrap();
+    assert_eq!(compressor(&db).unwrap(), b"LzCompressor");
+    assert!(db.compression_stats("default").unwrap().ratio() > 1.0);
+    drop(db);
+
+    // The compacted table stays readable without the compaction Compressor.
+    let db = Database::open(path, Options::new()).unwrap();
+    let value = db.get(&1000u32.to_be_bytes()).unwrap().unwrap();
+    assert_eq!(
+      value.value(),
+      format!("Smoothie of mango, banana and strawberry {}", 1000 % 37).as_bytes()
+    );
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_blob_garbage_under_merge_operands() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+    let options = Options::new().enable_blob_files(true).min_blob_size(100);
+
+    {
+      let mut db = Database::open(path, options.clone()).unwrap();
+      db.set_merge_operator(Box::new(StringAppendOperator::new(b",")));
+      db.set(b"Mango", &[1; 300]).unwrap();
+      db.set(b"Lemon", &[2; 300]).unwrap();
+      db.delete(b"Lemon").unwrap();
+      db.flush(true).unwrap();
+      // The newest entry of Mango only holds an operand, and its base value is in the blob file.
+      db.merge(b"Mango", b"Ice").unwrap();
+    }
+
+    let mut expected = vec![1; 300];
+    expected.extend_from_slice(b",Ice");
+    let mut db = Database::open(path, options).unwrap();
+    db.set_merge_operator(Box::new(StringAppendOperator::new(b",")));
+    assert_eq!(db.collect_blob_garbage(), Ok(1));
+    assert_eq!(db.get(b"Mango").unwrap().unwrap().value(), &expected[..]);
+    assert!(db.get(b"Lemon").unwrap().is_none());
+
+    // A missing blob file fails the read instead of panicking.
+    for file in read_dir(&dir).unwrap() {
+      let path = file.unwrap().path();
+      if path.extension().is_some_and(|e| e == "blob") {
+        remove_file(path).unwrap();
+      }
+    }
+    assert!(db.get(b"Mango").is_err());
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_blob_garbage_from_compaction() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+    let options = Options::new().enable_blob_files(true).min_blob_size(100);
+
+    let mut db = Database::open(path, options.clone()).unwrap();
+    db.set(b"Mango", &[1; 300]).unwrap();
+    db.flush(true).unwrap();
+    db.set(b"Mango", &[2; 300]).unwrap();
+    db.flush(true).unwrap();
+    // The older value is still held by a table, if hidden.
+    assert_eq!(db.blob_file_stats()[0].garbage_ratio(), 0.0);
+
+    db.compact_range(b"A", b"Z").unwrap();
+    assert_eq!(db.blob_file_stats()[0].garbage_ratio(), 0.5);
+    assert_eq!(db.get(b"Mango").unwrap().unwrap().value(), &[2; 300][..]);
+
+    db.delete(b"Mango").unwrap();
+    db.compact_range(b"A", b"Z").unwrap();
+    assert_eq!(db.blob_file_stats()[0].garbage_ratio(), 1.0);
+    drop(db);
+
+    // Reopening counts the same live bytes as the compactions left.
+    let mut db = Database::open(path, options).unwrap();
+    assert_eq!(db.blob_file_stats()[0].garbage_ratio(), 1.0);
+    assert_eq!(db.collect_blob_garbage(), Ok(1));
+    assert!(db.blob_file_stats().is_empty());
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_merge_on_flush_and_compaction() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+    let table_entry = |db: &Database, key: &[u8]| {
+      let table_file = &db.column_families[0].tables[0];
+      let value = table_file.table.get(key, &ReadOptions::new()).unwrap();
+      table_file.decode_entry(key, &value.unwrap()).unwrap()
+    };
+
+    let mut db = Database::open(path, Options::new()).unwrap();
+    db.set_merge_operator(Box::new(U64AddOperator));
+    db.set(b"Count", &1u64.to_le_bytes()).unwrap();
+    db.merge(b"Fresh", &1u64.to_le_bytes()).unwrap();
+    db.flush(true).unwrap();
+    // No table is older than the first, so the operands are merged into a value.
+    let entry = table_entry(&db, b"Fresh");
+    assert_eq!(entry.value.unwrap(), 1u64.to_le_bytes());
+    assert!(entry.operands.is_empty());
+
+    db.merge(b"Count", &2u64.to_le_bytes()).unwrap();
+    db.merge(b"Count", &3u64.to_le_bytes()).unwrap();
+    db.delete(b"Total").unwrap();
+    db.merge(b"Total", &4u64.to_le_bytes()).unwrap();
+    db.merge(b"Total", &5u64.to_le_bytes()).unwrap();
+    db.flush(true).unwrap();
+    // The value of Count is in an older table, so its operands are combined into one.
+    let entry = table_entry(&db, b"Count");
+    assert!(entry.value.is_none());
+    assert_eq!(entry.operands, vec![5u64.to_le_bytes().to_vec()]);
+    let entry = table_entry(&db, b"Total");
+    assert_eq!(entry.value.unwrap(), 9u64.to_le_bytes());
+    assert!(entry.operands.is_empty());
+
+    db.compact_range(b"A", b"Z").unwrap();
+    assert_eq!(db.column_families[0].tables.len(), 1);
+    let entry = table_entry(&db, b"Count");
+    assert_eq!(entry.value.unwrap(), 6u64.to_le_bytes());
+    assert!(entry.operands.is_empty());
+    assert_eq!(
+      db.get(b"Count").unwrap().unwrap().value(),
+      6u64.to_le_bytes()
+    );
+    assert_eq!(
+      db.get(b"Total").unwrap().unwrap().value(),
+      9u64.to_le_bytes()
+    );
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_ttl_on_flush_and_compaction() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+    let table_entry = |db: &Database, key: &[u8]| {
+      let table_file = &db.column_families[0].tables[0];
+      let value = table_file.table.get(key, &ReadOptions::new()).unwrap()?;
+      Some(table_file.decode_entry(key, &value).unwrap())
+    };
+    let ttl = Duration::from_millis(50);
+    let expire = || sleep(Duration::from_millis(100));
+
+    let mut db = Database::open(path, Options::new()).unwrap();
+    db.set_with_ttl(b"Apple", b"Apple Pie", ttl).unwrap();
+    expire();
+    db.flush(true).unwrap();
+    // No table is older, so the expired value is dropped and no table is left.
+    assert!(db.column_families[0].tables.is_empty());
+    assert_eq!(db.column_families[0].mem_table.len(), 0);
+
+    db.set(b"Kiwi", b"Kiwi Smoothie").unwrap();
+    db.flush(true).unwrap();
+    db.set_with_ttl(b"Kiwi", b"Kiwi Pie", ttl).unwrap();
+    db.set_with_ttl(b"Lime", b"Lime Pie", ttl).unwrap();
+    expire();
+    db.flush(true).unwrap();
+    // The older table has Kiwi, so the expired values are written as tombstones.
+    assert!(table_entry(&db, b"Kiwi").unwrap().deleted);
+    assert!(table_entry(&db, b"Lime").unwrap().deleted);
+    assert!(db.get(b"Kiwi").unwrap().is_none());
+
+    db.set_with_ttl(b"Mango", b"Mango Pie", ttl).unwrap();
+    db.set(b"Apple", b"Apple Juice").unwrap();
+    db.flush(true).unwrap();
+    assert!(!table_entry(&db, b"Mango").unwrap().deleted);
+    expire();
+
+    // Only the newest table has a key in the range, so Mango is deleted rather than dropped.
+    db.compact_range(b"A", b"B").unwrap();
+    assert_eq!(db.column_families[0].tables.len(), 3);
+    assert!(table_entry(&db, b"Mango").unwrap().deleted);
+
+    db.compact_range(b"A", b"Z").unwrap();
+    assert_eq!(db.column_families[0].tables.len(), 1);
+    let keys: [&[u8]; 3] = [b"Kiwi", b"Lime", b"Mango"];
+    for key in keys {
+      assert!(table_entry(&db, key).is_none());
+    }
+    assert_eq!(db.get(b"Apple").unwrap().unwrap().value(), b"Apple Juice");
+    assert!(db.get(b"Kiwi").unwrap().is_none());
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_direct_io_for_flush_and_compaction() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+
+    let options = Options::new()
+      .memtable_size(2048)
+      .use_direct_io_for_flush_and_compaction(true);
+    let mut db = Database::open(path, options.clone()).unwrap();
+    for i in 0..1000u32 {
+      db.set(&i.to_be_bytes(), format!("Smoothie {}", i).as_bytes())
+        .unwrap();
+    }
+    db.delete_range(&100u32.to_be_bytes(), &200u32.to_be_bytes())
+      .unwrap();
+    db.flush(true).unwrap();
+    assert!(db.column_families[0].tables.len() > 1);
+
+    // Reads outside of compactions stay buffered, while compactions read their inputs with direct
+    // I/O where the file system supports it.
+    let table_file = &db.column_families[0].tables[0];
+    let path_of = |number: u64| dir.join(format!("{}.sst", number));
+    let supported = Table::open(
+      &path_of(table_file.number),
+      &Options::new().use_direct_reads(true),
+      db.block_cache.clone(),
+    )
+    .unwrap()
+    .is_direct();
+    assert!(!table_file.table.is_direct());
+    let input = table_file
+      .compaction_input(&dir, &db.options, db.block_cache.clone())
+      .unwrap();
+    assert_eq!(input.table.is_direct(), supported);
+
+    db.compact_range(&0u32.to_be_bytes(), &1000u32.to_be_bytes())
+      .unwrap();
+    assert_eq!(db.column_families[0].tables.len(), 1);
+    let check = |db: &Database| {
+      for i in (0..1000u32).step_by(7) {
+        let entry = db.get(&i.to_be_bytes()).unwrap();
+        if (100..200).contains(&i) {
+          assert!(entry.is_none());
+        } else {
+          let value = format!("Smoothie {}", i);
+          assert_eq!(entry.unwrap().value(), value.as_bytes());
+        }
+      }
+    };
+    check(&db);
+    drop(db);
+
+    let db = Database::open(path, options).unwrap();
+    check(&db);
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_checkpoint_into_existing_dir() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let checkpoint_dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+
+    let mut db = Database::open(dir.to_str().unwrap(), Options::new()).unwrap();
+    db.set(b"Lime", b"Lime Smoothie").unwrap();
+
+    // An existing directory is left as it is, even if it is empty.
+    create_dir(&checkpoint_dir).unwrap();
+    let err = db.checkpoint(checkpoint_dir.to_str().unwrap()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+    assert_eq!(read_dir(&checkpoint_dir).unwrap().count(), 0);
+
+    let mut file = OpenOptions::new()
+      .write(true)
+      .create(true)
+      .truncate(true)
+      .open(checkpoint_dir.join("MANIFEST"))
+      .unwrap();
+    file.write_all(b"Mango").unwrap();
+    let err = db.checkpoint(checkpoint_dir.to_str().unwrap()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+    assert_eq!(read_dir(&checkpoint_dir).unwrap().count(), 1);
+    let mut contents = Vec::new();
+    OpenOptions::new()
+      .read(true)
+      .open(checkpoint_dir.join("MANIFEST"))
+      .unwrap()
+      .read_to_end(&mut contents)
+      .unwrap();
+    assert_eq!(contents, b"Mango");
+
+    // The failed checkpoints leave the Database usable.
+    db.set(b"Apple", b"Apple Pie").unwrap();
+    assert_eq!(db.get(b"Lime").unwrap().unwrap().value(), b"Lime Smoothie");
+
+    remove_dir_all(&dir).unwrap();
+    remove_dir_all(&checkpoint_dir).unwrap();
+  }
 }
@@ -0,0 +1,363 @@
+use crate::column_family::ColumnFamily;
+use crate::comparator::Comparator;
+use crate::database::{Database, DatabaseEntry};
+use crate::merging_iterator::{Direction, MergingIterator, Source};
+use crate::options::ReadOptions;
+use std::cmp::Ordering;
+use std::io;
+
+/// Iterator over the Key-Value pairs of a column family, in the order of the Database's Comparator.
+///
+/// The iterator merges the MemTable with every table through a MergingIterator, and yields each
+/// key once with its newest value. Deleted and expired keys are skipped, and merge operands are
+/// combined with the value they apply to. The iterator borrows the Database, so it sees the writes
+/// made before it was created, or only those made before the Snapshot of its ReadOptions was taken.
+/// Flushes run on the calling thread and tables are not organized into levels, so the MemTable and
+/// the tables are every source there is.
+///
+/// Keys outside of the `iterate_lower_bound` and `iterate_upper_bound` of the ReadOptions are not
+/// yielded, and the iterator stops at the bounds without reading past them.
+pub struct DBIterator<'a> {
+  db: &'a Database,
+  column_family: &'a ColumnFamily,
+  comparator: &'a dyn Comparator,
+  merging: MergingIterator<'a>,
+  lower_bound: Option<Vec<u8>>,
+  upper_bound: Option<Vec<u8>>,
+  current: Option<DatabaseEntry>,
+  error: Option<io::Error>,
+}
+
+impl<'a> DBIterator<'a> {
+  /// Creates a new DBIterator over a column family, which is not positioned until it is seeked.
+  ///
+  /// The entries are read from the MemTable and the tables of `source`, which is the column family
+  /// itself or its copy in a Snapshot.
+  pub(crate) fn new(
+    db: &'a Database,
+    column_family: &'a ColumnFamily,
+    source: &ColumnFamily,
+    comparator: &'a dyn Comparator,
+    read_options: &ReadOptions,
+  ) -> DBIterator<'a> {
+    let mut sources = vec![Source::MemTable {
+      mem_table: source.mem_table.clone(),
+      pos: source.mem_table.len(),
+    }];
+    for table_file in source.tables.iter() {
+      sources.push(Source::Table {
+        table_file: table_file.clone(),
+        iter: Box::new(table_file.table.iter(read_options.clone())),
+      });
+    }
+
+    DBIterator {
+      db,
+      column_family,
+      comparator,
+      merging: MergingIterator::new(sources, comparator),
+      lower_bound: read_options.iterate_lower_bound.clone(),
+      upper_bound: read_options.iterate_upper_bound.clone(),
+      current: None,
+      error: None,
+    }
+  }
+
+  /// Checks if the iterator is positioned at a Key-Value pair.
+  pub fn valid(&self) -> bool {
+    self.current.is_some()
+  }
+
+  /// Gets the key of the current Key-Value pair.
+  pub fn key(&self) -> &[u8] {
+    self.current.as_ref().unwrap().key()
+  }
+
+  /// Gets the value of the current Key-Value pair.
+  pub fn value(&self) -> &[u8] {
+    self.current.as_ref().unwrap().value()
+  }
+
+  /// Gets the timestamp of the current Key-Value pair.
+  pub fn timestamp(&self) -> u128 {
+    self.current.as_ref().unwrap().timestamp()
+  }
+
+  /// Gets the error that stopped the iterator, if reading a table failed.
+  pub fn status(&self) -> Option<&io::Error> {
+    self.error.as_ref()
+  }
+
+  /// Positions the iterator at the first Key-Value pair.
+  pub fn seek_to_first(&mut self) {
+    match self.lower_bound.clone() {
+      Some(lower_bound) => self.merging.seek(&lower_bound),
+      None => self.merging.seek_to_first(),
+    }
+    self.find_next();
+  }
+
+  /// Positions the iterator at the last Key-Value pair.
+  pub fn seek_to_last(&mut self) {
+    match self.upper_bound.clone() {
+      Some(upper_bound) => self.merging.seek_for_prev(&upper_bound),
+      None => self.merging.seek_to_last(),
+    }
+    self.find_prev();
+  }
+
+  /// Positions the iterator at the first Key-Value pair with a key at or after the target.
+  pub fn seek(&mut self, target: &[u8]) {
+    match self.lower_bound.clone() {
+      Some(lower_bound) if self.comparator.compare(target, &lower_bound) == Ordering::Less => {
+        self.merging.seek(&lower_bound)
+      }
+      _ => self.merging.seek(target),
+    }
+    self.find_next();
+  }
+
+  /// Positions the iterator at the last Key-Value pair with a key at or before the target.
+  pub fn seek_for_prev(&mut self, target: &[u8]) {
+    match self.upper_bound.clone() {
+      Some(upper_bound) if self.comparator.compare(target, &upper_bound) != Ordering::Less => {
+        self.merging.seek_for_prev(&upper_bound)
+      }
+      _ => self.merging.seek_for_prev(target),
+    }
+    self.find_prev();
+  }
+
+  /// Moves the iterator to the next Key-Value pair.
+  pub fn next(&mut self) {
+    let key = match self.current.take() {
+      Some(entry) => entry.key().to_vec(),
+      None => return,
+    };
+    if self.merging.direction() == Direction::Reverse {
+      self.merging.seek(&key);
+      self.merging.skip(&key);
+    }
+    self.find_next();
+  }
+
+  /// Moves the iterator to the previous Key-Value pair.
+  pub fn prev(&mut self) {
+    let key = match self.current.take() {
+      Some(entry) => entry.key().to_vec(),
+      None => return,
+    };
+    if self.merging.direction() == Direction::Forward {
+      self.merging.seek_for_prev(&key);
+      self.merging.skip(&key);
+    }
+    self.find_prev();
+  }
+
+  /// Moves forward to the next key that has a value, stopping at the upper bound.
+  fn find_next(&mut self) {
+    while let Some(key) = self.pop() {
+      if self.is_past_upper_bound(&key) {
+        break;
+      }
+      if self.resolve(&key) {
+        return;
+      }
+    }
+    self.current = None;
+  }
+
+  /// Moves backward to the previous key that has a value, stopping at the lower bound.
+  fn find_prev(&mut self) {
+    while let Some(key) = self.pop() {
+      if self
+        .lower_bound
+        .as_ref()
+        .is_some_and(|b| self.comparator.compare(&key, b) == Ordering::Less)
+      {
+        break;
+      }
+      if !self.is_past_upper_bound(&key) && self.resolve(&key) {
+        return;
+      }
+    }
+    self.current = None;
+  }
+
+  /// Takes the next key from the MergingIterator.
+  ///
+  /// Returns `None` once every source is exhausted, or a source failed.
+  fn pop(&mut self) -> Option<Vec<u8>> {
+    if self.error.is_some() {
+      return None;
+    }
+    let key = self.merging.pop();
+    if let Some(e) = self.merging.status() {
+      self.error = Some(io::Error::new(e.kind(), e.to_string()));
+      return None;
+    }
+    key
+  }
+
+  /// Gets if a key is at or after the upper bound.
+  fn is_past_upper_bound(&self, key: &[u8]) -> bool {
+    self
+      .upper_bound
+      .as_ref()
+      .is_some_and(|b| self.comparator.compare(key, b) != Ordering::Less)
+  }
+
+  /// Resolves the value of a key from the current sources of the MergingIterator.
+  ///
+  /// Returns `false` if the key is deleted or expired.
+  fn resolve(&mut self, key: &[u8]) -> bool {
+    let entries = match self.merging.entries(key) {
+      Ok(entries) => entries,
+      Err(e) => {
+        self.error = Some(e);
+        return false;
+      }
+    };
+
+    match self.db.resolve_entries(self.column_family, key, &entries) {
+      Ok(current) => self.current = current,
+      Err(e) => self.error = Some(e),
+    }
+    self.current.is_some()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::database::Database;
+  use crate::merge_operator::U64AddOperator;
+  use crate::options::{Options, ReadOptions};
+  use rand::Rng;
+  use std::collections::BTreeMap;
+  use std::fs::{read_dir, remove_dir_all};
+  use std::path::PathBuf;
+
+  #[test]
+  fn test_db_iterator() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+
+    let mut db = Database::open(path, Options::new().memtable_size(2048)).unwrap();
+    db.set_merge_operator(Box::new(U64AddOperator));
+    let mut expected = BTreeMap::new();
+    for i in 0..600u32 {
+      let key = (i * 11 % 600).to_be_bytes();
+      db.set(&key, format!("Smoothie {}", i).as_bytes()).unwrap();
+      expected.insert(key.to_vec(), format!("Smoothie {}", i).into_bytes());
+    }
+    db.delete_range(&100u32.to_be_bytes(), &200u32.to_be_bytes())
+      .unwrap();
+    expected.retain(|k, _| k[..] < 100u32.to_be_bytes()[..] || k[..] >= 200u32.to_be_bytes()[..]);
+    for i in (0..600u32).step_by(7) {
+      db.delete(&i.to_be_bytes()).unwrap();
+      expected.remove(i.to_be_bytes().as_slice());
+    }
+    db.set(&150u32.to_be_bytes(), b"Lime").unwrap();
+    expected.insert(150u32.to_be_bytes().to_vec(), b"Lime".to_vec());
+    db.merge(b"Count", &1u64.to_le_bytes()).unwrap();
+    for i in 600..800u32 {
+      db.set(&i.to_be_bytes(), b"Mango").unwrap();
+      expected.insert(i.to_be_bytes().to_vec(), b"Mango".to_vec());
+    }
+    db.merge(b"Count", &2u64.to_le_bytes()).unwrap();
+    expected.insert(b"Count".to_vec(), 3u64.to_le_bytes().to_vec());
+    let tables = read_dir(&dir)
+      .unwrap()
+      .filter(|f| {
+        let path = f.as_ref().unwrap().path();
+        path.extension().is_some_and(|e| e == "sst")
+      })
+      .count();
+    assert!(tables > 1);
+
+    let mut iter = db.iter();
+    assert!(!iter.valid());
+    iter.seek_to_first();
+    for (key, value) in expected.iter() {
+      assert!(iter.valid());
+      assert_eq!(iter.key(), &key[..]);
+      assert_eq!(iter.value(), &value[..]);
+      iter.next();
+    }
+    assert!(!iter.valid());
+
+    iter.seek_to_last();
+    for (key, value) in expected.iter().rev() {
+      assert!(iter.valid());
+      assert_eq!(iter.key(), &key[..]);
+      assert_eq!(iter.value(), &value[..]);
+      iter.prev();
+    }
+    assert!(!iter.valid());
+    assert!(iter.status().is_none());
+
+    // The latest key at or before the target, skipping deleted keys.
+    iter.seek_for_prev(&199u32.to_be_bytes());
+    assert_eq!(iter.key(), 150u32.to_be_bytes());
+    iter.seek_for_prev(&14u32.to_be_bytes());
+    assert_eq!(iter.key(), 13u32.to_be_bytes());
+    iter.seek_for_prev(&0u32.to_be_bytes());
+    assert!(!iter.valid());
+
+    // Changing direction.
+    iter.seek(&14u32.to_be_bytes());
+    assert_eq!(iter.key(), 15u32.to_be_bytes());
+    iter.prev();
+    assert_eq!(iter.key(), 13u32.to_be_bytes());
+    iter.next();
+    assert_eq!(iter.key(), 15u32.to_be_bytes());
+    iter.next();
+    assert_eq!(iter.key(), 16u32.to_be_bytes());
+
+    assert!(db.iter_cf("Missing").is_none());
+    drop(iter);
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_db_iterator_bounds() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+
+    let mut db = Database::open(path, Options::new().memtable_size(2048)).unwrap();
+    for i in 0..300u32 {
+      db.set(&i.to_be_bytes(), b"Lime").unwrap();
+    }
+    db.delete(&100u32.to_be_bytes()).unwrap();
+
+    let read_options = ReadOptions::new()
+      .iterate_lower_bound(&100u32.to_be_bytes())
+      .iterate_upper_bound(&200u32.to_be_bytes());
+    let mut iter = db.iter_cf_opt(&read_options, "default").unwrap();
+    iter.seek_to_first();
+    assert_eq!(iter.key(), 101u32.to_be_bytes());
+    let mut count = 0;
+    while iter.valid() {
+      count += 1;
+      iter.next();
+    }
+    assert_eq!(count, 99);
+
+    iter.seek_to_last();
+    assert_eq!(iter.key(), 199u32.to_be_bytes());
+    iter.seek_for_prev(&250u32.to_be_bytes());
+    assert_eq!(iter.key(), 199u32.to_be_bytes());
+    iter.seek(&50u32.to_be_bytes());
+    assert_eq!(iter.key(), 101u32.to_be_bytes());
+    iter.prev();
+    assert!(!iter.valid());
+    iter.seek(&200u32.to_be_bytes());
+    assert!(!iter.valid());
+    assert!(iter.status().is_none());
+
+    drop(iter);
+    remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,124 @@
+use std::alloc::{alloc_zeroed, dealloc, Layout};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+use std::ptr::NonNull;
+
+/// Alignment of the offsets, lengths and buffers of direct I/O, which covers the logical block
+/// size of common devices.
+pub const ALIGNMENT: usize = 4096;
+
+/// Rounds a size up to a multiple of the alignment.
+pub fn align_up(size: usize) -> usize {
+  size.div_ceil(ALIGNMENT) * ALIGNMENT
+}
+
+/// Rounds a size down to a multiple of the alignment.
+pub fn align_down(size: u64) -> u64 {
+  size / ALIGNMENT as u64 * ALIGNMENT as u64
+}
+
+/// Zeroed buffer whose address and capacity are aligned for direct I/O.
+pub struct AlignedBuffer {
+  ptr: NonNull<u8>,
+  capacity: usize,
+}
+
+// The buffer owns its allocation, like a Vec.
+unsafe impl Send for AlignedBuffer {}
+unsafe impl Sync for AlignedBuffer {}
+
+impl AlignedBuffer {
+  /// Creates a new AlignedBuffer with a capacity rounded up to the alignment.
+  pub fn new(capacity: usize) -> AlignedBuffer {
+    let capacity = align_up(capacity.max(1));
+    let ptr = unsafe { alloc_zeroed(AlignedBuffer::layout(capacity)) };
+    AlignedBuffer {
+      ptr: NonNull::new(ptr).expect("failed to allocate an aligned buffer"),
+      capacity,
+    }
+  }
+
+  /// Gets the capacity of the buffer in bytes.
+  pub fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  pub fn as_slice(&self) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.capacity) }
+  }
+
+  pub fn as_mut_slice(&mut self) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.capacity) }
+  }
+
+  fn layout(capacity: usize) -> Layout {
+    Layout::from_size_align(capacity, ALIGNMENT).unwrap()
+  }
+}
+
+impl Drop for AlignedBuffer {
+  fn drop(&mut self) {
+    unsafe { dealloc(self.ptr.as_ptr(), AlignedBuffer::layout(self.capacity)) }
+  }
+}
+
+/// Opens a file that bypasses the page cache, with `O_DIRECT`.
+///
+/// Fails on platforms and file systems without direct I/O, so callers fall back to buffered I/O.
+#[cfg(target_os = "linux")]
+pub fn open_direct(path: &Path, options: &mut OpenOptions) -> io::Result<File> {
+  use std::os::unix::fs::OpenOptionsExt;
+
+  options.custom_flags(libc::O_DIRECT).open(path)
+}
+
+/// Opens a file that bypasses the page cache, with `O_DIRECT`.
+///
+/// Fails on platforms and file systems without direct I/O, so callers fall back to buffered I/O.
+#[cfg(not(target_os = "linux"))]
+pub fn open_direct(_path: &Path, _options: &mut OpenOptions) -> io::Result<File> {
+  Err(io::Error::new(
+    io::ErrorKind::Unsupported,
+    "direct I/O is not supported on this platform",
+  ))
+}
+
+/// Reads into an aligned buffer at an aligned offset, stopping early at the end of the file.
+///
+/// Returns the number of bytes read.
+#[cfg(unix)]
+pub fn read_at(file: &File, buf: &mut AlignedBuffer, offset: u64) -> io::Result<usize> {
+  use std::os::unix::fs::FileExt;
+
+  let buf = buf.as_mut_slice();
+  let mut read = 0;
+  while read < buf.len() {
+    match file.read_at(&mut buf[read..], offset + read as u64) {
+      Ok(0) => break,
+      Ok(n) => read += n,
+      Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+      Err(e) => return Err(e),
+    }
+  }
+  Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::direct_io::{align_down, align_up, AlignedBuffer, ALIGNMENT};
+
+  #[test]
+  fn test_aligned_buffer() {
+    let mut buf = AlignedBuffer::new(5000);
+    assert_eq!(buf.capacity(), 2 * ALIGNMENT);
+    assert_eq!(buf.as_slice().as_ptr() as usize % ALIGNMENT, 0);
+    assert!(buf.as_slice().iter().all(|b| *b == 0));
+    buf.as_mut_slice()[4999] = 1;
+    assert_eq!(buf.as_slice()[4999], 1);
+
+    assert_eq!(align_up(0), 0);
+    assert_eq!(align_up(4097), 8192);
+    assert_eq!(align_down(4097), 4096);
+  }
+}
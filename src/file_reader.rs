@@ -0,0 +1,291 @@
+#[cfg(unix)]
+use crate::direct_io::{align_down, align_up, open_direct, read_at, AlignedBuffer};
+use crate::options::MmapAdvice;
+use std::borrow::Cow;
+use std::fs::File;
+#[cfg(unix)]
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+use std::io::{self, SeekFrom};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Read-only memory mapping of a whole file.
+#[cfg(unix)]
+pub(crate) struct Mmap {
+  ptr: *mut libc::c_void,
+  len: usize,
+}
+
+// The mapping is read-only and is only unmapped when it is dropped.
+#[cfg(unix)]
+unsafe impl Send for Mmap {}
+#[cfg(unix)]
+unsafe impl Sync for Mmap {}
+
+#[cfg(unix)]
+impl Mmap {
+  /// Maps the first `len` bytes of a file.
+  fn map(file: &File, len: usize) -> io::Result<Mmap> {
+    use std::os::unix::io::AsRawFd;
+
+    if len == 0 {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "cannot map an empty file",
+      ));
+    }
+    let ptr = unsafe {
+      libc::mmap(
+        std::ptr::null_mut(),
+        len,
+        libc::PROT_READ,
+        libc::MAP_SHARED,
+        file.as_raw_fd(),
+        0,
+      )
+    };
+    if ptr == libc::MAP_FAILED {
+      return Err(io::Error::last_os_error());
+    }
+    Ok(Mmap { ptr, len })
+  }
+
+  /// Gives the operating system a hint about how the mapping will be read.
+  fn advise(&self, advice: MmapAdvice) -> io::Result<()> {
+    let advice = match advice {
+      MmapAdvice::Normal => libc::MADV_NORMAL,
+      MmapAdvice::Random => libc::MADV_RANDOM,
+      MmapAdvice::Sequential => libc::MADV_SEQUENTIAL,
+      MmapAdvice::WillNeed => libc::MADV_WILLNEED,
+    };
+    if unsafe { libc::madvise(self.ptr, self.len, advice) } != 0 {
+      return Err(io::Error::last_os_error());
+    }
+    Ok(())
+  }
+
+  fn as_slice(&self) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+  }
+}
+
+#[cfg(unix)]
+impl Drop for Mmap {
+  fn drop(&mut self) {
+    unsafe {
+      libc::munmap(self.ptr, self.len);
+    }
+  }
+}
+
+/// Immutable file that is read at arbitrary offsets, through a memory mapping, with direct I/O or
+/// with buffered reads.
+///
+/// Reads from a memory mapping borrow the mapped pages, while buffered reads copy the bytes into a
+/// new buffer. The file must not be truncated while it is mapped. Direct reads bypass the page
+/// cache: the aligned blocks around the bytes are read into an aligned buffer, and the bytes are
+/// copied out of it.
+pub(crate) enum RandomAccessFile {
+  Buffered {
+    file: Mutex<File>,
+    len: u64,
+  },
+  #[cfg(unix)]
+  Mmap(Mmap),
+  #[cfg(unix)]
+  Direct {
+    file: File,
+    len: u64,
+  },
+}
+
+impl RandomAccessFile {
+  /// Opens a file, mapping it into memory with an access pattern hint if `use_mmap` is set, or
+  /// bypassing the page cache if `use_direct` is set.
+  ///
+  /// Falls back to buffered reads if the file cannot be mapped or opened with direct I/O.
+  pub fn open(
+    path: &Path,
+    use_mmap: bool,
+    use_direct: bool,
+    advice: MmapAdvice,
+  ) -> io::Result<RandomAccessFile> {
+    #[cfg(unix)]
+    {
+      if use_direct {
+        if let Ok(file) = open_direct(path, OpenOptions::new().read(true)) {
+          let len = file.metadata()?.len();
+          return Ok(RandomAccessFile::Direct { file, len });
+        }
+      }
+    }
+    #[cfg(not(unix))]
+    let _ = use_direct;
+
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    #[cfg(unix)]
+    {
+      if use_mmap {
+        if let Ok(mmap) = Mmap::map(&file, len as usize) {
+          // The hint is only an optimization, so a failure to apply it is ignored.
+          let _ = mmap.advise(advice);
+          return Ok(RandomAccessFile::Mmap(mmap));
+        }
+      }
+    }
+    #[cfg(not(unix))]
+    let _ = (use_mmap, advice);
+
+    Ok(RandomAccessFile::Buffered {
+      file: Mutex::new(file),
+      len,
+    })
+  }
+
+  /// Gets the size of the file in bytes.
+  pub fn len(&self) -> u64 {
+    match self {
+      RandomAccessFile::Buffered { len, .. } => *len,
+      #[cfg(unix)]
+      RandomAccessFile::Mmap(mmap) => mmap.len as u64,
+      #[cfg(unix)]
+      RandomAccessFile::Direct { len, .. } => *len,
+    }
+  }
+
+  /// Gets if the file is read through a memory mapping.
+  pub fn is_mmap(&self) -> bool {
+    #[cfg(unix)]
+    return matches!(self, RandomAccessFile::Mmap(_));
+    #[cfg(not(unix))]
+    return false;
+  }
+
+  /// Gets if the file is read with direct I/O.
+  pub fn is_direct(&self) -> bool {
+    #[cfg(unix)]
+    return matches!(self, RandomAccessFile::Direct { .. });
+    #[cfg(not(unix))]
+    return false;
+  }
+
+  /// Reads `len` bytes at an offset, borrowing them from the mapping if the file is mapped.
+  ///
+  /// With the `io_uring` feature, buffered files are read through io_uring.
+  pub fn read(&self, offset: u64, len: usize) -> io::Result<Cow<'_, [u8]>> {
+    self.check_range(offset, len)?;
+
+    match self {
+      RandomAccessFile::Buffered { file, .. } => {
+        let mut file = file.lock().unwrap();
+        #[cfg(all(feature = "io_uring", target_os = "linux"))]
+        {
+          if let Some(bufs) = crate::io_uring::read_batch(&file, &[(offset, len)]) {
+            return Ok(Cow::Owned(bufs?.pop().unwrap()));
+          }
+        }
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf = vec![0; len];
+        file.read_exact(&mut buf)?;
+        Ok(Cow::Owned(buf))
+      }
+      #[cfg(unix)]
+      RandomAccessFile::Mmap(mmap) => {
+        let offset = offset as usize;
+        Ok(Cow::Borrowed(&mmap.as_slice()[offset..offset + len]))
+      }
+      #[cfg(unix)]
+      RandomAccessFile::Direct { file, .. } => {
+        let start = align_down(offset);
+        let skip = (offset - start) as usize;
+        let mut buf = AlignedBuffer::new(align_up(skip + len));
+        if read_at(file, &mut buf, start)? < skip + len {
+          return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "read past the end of the file",
+          ));
+        }
+        Ok(Cow::Owned(buf.as_slice()[skip..skip + len].to_vec()))
+      }
+    }
+  }
+
+  /// Reads ranges of the file, as `(offset, len)` pairs.
+  ///
+  /// With the `io_uring` feature, the reads of a buffered file are submitted together through
+  /// io_uring, so the kernel can serve them in parallel. Otherwise the ranges are read one after
+  /// another.
+  pub fn read_batch(&self, ranges: &[(u64, usize)]) -> io::Result<Vec<Cow<'_, [u8]>>> {
+    for (offset, len) in ranges.iter() {
+      self.check_range(*offset, *len)?;
+    }
+
+    #[cfg(all(feature = "io_uring", target_os = "linux"))]
+    {
+      if let RandomAccessFile::Buffered { file, .. } = self {
+        let file = file.lock().unwrap();
+        if let Some(bufs) = crate::io_uring::read_batch(&file, ranges) {
+          return Ok(bufs?.into_iter().map(Cow::Owned).collect());
+        }
+      }
+    }
+
+    ranges
+      .iter()
+      .map(|(offset, len)| self.read(*offset, *len))
+      .collect()
+  }
+
+  /// Checks that a range is within the file.
+  fn check_range(&self, offset: u64, len: usize) -> io::Result<()> {
+    if offset
+      .checked_add(len as u64)
+      .is_none_or(|end| end > self.len())
+    {
+      return Err(io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "read past the end of the file",
+      ));
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::file_reader::RandomAccessFile;
+  use crate::options::MmapAdvice;
+  use rand::Rng;
+  use std::borrow::Cow;
+  use std::fs::{create_dir, remove_dir_all, write};
+  use std::path::PathBuf;
+
+  #[test]
+  fn test_read_borrows_mapped_pages() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+    let path = dir.join("file");
+    write(&path, b"0123456789").unwrap();
+
+    let file = RandomAccessFile::open(&path, false, false, MmapAdvice::Random).unwrap();
+    assert!(!file.is_mmap());
+    let bytes = file.read(2, 3).unwrap();
+    assert!(matches!(bytes, Cow::Owned(_)));
+    assert_eq!(&*bytes, b"234");
+
+    let file = RandomAccessFile::open(&path, true, false, MmapAdvice::Sequential).unwrap();
+    if cfg!(unix) {
+      assert!(file.is_mmap());
+      let bytes = file.read(2, 3).unwrap();
+      assert!(matches!(bytes, Cow::Borrowed(_)));
+      assert_eq!(&*bytes, b"234");
+    }
+    assert!(file.read(8, 3).is_err());
+
+    remove_dir_all(&dir).unwrap();
+  }
+}
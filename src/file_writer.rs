@@ -0,0 +1,148 @@
+use crate::direct_io::{align_up, open_direct, AlignedBuffer};
+use std::fs::{File, OpenOptions};
+use std::io::prelude::*;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+/// Size of the buffer of a file written with direct I/O.
+const DIRECT_BUFFER_SIZE: usize = 256 * 1024;
+
+/// New file that is appended to, either through the page cache or with direct I/O.
+///
+/// Direct I/O writes whole aligned blocks from an aligned buffer, so bytes are only written once
+/// the buffer fills up, and `flush` does not write a partially filled buffer. `sync` pads the last
+/// block, writes it, and truncates the file back to the number of bytes written.
+pub(crate) enum WritableFile {
+  Buffered(BufWriter<File>),
+  Direct {
+    file: File,
+    buf: AlignedBuffer,
+    buffered: usize,
+    written: u64,
+  },
+}
+
+impl WritableFile {
+  /// Creates a new file, truncating an existing one, that bypasses the page cache if `direct` is
+  /// set.
+  ///
+  /// Falls back to buffered writes if the file cannot be opened with direct I/O.
+  pub fn create(path: &Path, direct: bool) -> io::Result<WritableFile> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    if direct {
+      if let Ok(file) = open_direct(path, &mut options.clone()) {
+        return Ok(WritableFile::Direct {
+          file,
+          buf: AlignedBuffer::new(DIRECT_BUFFER_SIZE),
+          buffered: 0,
+          written: 0,
+        });
+      }
+    }
+    Ok(WritableFile::Buffered(BufWriter::new(options.open(path)?)))
+  }
+
+  /// Writes every buffered byte and syncs the file to disk.
+  ///
+  /// With direct I/O, nothing may be written to the file after it is synced.
+  pub fn sync(&mut self) -> io::Result<()> {
+    match self {
+      WritableFile::Buffered(file) => {
+        file.flush()?;
+        file.get_ref().sync_all()
+      }
+      WritableFile::Direct {
+        file,
+        buf,
+        buffered,
+        written,
+      } => {
+        if *buffered > 0 {
+          let len = align_up(*buffered);
+          buf.as_mut_slice()[*buffered..len].fill(0);
+          file.write_all(&buf.as_slice()[..len])?;
+          *written += *buffered as u64;
+          *buffered = 0;
+          file.set_len(*written)?;
+        }
+        file.sync_all()
+      }
+    }
+  }
+}
+
+/// Existing file that is appended to, such as a WAL.
+///
+/// With the `io_uring` feature, writes are submitted through io_uring at the end of the file.
+pub(crate) struct AppendFile {
+  file: File,
+  #[cfg_attr(not(all(feature = "io_uring", target_os = "linux")), allow(dead_code))]
+  offset: u64,
+}
+
+impl AppendFile {
+  /// Opens a file for appending, creating it if it does not exist.
+  pub fn open(path: &Path) -> io::Result<AppendFile> {
+    let file = OpenOptions::new().append(true).create(true).open(path)?;
+    let offset = file.metadata()?.len();
+    Ok(AppendFile { file, offset })
+  }
+
+  /// Syncs the file to disk.
+  pub fn sync_all(&self) -> io::Result<()> {
+    self.file.sync_all()
+  }
+}
+
+impl Write for AppendFile {
+  fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+    #[cfg(all(feature = "io_uring", target_os = "linux"))]
+    {
+      if let Some(result) = crate::io_uring::write_at(&self.file, data, self.offset) {
+        let n = result?;
+        self.offset += n as u64;
+        return Ok(n);
+      }
+    }
+    let n = self.file.write(data)?;
+    self.offset += n as u64;
+    Ok(n)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.file.flush()
+  }
+}
+
+impl Write for WritableFile {
+  fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+    match self {
+      WritableFile::Buffered(file) => file.write(data),
+      WritableFile::Direct {
+        file,
+        buf,
+        buffered,
+        written,
+      } => {
+        let n = data.len().min(buf.capacity() - *buffered);
+        buf.as_mut_slice()[*buffered..*buffered + n].copy_from_slice(&data[..n]);
+        *buffered += n;
+        if *buffered == buf.capacity() {
+          file.write_all(buf.as_slice())?;
+          *written += *buffered as u64;
+          *buffered = 0;
+        }
+        Ok(n)
+      }
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match self {
+      WritableFile::Buffered(file) => file.flush(),
+      WritableFile::Direct { .. } => Ok(()),
+    }
+  }
+}
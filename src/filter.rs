@@ -0,0 +1,88 @@
+/// Hashes a key for a bloom filter.
+///
+/// This is the Murmur-like hash of LevelDB, which is stable across platforms and versions of Rust,
+/// so filters written to disk stay readable.
+fn bloom_hash(key: &[u8]) -> u32 {
+  const SEED: u32 = 0xbc9f_1d34;
+  const M: u32 = 0xc6a4_a793;
+
+  let mut h = SEED ^ (key.len() as u32).wrapping_mul(M);
+  let mut chunks = key.chunks_exact(4);
+  for chunk in chunks.by_ref() {
+    let w = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    h = h.wrapping_add(w).wrapping_mul(M);
+    h ^= h >> 16;
+  }
+  let rest = chunks.remainder();
+  for (i, byte) in rest.iter().enumerate().rev() {
+    h = h.wrapping_add((*byte as u32) << (8 * i));
+  }
+  if !rest.is_empty() {
+    h = h.wrapping_mul(M);
+    h ^= h >> 24;
+  }
+  h
+}
+
+/// Builds a bloom filter of a set of keys, with a number of bits per key.
+///
+/// The filter is the bit array followed by the number of probes per key. Each key is probed with
+/// double hashing, so a single hash is computed per key.
+pub fn build_bloom_filter(keys: &[Vec<u8>], bits_per_key: usize) -> Vec<u8> {
+  // 0.69 is ln(2), which minimizes the false positive rate for the number of bits per key.
+  let probes = ((bits_per_key as f64 * 0.69) as usize).clamp(1, 30);
+  let bits = (keys.len() * bits_per_key).max(64);
+  let bytes = bits.div_ceil(8);
+  let bits = bytes * 8;
+
+  let mut filter = vec![0; bytes + 1];
+  for key in keys.iter() {
+    let mut h = bloom_hash(key);
+    let delta = h.rotate_right(17);
+    for _ in 0..probes {
+      let bit = h as usize % bits;
+      filter[bit / 8] |= 1 << (bit % 8);
+      h = h.wrapping_add(delta);
+    }
+  }
+  filter[bytes] = probes as u8;
+  filter
+}
+
+/// Checks if a key may be in a bloom filter. A key that was added to the filter is always found.
+pub fn bloom_filter_may_contain(filter: &[u8], key: &[u8]) -> bool {
+  if filter.len() < 2 {
+    return true;
+  }
+  let bits = (filter.len() - 1) * 8;
+  let probes = filter[filter.len() - 1];
+
+  let mut h = bloom_hash(key);
+  let delta = h.rotate_right(17);
+  for _ in 0..probes {
+    let bit = h as usize % bits;
+    if filter[bit / 8] & (1 << (bit % 8)) == 0 {
+      return false;
+    }
+    h = h.wrapping_add(delta);
+  }
+  true
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::filter::{bloom_filter_may_contain, build_bloom_filter};
+
+  #[test]
+  fn test_bloom_filter() {
+    let keys: Vec<Vec<u8>> = (0..1000u32).map(|i| i.to_be_bytes().to_vec()).collect();
+    let filter = build_bloom_filter(&keys, 10);
+
+    assert!(keys.iter().all(|k| bloom_filter_may_contain(&filter, k)));
+
+    let false_positives = (1000..11000u32)
+      .filter(|i| bloom_filter_may_contain(&filter, &i.to_be_bytes()))
+      .count();
+    assert!(false_positives < 300);
+  }
+}
@@ -0,0 +1,230 @@
+use io_uring::{opcode, types, IoUring};
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::ops::Range;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// Number of entries of the submission queue, which bounds the reads submitted at once.
+const RING_ENTRIES: u32 = 64;
+
+/// Gets the io_uring instance shared by the process, or `None` if the kernel does not support
+/// io_uring, in which case callers fall back to `std::fs`.
+fn ring() -> Option<&'static Mutex<IoUring>> {
+  static RING: OnceLock<Option<Mutex<IoUring>>> = OnceLock::new();
+  RING
+    .get_or_init(|| IoUring::new(RING_ENTRIES).ok().map(Mutex::new))
+    .as_ref()
+}
+
+/// Reserves `n` user_data values that no other operation on the ring uses, so a completion is
+/// only ever matched to the operation that submitted it.
+fn reserve_user_data(n: usize) -> Range<u64> {
+  static NEXT_USER_DATA: AtomicU64 = AtomicU64::new(0);
+  let start = NEXT_USER_DATA.fetch_add(n as u64, Ordering::Relaxed);
+  start..start + n as u64
+}
+
+/// Submits the queued operations and waits for the completions of `pending` of them, whose
+/// user_data are in `ids`, passing the user_data and result of each to `complete`.
+///
+/// Completions of other operations, left behind by a caller that gave up on them, are discarded.
+/// If waiting fails, some of the operations may still be queued or in flight, so the caller must
+/// not free their buffers.
+fn wait(
+  ring: &mut IoUring,
+  ids: Range<u64>,
+  mut pending: usize,
+  mut complete: impl FnMut(u64, i32),
+) -> io::Result<()> {
+  while pending > 0 {
+    if let Err(e) = ring.submit_and_wait(1) {
+      if e.kind() != io::ErrorKind::Interrupted {
+        return Err(e);
+      }
+    }
+    for cqe in ring.completion() {
+      if ids.contains(&cqe.user_data()) {
+        pending -= 1;
+        complete(cqe.user_data(), cqe.result());
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Reads ranges of a file, submitting the reads together so the kernel can serve them in
+/// parallel.
+///
+/// Returns `None` if io_uring is not available. Reads that come back short, because they were
+/// interrupted or crossed the end of the file, are completed with `read_exact_at`.
+pub fn read_batch(file: &File, ranges: &[(u64, usize)]) -> Option<io::Result<Vec<Vec<u8>>>> {
+  let ring = ring()?;
+  let mut bufs: Vec<Vec<u8>> = ranges.iter().map(|(_, len)| vec![0; *len]).collect();
+  let mut done = vec![0; ranges.len()];
+  let fd = types::Fd(file.as_raw_fd());
+  let ids = reserve_user_data(ranges.len());
+
+  let mut ring = ring.lock().unwrap();
+  let mut error = None;
+  for start in (0..ranges.len()).step_by(RING_ENTRIES as usize) {
+    let end = (start + RING_ENTRIES as usize).min(ranges.len());
+    let mut pushed = 0;
+    for i in start..end {
+      let entry = opcode::Read::new(fd, bufs[i].as_mut_ptr(), bufs[i].len() as u32)
+        .offset(ranges[i].0)
+        .build()
+        .user_data(ids.start + i as u64);
+      // The buffer outlives the read, as every queued read is waited for below.
+      if unsafe { ring.submission().push(&entry) }.is_err() {
+        error = Some(io::Error::other("io_uring submission queue is full"));
+        break;
+      }
+      pushed += 1;
+    }
+    let result = wait(&mut ring, ids.clone(), pushed, |id, result| {
+      if result < 0 {
+        error.get_or_insert(io::Error::from_raw_os_error(-result));
+      } else {
+        done[(id - ids.start) as usize] = result as usize;
+      }
+    });
+    if let Err(e) = result {
+      // Reads may still be queued or in flight, so the buffers are leaked rather than freed
+      // under them.
+      mem::forget(bufs);
+      return Some(Err(e));
+    }
+    if error.is_some() {
+      break;
+    }
+  }
+  drop(ring);
+  if let Some(e) = error {
+    return Some(Err(e));
+  }
+
+  for (i, buf) in bufs.iter_mut().enumerate() {
+    if done[i] < buf.len() {
+      if let Err(e) = file.read_exact_at(&mut buf[done[i]..], ranges[i].0 + done[i] as u64) {
+        return Some(Err(e));
+      }
+    }
+  }
+  Some(Ok(bufs))
+}
+
+/// Writes a buffer to a file at an offset.
+///
+/// Returns `None` if io_uring is not available. For a file opened in append mode, the write is
+/// appended to the end of the file whatever the offset.
+pub fn write_at(file: &File, buf: &[u8], offset: u64) -> Option<io::Result<usize>> {
+  let ring = ring()?;
+  // The write reads from a copy of the buffer, which can be leaked if the write is abandoned,
+  // as the caller's buffer may be freed as soon as this returns.
+  let buf = buf.to_vec();
+  let ids = reserve_user_data(1);
+  let entry = opcode::Write::new(types::Fd(file.as_raw_fd()), buf.as_ptr(), buf.len() as u32)
+    .offset(offset)
+    .build()
+    .user_data(ids.start);
+
+  let mut ring = ring.lock().unwrap();
+  // The buffer outlives the write, as the write is waited for below.
+  if unsafe { ring.submission().push(&entry) }.is_err() {
+    return Some(Err(io::Error::other("io_uring submission queue is full")));
+  }
+  let mut written = 0;
+  if let Err(e) = wait(&mut ring, ids, 1, |_, result| written = result) {
+    mem::forget(buf);
+    return Some(Err(e));
+  }
+  if written < 0 {
+    return Some(Err(io::Error::from_raw_os_error(-written)));
+  }
+  Some(Ok(written as usize))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::io_uring::{read_batch, write_at};
+  use rand::Rng;
+  use std::fs::{remove_file, OpenOptions};
+  use std::io;
+  use std::path::PathBuf;
+
+  #[test]
+  fn test_io_uring() {
+    let mut rng = rand::thread_rng();
+    let path = PathBuf::from(format!("./{}.bin", rng.gen::<u32>()));
+    let file = OpenOptions::new()
+      .read(true)
+      .append(true)
+      .create(true)
+      .open(&path)
+      .unwrap();
+
+    let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+    let mut written = 0;
+    while written < data.len() {
+      written += match write_at(&file, &data[written..], written as u64) {
+        Some(result) => result.unwrap(),
+        None => {
+          // io_uring is not available, which the callers handle by falling back to std::fs.
+          remove_file(&path).unwrap();
+          return;
+        }
+      };
+    }
+
+    let ranges: Vec<(u64, usize)> = (0..100).map(|i| (i * 97, 50)).collect();
+    let bufs = read_batch(&file, &ranges).unwrap().unwrap();
+    for ((offset, len), buf) in ranges.iter().zip(bufs.iter()) {
+      assert_eq!(&buf[..], &data[*offset as usize..*offset as usize + len]);
+    }
+
+    remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn test_io_uring_errors() {
+    let mut rng = rand::thread_rng();
+    let path = PathBuf::from(format!("./{}.bin", rng.gen::<u32>()));
+    let file = OpenOptions::new()
+      .read(true)
+      .append(true)
+      .create(true)
+      .open(&path)
+      .unwrap();
+    if write_at(&file, &[7; 100], 0).is_none() {
+      // io_uring is not available, which the callers handle by falling back to std::fs.
+      remove_file(&path).unwrap();
+      return;
+    }
+
+    // A read that crosses the end of the file comes back short, and cannot be completed.
+    let err = read_batch(&file, &[(0, 50), (90, 20)])
+      .unwrap()
+      .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+    // The kernel's error of a failed write is returned.
+    let read_only = OpenOptions::new().read(true).open(&path).unwrap();
+    assert!(write_at(&read_only, b"Lime", 0).unwrap().is_err());
+
+    // Every read of a failed batch is completed before it returns, so the completions of its
+    // other reads are not mistaken for those of the next operations.
+    let write_only = OpenOptions::new().append(true).open(&path).unwrap();
+    let ranges: Vec<(u64, usize)> = (0..100).map(|i| (i, 1)).collect();
+    assert!(read_batch(&write_only, &ranges).unwrap().is_err());
+    assert_eq!(write_at(&file, &[8; 100], 100).unwrap().unwrap(), 100);
+    let bufs = read_batch(&file, &[(50, 100)]).unwrap().unwrap();
+    assert_eq!(&bufs[0][..50], &[7; 50][..]);
+    assert_eq!(&bufs[0][50..], &[8; 50][..]);
+
+    remove_file(&path).unwrap();
+  }
+}
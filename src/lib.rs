@@ -1,9 +1,30 @@
+pub mod backup;
+pub mod blob;
+pub mod block;
+pub mod block_cache;
+pub mod column_family;
+pub mod comparator;
+pub mod compression;
 pub mod database;
+pub mod db_iterator;
+mod direct_io;
+mod file_reader;
+mod file_writer;
+pub mod filter;
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+mod io_uring;
+mod manifest;
 mod mem_table;
+pub mod merge_operator;
+mod merging_iterator;
+pub mod options;
+mod options_file;
+pub mod snapshot;
+pub mod table;
+mod table_entry;
+pub mod table_file_writer;
+pub mod transaction;
 mod utils;
 mod wal;
 mod wal_iterator;
-
-// mod table;
-// mod table_manager;
-// mod utils;
+pub mod write_batch;
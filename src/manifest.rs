@@ -0,0 +1,125 @@
+use std::fs::{rename, File, OpenOptions};
+use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Name of the file that lists the tables of a Database in its directory.
+pub const MANIFEST_FILE: &str = "MANIFEST";
+
+/// Table files of a Database, and the WALs that hold the writes not yet flushed to them.
+///
+/// The MANIFEST is a text file of `name=value` lines. Each table is a `table=<column family id>:<table
+/// number>` line, oldest first. Table files that are not listed are left over from a flush that
+/// did not finish, and WALs older than `wal_number` only hold writes that are already in tables.
+///
+/// Tables ingested from external files also have an `ingested=<table number>:<timestamp>` line,
+/// with the timestamp assigned to every entry of the table when it was ingested.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+  pub next_table_number: u64,
+  pub wal_number: u128,
+  pub tables: Vec<(u32, u64)>,
+  pub ingested: Vec<(u64, u128)>,
+}
+
+impl Manifest {
+  /// Reads the MANIFEST file within a directory.
+  ///
+  /// Returns `None` if the directory does not have a MANIFEST file.
+  pub fn read(dir: &Path) -> io::Result<Option<Manifest>> {
+    let file = match File::open(dir.join(MANIFEST_FILE)) {
+      Ok(file) => file,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+      Err(e) => return Err(e),
+    };
+    let corrupted = || io::Error::new(io::ErrorKind::InvalidData, "MANIFEST is corrupted");
+
+    let mut manifest = Manifest::default();
+    for line in BufReader::new(file).lines() {
+      let line = line?;
+      let (name, value) = match line.find('=') {
+        Some(idx) => (&line[..idx], &line[idx + 1..]),
+        None => continue,
+      };
+      match name {
+        "next_table_number" => {
+          manifest.next_table_number = value.parse().map_err(|_| corrupted())?
+        }
+        "wal_number" => manifest.wal_number = value.parse().map_err(|_| corrupted())?,
+        "table" => {
+          let idx = value.find(':').ok_or_else(corrupted)?;
+          let column_family = value[..idx].parse().map_err(|_| corrupted())?;
+          let number = value[idx + 1..].parse().map_err(|_| corrupted())?;
+          manifest.tables.push((column_family, number));
+        }
+        "ingested" => {
+          let idx = value.find(':').ok_or_else(corrupted)?;
+          let number = value[..idx].parse().map_err(|_| corrupted())?;
+          let timestamp = value[idx + 1..].parse().map_err(|_| corrupted())?;
+          manifest.ingested.push((number, timestamp));
+        }
+        _ => {}
+      }
+    }
+
+    Ok(Some(manifest))
+  }
+
+  /// Writes the MANIFEST file within a directory.
+  ///
+  /// The MANIFEST is written to a temporary file first, which then replaces the MANIFEST file, so
+  /// a crash never leaves a partially written MANIFEST behind.
+  pub fn write(&self, dir: &Path) -> io::Result<()> {
+    let tmp_path = dir.join(MANIFEST_FILE.to_owned() + ".tmp");
+    let file = OpenOptions::new()
+      .write(true)
+      .create(true)
+      .truncate(true)
+      .open(&tmp_path)?;
+    let mut file = BufWriter::new(file);
+    writeln!(file, "next_table_number={}", self.next_table_number)?;
+    writeln!(file, "wal_number={}", self.wal_number)?;
+    for (column_family, number) in self.tables.iter() {
+      writeln!(file, "table={}:{}", column_family, number)?;
+    }
+    for (number, timestamp) in self.ingested.iter() {
+      writeln!(file, "ingested={}:{}", number, timestamp)?;
+    }
+    file.flush()?;
+    file.get_ref().sync_all()?;
+
+    rename(tmp_path, dir.join(MANIFEST_FILE))
+  }
+}
+
+/// Gets the path of a table file within a directory.
+pub fn table_path(dir: &Path, number: u64) -> PathBuf {
+  dir.join(number.to_string() + ".sst")
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::manifest::Manifest;
+  use rand::Rng;
+  use std::fs::{create_dir, remove_dir_all};
+  use std::path::PathBuf;
+
+  #[test]
+  fn test_manifest() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    assert_eq!(Manifest::read(&dir).unwrap(), None);
+    let manifest = Manifest {
+      next_table_number: 3,
+      wal_number: 1_600_000_000_000_000,
+      tables: vec![(0, 0), (1, 1), (0, 2)],
+      ingested: vec![(1, 1_600_000_000_000_001)],
+    };
+    manifest.write(&dir).unwrap();
+    assert_eq!(Manifest::read(&dir).unwrap(), Some(manifest));
+
+    remove_dir_all(&dir).unwrap();
+  }
+}
@@ -1,9 +1,21 @@
+use crate::comparator::Comparator;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
 /// MemTable entry.
+///
+/// Merge operands are stacked on top of the value, oldest first, and are combined with it on read.
+/// A value with an expiry time is hidden once the expiry time has passed. The value of a blob index
+/// entry is an encoded BlobIndex, pointing to the value in a blob file.
+#[derive(Clone)]
 pub struct MemTableEntry {
   pub key: Vec<u8>,
   pub value: Option<Vec<u8>>,
   pub timestamp: u128,
+  pub expires_at: Option<u128>,
   pub deleted: bool,
+  pub operands: Vec<Vec<u8>>,
+  pub blob_index: bool,
 }
 
 /// MemTable holds a sorted list of the latest written records.
@@ -13,32 +25,110 @@ pub struct MemTableEntry {
 /// MemTables have a max capacity and when that is reached, we flush the MemTable
 /// to disk as a Table(SSTable).
 ///
-/// Entries are stored in a Vector instead of a HashMap to support Scans.
+/// Entries are stored in a Vector instead of a HashMap to support Scans. They are sorted by the
+/// MemTable's Comparator.
+///
+/// Range tombstones are stored separately from the entries. Entries covered by a range tombstone
+/// are dropped when it is written, so any entry in the MemTable is newer than the range tombstones
+/// that cover it. The range tombstones are kept to shadow older records outside of the MemTable.
+#[derive(Clone)]
 pub struct MemTable {
+  comparator: Arc<dyn Comparator>,
   entries: Vec<MemTableEntry>,
+  range_tombstones: Vec<RangeTombstone>,
   size: usize,
 }
 
+/// Range tombstone deleting every key from `start` (inclusive) to `end` (exclusive).
+#[derive(Clone)]
+pub struct RangeTombstone {
+  pub start: Vec<u8>,
+  pub end: Vec<u8>,
+  pub timestamp: u128,
+}
+
+impl RangeTombstone {
+  /// Gets if the key is within the range of the tombstone.
+  pub fn covers(&self, key: &[u8], comparator: &dyn Comparator) -> bool {
+    comparator.compare(&self.start, key) != Ordering::Greater
+      && comparator.compare(key, &self.end) == Ordering::Less
+  }
+}
+
+impl MemTableEntry {
+  /// Creates a tombstone entry that deletes a key.
+  pub fn tombstone(key: &[u8], timestamp: u128) -> MemTableEntry {
+    MemTableEntry {
+      key: key.to_owned(),
+      value: None,
+      timestamp,
+      expires_at: None,
+      deleted: true,
+      operands: Vec::new(),
+      blob_index: false,
+    }
+  }
+
+  /// Gets if the entry only holds merge operands, which apply to an older entry of the key.
+  pub fn only_operands(&self) -> bool {
+    !self.deleted && self.value.is_none()
+  }
+
+  /// Gets if the value of the entry has expired at a time in microseconds since the UNIX epoch.
+  pub fn is_expired(&self, now: u128) -> bool {
+    self.expires_at.is_some_and(|expires_at| expires_at <= now)
+  }
+
+  /// Gets the total size of the merge operands stacked on the entry.
+  fn operands_size(&self) -> usize {
+    self.operands.iter().map(|o| o.len()).sum()
+  }
+}
+
 impl MemTable {
   /// Creates a new empty MemTable
+  #[cfg(test)]
   pub fn new() -> MemTable {
+    MemTable::with_comparator(Arc::new(crate::comparator::BytewiseComparator))
+  }
+
+  /// Creates a new empty MemTable that sorts its entries with a Comparator.
+  pub fn with_comparator(comparator: Arc<dyn Comparator>) -> MemTable {
     MemTable {
+      comparator,
       entries: Vec::new(),
+      range_tombstones: Vec::new(),
       size: 0,
     }
   }
 
   /// Sets a Key-Value pair in the MemTable.
+  #[cfg(test)]
   pub fn set(&mut self, key: &[u8], value: &[u8], timestamp: u128) {
+    self.set_with_expiry(key, value, timestamp, None);
+  }
+
+  /// Sets a Key-Value pair in the MemTable that expires at a time in microseconds since the UNIX epoch.
+  pub fn set_with_expiry(
+    &mut self,
+    key: &[u8],
+    value: &[u8],
+    timestamp: u128,
+    expires_at: Option<u128>,
+  ) {
     let entry = MemTableEntry {
       key: key.to_owned(),
       value: Some(value.to_owned()),
       timestamp,
+      expires_at,
       deleted: false,
+      operands: Vec::new(),
+      blob_index: false,
     };
 
     match self.get_index(key) {
       Ok(idx) => {
+        self.size -= self.entries[idx].operands_size();
         // If a Value existed on the deleted record, then add the difference of the new and old Value to the MemTable's size.
         if let Some(v) = self.entries[idx].value.as_ref() {
           if value.len() < v.len() {
@@ -58,6 +148,14 @@ impl MemTable {
     }
   }
 
+  /// Sets a Key-Value pair in the MemTable whose value is in a blob file.
+  pub fn set_blob_index(&mut self, key: &[u8], blob_index: &[u8], timestamp: u128) {
+    self.set_with_expiry(key, blob_index, timestamp, None);
+    if let Ok(idx) = self.get_index(key) {
+      self.entries[idx].blob_index = true;
+    }
+  }
+
   /// Deletes a Key-Value pair in the MemTable.
   ///
   /// This is achieved using tombstones.
@@ -65,11 +163,15 @@ impl MemTable {
     let entry = MemTableEntry {
       key: key.to_owned(),
       value: None,
-      timestamp: timestamp,
+      timestamp,
+      expires_at: None,
       deleted: true,
+      operands: Vec::new(),
+      blob_index: false,
     };
     match self.get_index(key) {
       Ok(idx) => {
+        self.size -= self.entries[idx].operands_size();
         // If a Value existed on the deleted record, then subtract the size of the Value from the MemTable.
         if let Some(value) = self.entries[idx].value.as_ref() {
           self.size -= value.len();
@@ -83,6 +185,59 @@ impl MemTable {
     }
   }
 
+  /// Deletes every Key-Value pair from `start` (inclusive) to `end` (exclusive) in the MemTable.
+  ///
+  /// The covered entries are dropped and a single range tombstone is recorded.
+  pub fn delete_range(&mut self, start: &[u8], end: &[u8], timestamp: u128) {
+    if self.comparator.compare(start, end) != Ordering::Less {
+      return;
+    }
+
+    let start_idx = self.get_index(start).unwrap_or_else(|idx| idx);
+    let end_idx = self.get_index(end).unwrap_or_else(|idx| idx);
+    for entry in self.entries.drain(start_idx..end_idx) {
+      // Decrease the size of the MemTable by the Key size, Value size, Operands size, Timestamp size (16 bytes), Tombstone size (1 byte).
+      self.size -= entry.key.len() + entry.operands_size() + 16 + 1;
+      if let Some(value) = entry.value.as_ref() {
+        self.size -= value.len();
+      }
+    }
+
+    self.size += start.len() + end.len() + 16; // Increase the size of the MemTable by the Start Key size, End Key size, Timestamp size (16 bytes).
+    self.range_tombstones.push(RangeTombstone {
+      start: start.to_owned(),
+      end: end.to_owned(),
+      timestamp,
+    });
+  }
+
+  /// Merges an operand into a Key-Value pair in the MemTable.
+  ///
+  /// The operand is stacked on top of the existing entry, if any, and is combined with it on read.
+  pub fn merge(&mut self, key: &[u8], operand: &[u8], timestamp: u128) {
+    match self.get_index(key) {
+      Ok(idx) => {
+        let entry = &mut self.entries[idx];
+        entry.operands.push(operand.to_owned());
+        entry.timestamp = timestamp;
+        self.size += operand.len();
+      }
+      Err(idx) => {
+        let entry = MemTableEntry {
+          key: key.to_owned(),
+          value: None,
+          timestamp,
+          expires_at: None,
+          deleted: false,
+          operands: vec![operand.to_owned()],
+          blob_index: false,
+        };
+        self.size += key.len() + operand.len() + 16 + 1; // Increase the size of the MemTable by the Key size, Operand size, Timestamp size (16 bytes), Tombstone size (1 byte).
+        self.entries.insert(idx, entry);
+      }
+    }
+  }
+
   /// Gets a Key-Value pair from the MemTable.alloc
   ///
   /// If no record with the same key exists in the MemTable, return None.
@@ -93,6 +248,21 @@ impl MemTable {
     None
   }
 
+  /// Gets the entries from `start` (inclusive) to `end` (exclusive).
+  pub fn range(&self, start: &[u8], end: &[u8]) -> &[MemTableEntry] {
+    let start_idx = self.get_index(start).unwrap_or_else(|idx| idx);
+    let end_idx = self.get_index(end).unwrap_or_else(|idx| idx);
+    if start_idx >= end_idx {
+      return &[];
+    }
+    &self.entries[start_idx..end_idx]
+  }
+
+  /// Gets the index of the first entry with a key at or after `key`.
+  pub fn seek_index(&self, key: &[u8]) -> usize {
+    self.get_index(key).unwrap_or_else(|idx| idx)
+  }
+
   /// Performs Binary Search to find a record in the MemTable.
   ///
   /// If the record is found `[Result::Ok]` is returned, with the index of record. If the record is not
@@ -100,7 +270,7 @@ impl MemTable {
   fn get_index(&self, key: &[u8]) -> Result<usize, usize> {
     self
       .entries
-      .binary_search_by_key(&key, |e| e.key.as_slice())
+      .binary_search_by(|e| self.comparator.compare(&e.key, key))
   }
 
   /// Gets the number of records in the MemTable.
@@ -113,6 +283,11 @@ impl MemTable {
     &self.entries
   }
 
+  /// Gets the range tombstones from the MemTable, oldest first.
+  pub fn range_tombstones(&self) -> &[RangeTombstone] {
+    &self.range_tombstones
+  }
+
   /// Gets the total size of the records in the MemTable
   pub fn size(&self) -> usize {
     self.size
@@ -121,7 +296,9 @@ impl MemTable {
 
 #[cfg(test)]
 mod tests {
+  use crate::comparator::{BytewiseComparator, ReverseBytewiseComparator};
   use crate::mem_table::MemTable;
+  use std::sync::Arc;
 
   #[test]
   fn test_mem_table_put_start() {
@@ -134,15 +311,15 @@ mod tests {
     assert_eq!(table.entries[0].key, b"Apple");
     assert_eq!(table.entries[0].value.as_ref().unwrap(), b"Apple Smoothie");
     assert_eq!(table.entries[0].timestamp, 20);
-    assert_eq!(table.entries[0].deleted, false);
+    assert!(!table.entries[0].deleted);
     assert_eq!(table.entries[1].key, b"Lime");
     assert_eq!(table.entries[1].value.as_ref().unwrap(), b"Lime Smoothie");
     assert_eq!(table.entries[1].timestamp, 0);
-    assert_eq!(table.entries[1].deleted, false);
+    assert!(!table.entries[1].deleted);
     assert_eq!(table.entries[2].key, b"Orange");
     assert_eq!(table.entries[2].value.as_ref().unwrap(), b"Orange Smoothie");
     assert_eq!(table.entries[2].timestamp, 10);
-    assert_eq!(table.entries[2].deleted, false);
+    assert!(!table.entries[2].deleted);
 
     assert_eq!(table.size, 108);
   }
@@ -158,15 +335,15 @@ mod tests {
     assert_eq!(table.entries[0].key, b"Apple");
     assert_eq!(table.entries[0].value.as_ref().unwrap(), b"Apple Smoothie");
     assert_eq!(table.entries[0].timestamp, 0);
-    assert_eq!(table.entries[0].deleted, false);
+    assert!(!table.entries[0].deleted);
     assert_eq!(table.entries[1].key, b"Lime");
     assert_eq!(table.entries[1].value.as_ref().unwrap(), b"Lime Smoothie");
     assert_eq!(table.entries[1].timestamp, 20);
-    assert_eq!(table.entries[1].deleted, false);
+    assert!(!table.entries[1].deleted);
     assert_eq!(table.entries[2].key, b"Orange");
     assert_eq!(table.entries[2].value.as_ref().unwrap(), b"Orange Smoothie");
     assert_eq!(table.entries[2].timestamp, 10);
-    assert_eq!(table.entries[2].deleted, false);
+    assert!(!table.entries[2].deleted);
 
     assert_eq!(table.size, 108);
   }
@@ -182,15 +359,15 @@ mod tests {
     assert_eq!(table.entries[0].key, b"Apple");
     assert_eq!(table.entries[0].value.as_ref().unwrap(), b"Apple Smoothie");
     assert_eq!(table.entries[0].timestamp, 0);
-    assert_eq!(table.entries[0].deleted, false);
+    assert!(!table.entries[0].deleted);
     assert_eq!(table.entries[1].key, b"Lime");
     assert_eq!(table.entries[1].value.as_ref().unwrap(), b"Lime Smoothie");
     assert_eq!(table.entries[1].timestamp, 10);
-    assert_eq!(table.entries[1].deleted, false);
+    assert!(!table.entries[1].deleted);
     assert_eq!(table.entries[2].key, b"Orange");
     assert_eq!(table.entries[2].value.as_ref().unwrap(), b"Orange Smoothie");
     assert_eq!(table.entries[2].timestamp, 20);
-    assert_eq!(table.entries[2].deleted, false);
+    assert!(!table.entries[2].deleted);
 
     assert_eq!(table.size, 108);
   }
@@ -207,15 +384,15 @@ mod tests {
     assert_eq!(table.entries[0].key, b"Apple");
     assert_eq!(table.entries[0].value.as_ref().unwrap(), b"Apple Smoothie");
     assert_eq!(table.entries[0].timestamp, 0);
-    assert_eq!(table.entries[0].deleted, false);
+    assert!(!table.entries[0].deleted);
     assert_eq!(table.entries[1].key, b"Lime");
     assert_eq!(table.entries[1].value.as_ref().unwrap(), b"A sour fruit");
     assert_eq!(table.entries[1].timestamp, 30);
-    assert_eq!(table.entries[1].deleted, false);
+    assert!(!table.entries[1].deleted);
     assert_eq!(table.entries[2].key, b"Orange");
     assert_eq!(table.entries[2].value.as_ref().unwrap(), b"Orange Smoothie");
     assert_eq!(table.entries[2].timestamp, 20);
-    assert_eq!(table.entries[2].deleted, false);
+    assert!(!table.entries[2].deleted);
 
     assert_eq!(table.size, 107);
   }
@@ -242,7 +419,7 @@ mod tests {
     table.set(b"Orange", b"Orange Smoothie", 0);
 
     let res = table.get(b"Potato");
-    assert_eq!(res.is_some(), false);
+    assert!(res.is_none());
   }
 
   #[test]
@@ -256,12 +433,12 @@ mod tests {
     assert_eq!(res.key, b"Apple");
     assert_eq!(res.value, None);
     assert_eq!(res.timestamp, 10);
-    assert_eq!(res.deleted, true);
+    assert!(res.deleted);
 
     assert_eq!(table.entries[0].key, b"Apple");
     assert_eq!(table.entries[0].value, None);
     assert_eq!(table.entries[0].timestamp, 10);
-    assert_eq!(table.entries[0].deleted, true);
+    assert!(table.entries[0].deleted);
 
     assert_eq!(table.size, 22);
   }
@@ -276,13 +453,101 @@ mod tests {
     assert_eq!(res.key, b"Apple");
     assert_eq!(res.value, None);
     assert_eq!(res.timestamp, 10);
-    assert_eq!(res.deleted, true);
+    assert!(res.deleted);
 
     assert_eq!(table.entries[0].key, b"Apple");
     assert_eq!(table.entries[0].value, None);
     assert_eq!(table.entries[0].timestamp, 10);
-    assert_eq!(table.entries[0].deleted, true);
+    assert!(table.entries[0].deleted);
 
     assert_eq!(table.size, 22);
   }
+
+  #[test]
+  fn test_mem_table_merge() {
+    let mut table = MemTable::new();
+    table.merge(b"Fruits", b"Apple", 0); // 6 + 5 + 16 + 1
+    table.merge(b"Fruits", b"Lime", 10); // 4
+
+    let res = table.get(b"Fruits").unwrap();
+    assert_eq!(res.value, None);
+    assert!(!res.deleted);
+    assert_eq!(res.operands, vec![b"Apple".to_vec(), b"Lime".to_vec()]);
+    assert_eq!(res.timestamp, 10);
+    assert_eq!(table.size, 32);
+
+    table.set(b"Fruits", b"Orange", 20);
+
+    let res = table.get(b"Fruits").unwrap();
+    assert_eq!(res.value.as_ref().unwrap(), b"Orange");
+    assert!(res.operands.is_empty());
+    assert_eq!(table.size, 29);
+
+    table.merge(b"Fruits", b"Mango", 30);
+    table.delete(b"Fruits", 40);
+
+    let res = table.get(b"Fruits").unwrap();
+    assert!(res.deleted);
+    assert!(res.operands.is_empty());
+    assert_eq!(table.size, 23);
+  }
+
+  #[test]
+  fn test_mem_table_delete_range() {
+    let mut table = MemTable::new();
+    table.set(b"Apple", b"Apple Smoothie", 0);
+    table.set(b"Lime", b"Lime Smoothie", 10);
+    table.merge(b"Mango", b"Mango Smoothie", 20);
+    table.set(b"Orange", b"Orange Smoothie", 30);
+
+    table.delete_range(b"Banana", b"Orange", 40); // 6 + 6 + 16
+
+    assert_eq!(table.entries.len(), 2);
+    assert_eq!(table.entries[0].key, b"Apple");
+    assert_eq!(table.entries[1].key, b"Orange");
+    assert!(table.get(b"Lime").is_none());
+    let tombstone = &table.range_tombstones()[0];
+    assert!(tombstone.covers(b"Lime", &BytewiseComparator));
+    assert!(tombstone.covers(b"Banana", &BytewiseComparator));
+    assert!(!tombstone.covers(b"Orange", &BytewiseComparator));
+    assert_eq!(table.size, 36 + 38 + 28);
+
+    table.set(b"Lime", b"Lime Smoothie", 50);
+    assert_eq!(table.get(b"Lime").unwrap().timestamp, 50);
+  }
+
+  #[test]
+  fn test_mem_table_put_expiry() {
+    let mut table = MemTable::new();
+    table.set_with_expiry(b"Apple", b"Apple Smoothie", 0, Some(100));
+    table.merge(b"Apple", b"Lime Smoothie", 10);
+
+    let res = table.get(b"Apple").unwrap();
+    assert_eq!(res.expires_at, Some(100));
+    assert!(!res.is_expired(99));
+    assert!(res.is_expired(100));
+
+    table.set(b"Apple", b"Apple Smoothie", 20);
+    assert!(!table.get(b"Apple").unwrap().is_expired(100));
+  }
+
+  #[test]
+  fn test_mem_table_comparator() {
+    let mut table = MemTable::with_comparator(Arc::new(ReverseBytewiseComparator));
+    table.set(b"Apple", b"Apple Smoothie", 0);
+    table.set(b"Orange", b"Orange Smoothie", 10);
+    table.set(b"Lime", b"Lime Smoothie", 20);
+
+    assert_eq!(table.entries[0].key, b"Orange");
+    assert_eq!(table.entries[1].key, b"Lime");
+    assert_eq!(table.entries[2].key, b"Apple");
+    assert_eq!(table.get(b"Lime").unwrap().timestamp, 20);
+
+    table.delete_range(b"Orange", b"Apple", 30);
+    assert_eq!(table.entries.len(), 1);
+    assert_eq!(table.entries[0].key, b"Apple");
+
+    table.delete_range(b"Apple", b"Orange", 40);
+    assert_eq!(table.range_tombstones.len(), 1);
+  }
 }
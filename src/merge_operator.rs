@@ -0,0 +1,168 @@
+/// MergeOperator combines a stack of merge operands with the existing value of a key.
+///
+/// Merges let read-modify-write operations, like counters and append-only lists, be written
+/// without reading the current value first. Operands are stacked in the MemTable and combined
+/// lazily when the key is read, and when it is flushed or compacted.
+pub trait MergeOperator: Send + Sync {
+  /// Gets the name of the MergeOperator.
+  fn name(&self) -> &str;
+
+  /// Combines the operands, oldest first, with the existing value of a key.
+  ///
+  /// `existing` is `None` if the key did not exist before the first operand was written.
+  fn full_merge(&self, key: &[u8], existing: Option<&[u8]>, operands: &[Vec<u8>]) -> Vec<u8>;
+
+  /// Combines the operands, oldest first, into a single operand that has the same effect on any
+  /// existing value, or returns `None` if they cannot be combined without it.
+  ///
+  /// Flushes and compactions combine the operands of a key with it when the value they apply to
+  /// is not at hand. The default keeps the operands apart.
+  fn partial_merge(&self, _key: &[u8], _operands: &[Vec<u8>]) -> Option<Vec<u8>> {
+    None
+  }
+}
+
+/// Adds 64-bit unsigned integers, encoded as 8 little-endian bytes, with wrapping overflow.
+///
+/// Values and operands that are not 8 bytes long are treated as 0.
+pub struct U64AddOperator;
+
+impl U64AddOperator {
+  fn decode(value: &[u8]) -> u64 {
+    let mut buffer = [0; 8];
+    if value.len() == buffer.len() {
+      buffer.copy_from_slice(value);
+    }
+    u64::from_le_bytes(buffer)
+  }
+}
+
+impl MergeOperator for U64AddOperator {
+  fn name(&self) -> &str {
+    "U64AddOperator"
+  }
+
+  fn full_merge(&self, _key: &[u8], existing: Option<&[u8]>, operands: &[Vec<u8>]) -> Vec<u8> {
+    let mut sum = existing.map(U64AddOperator::decode).unwrap_or(0);
+    for operand in operands {
+      sum = sum.wrapping_add(U64AddOperator::decode(operand));
+    }
+    sum.to_le_bytes().to_vec()
+  }
+
+  fn partial_merge(&self, key: &[u8], operands: &[Vec<u8>]) -> Option<Vec<u8>> {
+    Some(self.full_merge(key, None, operands))
+  }
+}
+
+/// Keeps the largest value by lexicographic byte ordering.
+pub struct MaxOperator;
+
+impl MergeOperator for MaxOperator {
+  fn name(&self) -> &str {
+    "MaxOperator"
+  }
+
+  fn full_merge(&self, _key: &[u8], existing: Option<&[u8]>, operands: &[Vec<u8>]) -> Vec<u8> {
+    operands
+      .iter()
+      .map(|o| o.as_slice())
+      .chain(existing)
+      .max()
+      .unwrap_or_default()
+      .to_vec()
+  }
+
+  fn partial_merge(&self, key: &[u8], operands: &[Vec<u8>]) -> Option<Vec<u8>> {
+    Some(self.full_merge(key, None, operands))
+  }
+}
+
+/// Appends each operand to the existing value, separated by a delimiter.
+pub struct StringAppendOperator {
+  delimiter: Vec<u8>,
+}
+
+impl StringAppendOperator {
+  /// Creates a new StringAppendOperator with a delimiter.
+  pub fn new(delimiter: &[u8]) -> StringAppendOperator {
+    StringAppendOperator {
+      delimiter: delimiter.to_owned(),
+    }
+  }
+}
+
+impl MergeOperator for StringAppendOperator {
+  fn name(&self) -> &str {
+    "StringAppendOperator"
+  }
+
+  fn full_merge(&self, _key: &[u8], existing: Option<&[u8]>, operands: &[Vec<u8>]) -> Vec<u8> {
+    let mut value = existing.map(|v| v.to_vec());
+    for operand in operands {
+      match value.as_mut() {
+        Some(v) => {
+          v.extend_from_slice(&self.delimiter);
+          v.extend_from_slice(operand);
+        }
+        None => value = Some(operand.clone()),
+      }
+    }
+    value.unwrap_or_default()
+  }
+
+  fn partial_merge(&self, key: &[u8], operands: &[Vec<u8>]) -> Option<Vec<u8>> {
+    Some(self.full_merge(key, None, operands))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::merge_operator::{MaxOperator, MergeOperator, StringAppendOperator, U64AddOperator};
+
+  #[test]
+  fn test_u64_add() {
+    let op = U64AddOperator;
+    let operands = vec![5u64.to_le_bytes().to_vec(), 7u64.to_le_bytes().to_vec()];
+
+    let res = op.full_merge(b"Count", None, &operands);
+    assert_eq!(res, 12u64.to_le_bytes());
+
+    let res = op.full_merge(b"Count", Some(&30u64.to_le_bytes()), &operands);
+    assert_eq!(res, 42u64.to_le_bytes());
+
+    let operand = op.partial_merge(b"Count", &operands).unwrap();
+    assert_eq!(operand, 12u64.to_le_bytes());
+  }
+
+  #[test]
+  fn test_max() {
+    let op = MaxOperator;
+    let operands = vec![b"Lime".to_vec(), b"Apple".to_vec()];
+
+    assert_eq!(op.full_merge(b"Fruit", None, &operands), b"Lime");
+    assert_eq!(
+      op.full_merge(b"Fruit", Some(b"Orange"), &operands),
+      b"Orange"
+    );
+    assert_eq!(op.partial_merge(b"Fruit", &operands).unwrap(), b"Lime");
+  }
+
+  #[test]
+  fn test_string_append() {
+    let op = StringAppendOperator::new(b",");
+    let operands = vec![b"Lime".to_vec(), b"Orange".to_vec()];
+
+    assert_eq!(op.full_merge(b"Fruits", None, &operands), b"Lime,Orange");
+    assert_eq!(
+      op.full_merge(b"Fruits", Some(b"Apple"), &operands),
+      b"Apple,Lime,Orange"
+    );
+
+    let operand = op.partial_merge(b"Fruits", &operands).unwrap();
+    assert_eq!(
+      op.full_merge(b"Fruits", Some(b"Apple"), &[operand]),
+      b"Apple,Lime,Orange"
+    );
+  }
+}
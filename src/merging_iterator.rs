@@ -0,0 +1,376 @@
+use crate::column_family::TableFile;
+use crate::comparator::Comparator;
+use crate::mem_table::{MemTable, MemTableEntry, RangeTombstone};
+use crate::table::TableIterator;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::io;
+use std::sync::Arc;
+
+/// Sorted source of entries merged by a MergingIterator: the MemTable or a table.
+///
+/// A source shares the MemTable or table it reads, so it stays readable as the column family
+/// moves on.
+pub(crate) enum Source {
+  MemTable {
+    mem_table: Arc<MemTable>,
+    pos: usize,
+  },
+  Table {
+    table_file: TableFile,
+    iter: Box<TableIterator>,
+  },
+}
+
+impl Source {
+  pub fn valid(&self) -> bool {
+    match self {
+      Source::MemTable { mem_table, pos } => *pos < mem_table.len(),
+      Source::Table { iter, .. } => iter.valid(),
+    }
+  }
+
+  pub fn key(&self) -> &[u8] {
+    match self {
+      Source::MemTable { mem_table, pos } => &mem_table.entries()[*pos].key,
+      Source::Table { iter, .. } => iter.key(),
+    }
+  }
+
+  /// Gets the entry the source is positioned at, decoding it if it was read from a table.
+  pub fn entry(&self) -> io::Result<MemTableEntry> {
+    match self {
+      Source::MemTable { mem_table, pos } => Ok(mem_table.entries()[*pos].clone()),
+      Source::Table { table_file, iter } => table_file.decode_entry(iter.key(), iter.value()),
+    }
+  }
+
+  /// Gets the range tombstones of the source, which delete the entries of older sources.
+  pub fn range_tombstones(&self) -> &[RangeTombstone] {
+    match self {
+      Source::MemTable { mem_table, .. } => mem_table.range_tombstones(),
+      Source::Table { table_file, .. } => &table_file.range_tombstones,
+    }
+  }
+
+  pub fn status(&self) -> Option<&io::Error> {
+    match self {
+      Source::MemTable { .. } => None,
+      Source::Table { iter, .. } => iter.status(),
+    }
+  }
+
+  pub fn seek_to_first(&mut self) {
+    match self {
+      Source::MemTable { pos, .. } => *pos = 0,
+      Source::Table { iter, .. } => iter.seek_to_first(),
+    }
+  }
+
+  pub fn seek_to_last(&mut self) {
+    match self {
+      Source::MemTable { mem_table, pos } => *pos = mem_table.len().saturating_sub(1),
+      Source::Table { iter, .. } => iter.seek_to_last(),
+    }
+  }
+
+  pub fn seek(&mut self, target: &[u8]) {
+    match self {
+      Source::MemTable { mem_table, pos } => *pos = mem_table.seek_index(target),
+      Source::Table { iter, .. } => iter.seek(target),
+    }
+  }
+
+  pub fn seek_for_prev(&mut self, target: &[u8]) {
+    match self {
+      Source::MemTable { mem_table, pos } => {
+        *pos = match mem_table.get(target) {
+          Some(_) => mem_table.seek_index(target),
+          None => match mem_table.seek_index(target) {
+            0 => mem_table.len(),
+            idx => idx - 1,
+          },
+        }
+      }
+      Source::Table { iter, .. } => iter.seek_for_prev(target),
+    }
+  }
+
+  pub fn next(&mut self) {
+    match self {
+      Source::MemTable { pos, .. } => *pos += 1,
+      Source::Table { iter, .. } => iter.next(),
+    }
+  }
+
+  pub fn prev(&mut self) {
+    match self {
+      Source::MemTable { mem_table, pos } => {
+        *pos = match *pos {
+          0 => mem_table.len(),
+          pos => pos - 1,
+        }
+      }
+      Source::Table { iter, .. } => iter.prev(),
+    }
+  }
+}
+
+/// Direction a MergingIterator moves in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+  Forward,
+  Reverse,
+}
+
+/// Source positioned at a key, in the heap of a MergingIterator.
+struct HeapItem<'a> {
+  key: Vec<u8>,
+  source: usize,
+  comparator: &'a dyn Comparator,
+  direction: Direction,
+}
+
+impl Ord for HeapItem<'_> {
+  /// Orders the next key in the direction of the iterator first, and the newest source first for
+  /// the same key.
+  fn cmp(&self, other: &Self) -> Ordering {
+    let order = self.comparator.compare(&self.key, &other.key);
+    let order = match self.direction {
+      Direction::Forward => order.reverse(),
+      Direction::Reverse => order,
+    };
+    order.then_with(|| other.source.cmp(&self.source))
+  }
+}
+
+impl PartialOrd for HeapItem<'_> {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl PartialEq for HeapItem<'_> {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for HeapItem<'_> {}
+
+/// K-way merge of sorted sources, ordered newest first, through a binary heap of their positions.
+///
+/// The iterator moves from key to key. `pop` takes the next key off the heap, leaving the sources
+/// positioned at it as the current sources until `advance` moves them past it. A key is found in
+/// each source at most once, so the current sources hold every entry of the key.
+pub(crate) struct MergingIterator<'a> {
+  sources: Vec<Source>,
+  comparator: &'a dyn Comparator,
+  direction: Direction,
+  heap: BinaryHeap<HeapItem<'a>>,
+  current: Vec<usize>,
+}
+
+impl<'a> MergingIterator<'a> {
+  /// Creates a new MergingIterator over sources ordered newest first, which is not positioned
+  /// until it is seeked.
+  pub fn new(sources: Vec<Source>, comparator: &'a dyn Comparator) -> MergingIterator<'a> {
+    MergingIterator {
+      sources,
+      comparator,
+      direction: Direction::Forward,
+      heap: BinaryHeap::new(),
+      current: Vec::new(),
+    }
+  }
+
+  pub fn direction(&self) -> Direction {
+    self.direction
+  }
+
+  /// Gets the first error of a source, which stops the iterator.
+  pub fn status(&self) -> Option<&io::Error> {
+    self.sources.iter().find_map(|s| s.status())
+  }
+
+  /// Gets the next key, without moving past it.
+  pub fn key(&self) -> Option<&[u8]> {
+    self.heap.peek().map(|item| &item.key[..])
+  }
+
+  pub fn seek_to_first(&mut self) {
+    self.sources.iter_mut().for_each(|s| s.seek_to_first());
+    self.rebuild(Direction::Forward);
+  }
+
+  pub fn seek_to_last(&mut self) {
+    self.sources.iter_mut().for_each(|s| s.seek_to_last());
+    self.rebuild(Direction::Reverse);
+  }
+
+  /// Positions the sources at the first key at or after the target.
+  pub fn seek(&mut self, target: &[u8]) {
+    self.sources.iter_mut().for_each(|s| s.seek(target));
+    self.rebuild(Direction::Forward);
+  }
+
+  /// Positions the sources at the last key at or before the target.
+  pub fn seek_for_prev(&mut self, target: &[u8]) {
+    self
+      .sources
+      .iter_mut()
+      .for_each(|s| s.seek_for_prev(target));
+    self.rebuild(Direction::Reverse);
+  }
+
+  /// Takes the next key off the heap, making the sources positioned at it the current sources.
+  ///
+  /// Returns `None` once every source is exhausted.
+  pub fn pop(&mut self) -> Option<Vec<u8>> {
+    self.advance();
+    let item = self.heap.pop()?;
+    self.current.push(item.source);
+    while self
+      .heap
+      .peek()
+      .is_some_and(|next| self.comparator.compare(&next.key, &item.key) == Ordering::Equal)
+    {
+      self.current.push(self.heap.pop().unwrap().source);
+    }
+    Some(item.key)
+  }
+
+  /// Gets the entries of the last popped key from the current sources, newest first, like
+  /// `ColumnFamily::get_entries`.
+  ///
+  /// The entries stop at the first entry that holds a value or a tombstone, and a key deleted by
+  /// the range tombstones of a source gets a tombstone entry.
+  pub fn entries(&self, key: &[u8]) -> io::Result<Vec<MemTableEntry>> {
+    let mut entries = Vec::new();
+    for (i, source) in self.sources.iter().enumerate() {
+      if self.current.contains(&i) {
+        let entry = source.entry()?;
+        let only_operands = entry.only_operands();
+        entries.push(entry);
+        if !only_operands {
+          break;
+        }
+      }
+      if let Some(tombstone) = source
+        .range_tombstones()
+        .iter()
+        .find(|t| t.covers(key, self.comparator))
+      {
+        entries.push(MemTableEntry::tombstone(key, tombstone.timestamp));
+        break;
+      }
+    }
+    Ok(entries)
+  }
+
+  /// Gets the entries of the last popped key from every current source, newest first, including
+  /// the entries hidden by newer entries or range tombstones.
+  pub fn all_entries(&self) -> io::Result<Vec<MemTableEntry>> {
+    self
+      .sources
+      .iter()
+      .enumerate()
+      .filter(|(i, _)| self.current.contains(i))
+      .map(|(_, source)| source.entry())
+      .collect()
+  }
+
+  /// Moves the current sources past their key, in the direction of the iterator.
+  pub fn advance(&mut self) {
+    for i in std::mem::take(&mut self.current) {
+      match self.direction {
+        Direction::Forward => self.sources[i].next(),
+        Direction::Reverse => self.sources[i].prev(),
+      }
+      self.push(i);
+    }
+  }
+
+  /// Moves past a key if it is the next key, such as after re-seeking to change direction.
+  pub fn skip(&mut self, key: &[u8]) {
+    if self
+      .key()
+      .is_some_and(|k| self.comparator.compare(k, key) == Ordering::Equal)
+    {
+      self.pop();
+      self.advance();
+    }
+  }
+
+  /// Rebuilds the heap from the positions of the sources.
+  fn rebuild(&mut self, direction: Direction) {
+    self.direction = direction;
+    self.heap.clear();
+    self.current.clear();
+    for i in 0..self.sources.len() {
+      self.push(i);
+    }
+  }
+
+  /// Adds a source to the heap if it is positioned at a key.
+  fn push(&mut self, i: usize) {
+    if self.sources[i].valid() {
+      self.heap.push(HeapItem {
+        key: self.sources[i].key().to_vec(),
+        source: i,
+        comparator: self.comparator,
+        direction: self.direction,
+      });
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::comparator::BytewiseComparator;
+  use crate::mem_table::MemTable;
+  use crate::merging_iterator::{MergingIterator, Source};
+  use std::sync::Arc;
+
+  #[test]
+  fn test_merging_iterator() {
+    let mut newer = MemTable::new();
+    newer.set(b"Apple", b"Apple Pie", 3);
+    newer.set(b"Lime", b"Lime Smoothie", 4);
+    let mut older = MemTable::new();
+    older.set(b"Apple", b"Apple Juice", 1);
+    older.set(b"Cherry", b"Cherry Pie", 2);
+
+    let sources = vec![
+      Source::MemTable {
+        mem_table: Arc::new(newer),
+        pos: 0,
+      },
+      Source::MemTable {
+        mem_table: Arc::new(older),
+        pos: 0,
+      },
+    ];
+    let mut iter = MergingIterator::new(sources, &BytewiseComparator);
+
+    iter.seek_to_first();
+    assert_eq!(iter.pop().unwrap(), b"Apple");
+    let entries = iter.entries(b"Apple").unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].value.as_deref(), Some(&b"Apple Pie"[..]));
+    assert_eq!(iter.pop().unwrap(), b"Cherry");
+    assert_eq!(iter.entries(b"Cherry").unwrap()[0].timestamp, 2);
+    assert_eq!(iter.pop().unwrap(), b"Lime");
+    assert!(iter.pop().is_none());
+
+    iter.seek_for_prev(b"Banana");
+    assert_eq!(iter.pop().unwrap(), b"Apple");
+    assert!(iter.pop().is_none());
+
+    iter.seek_to_last();
+    iter.skip(b"Lime");
+    assert_eq!(iter.pop().unwrap(), b"Cherry");
+    assert_eq!(iter.pop().unwrap(), b"Apple");
+    assert_eq!(iter.entries(b"Apple").unwrap()[0].timestamp, 3);
+  }
+}
@@ -0,0 +1,691 @@
+use crate::block_cache::BlockCache;
+use crate::comparator::{BytewiseComparator, Comparator};
+use crate::compression::{builtin_compressors, Compressor, NoCompressor};
+use crate::snapshot::Snapshot;
+use std::collections::BTreeMap;
+use std::io;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// How writes to the WAL are made durable before they are acknowledged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncMode {
+  /// Writes are buffered in memory and only reach the WAL file when the buffer fills up or the
+  /// Database is closed. A crash of the process can lose the most recent writes.
+  Buffered,
+  /// Writes are flushed to the operating system after every write. A crash of the machine can lose
+  /// the most recent writes.
+  Flush,
+  /// Writes are flushed and synced to disk after every write.
+  Fsync,
+}
+
+impl SyncMode {
+  fn name(&self) -> &'static str {
+    match self {
+      SyncMode::Buffered => "buffered",
+      SyncMode::Flush => "flush",
+      SyncMode::Fsync => "fsync",
+    }
+  }
+}
+
+impl FromStr for SyncMode {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<SyncMode, ()> {
+    match s {
+      "buffered" => Ok(SyncMode::Buffered),
+      "flush" => Ok(SyncMode::Flush),
+      "fsync" => Ok(SyncMode::Fsync),
+      _ => Err(()),
+    }
+  }
+}
+
+/// Access pattern hint given to the operating system for memory-mapped table files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MmapAdvice {
+  /// No special treatment.
+  Normal,
+  /// Pages are read in random order, so read-ahead is disabled. Suits point lookups.
+  Random,
+  /// Pages are read in order, so read-ahead is aggressive. Suits scans.
+  Sequential,
+  /// The whole file will be needed soon, so it is read in ahead of time.
+  WillNeed,
+}
+
+impl MmapAdvice {
+  fn name(&self) -> &'static str {
+    match self {
+      MmapAdvice::Normal => "normal",
+      MmapAdvice::Random => "random",
+      MmapAdvice::Sequential => "sequential",
+      MmapAdvice::WillNeed => "willneed",
+    }
+  }
+}
+
+impl FromStr for MmapAdvice {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<MmapAdvice, ()> {
+    match s {
+      "normal" => Ok(MmapAdvice::Normal),
+      "random" => Ok(MmapAdvice::Random),
+      "sequential" => Ok(MmapAdvice::Sequential),
+      "willneed" => Ok(MmapAdvice::WillNeed),
+      _ => Err(()),
+    }
+  }
+}
+
+/// Options for opening a Database.
+///
+/// Options are built by chaining setters on `Options::new()`. They are persisted to the OPTIONS
+/// file in the Database's directory, and validated against it when the Database is reopened.
+#[derive(Clone)]
+pub struct Options {
+  pub(crate) create_if_missing: bool,
+  pub(crate) error_if_exists: bool,
+  pub(crate) paranoid_checks: bool,
+  pub(crate) memtable_size: usize,
+  pub(crate) sync_mode: SyncMode,
+  pub(crate) compression: Arc<dyn Compressor>,
+  pub(crate) compaction_compression: Option<Arc<dyn Compressor>>,
+  pub(crate) compression_dictionary_size: usize,
+  pub(crate) block_size: usize,
+  pub(crate) partition_size: usize,
+  pub(crate) bloom_bits_per_key: usize,
+  pub(crate) block_cache_size: usize,
+  pub(crate) block_cache: Option<Arc<BlockCache>>,
+  pub(crate) pin_index_and_filter_blocks: bool,
+  pub(crate) use_mmap_reads: bool,
+  pub(crate) use_direct_reads: bool,
+  pub(crate) use_direct_io_for_flush_and_compaction: bool,
+  pub(crate) mmap_advice: MmapAdvice,
+  pub(crate) enable_blob_files: bool,
+  pub(crate) min_blob_size: usize,
+  pub(crate) blob_file_size: u64,
+  pub(crate) blob_garbage_collection_ratio: f64,
+  pub(crate) comparator: Arc<dyn Comparator>,
+}
+
+impl Default for Options {
+  fn default() -> Options {
+    Options {
+      create_if_missing: true,
+      error_if_exists: false,
+      paranoid_checks: false,
+      memtable_size: 4 * 1024 * 1024,
+      sync_mode: SyncMode::Flush,
+      compression: Arc::new(NoCompressor),
+      compaction_compression: None,
+      compression_dictionary_size: 0,
+      block_size: 4096,
+      partition_size: 4096,
+      bloom_bits_per_key: 10,
+      block_cache_size: 8 * 1024 * 1024,
+      block_cache: None,
+      pin_index_and_filter_blocks: false,
+      use_mmap_reads: false,
+      use_direct_reads: false,
+      use_direct_io_for_flush_and_compaction: false,
+      mmap_advice: MmapAdvice::Random,
+      enable_blob_files: false,
+      min_blob_size: 4096,
+      blob_file_size: 256 * 1024 * 1024,
+      blob_garbage_collection_ratio: 0.5,
+      comparator: Arc::new(BytewiseComparator),
+    }
+  }
+}
+
+impl Options {
+  /// Creates new Options with the default values.
+  pub fn new() -> Options {
+    Options::default()
+  }
+
+  /// Creates the Database's directory if it does not exist. Defaults to `true`.
+  pub fn create_if_missing(mut self, create_if_missing: bool) -> Options {
+    self.create_if_missing = create_if_missing;
+    self
+  }
+
+  /// Fails to open the Database if it already exists. Defaults to `false`.
+  pub fn error_if_exists(mut self, error_if_exists: bool) -> Options {
+    self.error_if_exists = error_if_exists;
+    self
+  }
+
+  /// Fails to open the Database if the WAL is corrupted, instead of recovering every record
+  /// before the corruption. Defaults to `false`.
+  pub fn paranoid_checks(mut self, paranoid_checks: bool) -> Options {
+    self.paranoid_checks = paranoid_checks;
+    self
+  }
+
+  /// Sets the size in bytes a MemTable may grow to before it is flushed to disk. Defaults to 4 MiB.
+  ///
+  /// The MemTables of every column family are flushed to tables together, once their total size
+  /// reaches the limit.
+  pub fn memtable_size(mut self, memtable_size: usize) -> Options {
+    self.memtable_size = memtable_size;
+    self
+  }
+
+  /// Sets how writes to the WAL are made durable. Defaults to `SyncMode::Flush`.
+  pub fn sync_mode(mut self, sync_mode: SyncMode) -> Options {
+    self.sync_mode = sync_mode;
+    self
+  }
+
+  /// Sets the Compressor of the blocks of on-disk tables. Defaults to `NoCompressor`.
+  ///
+  /// Tables written by compactions use the `compaction_compression` Compressor instead, if it is
+  /// set.
+  pub fn compression(mut self, compression: Arc<dyn Compressor>) -> Options {
+    self.compression = compression;
+    self
+  }
+
+  /// Sets the Compressor of the blocks of tables written by compactions. Defaults to the
+  /// `compression` Compressor.
+  ///
+  /// The tables of a Database are not organized into levels. Instead, the small, recently flushed
+  /// tables stand in for the upper levels, and are commonly left uncompressed for speed, while the
+  /// large compacted tables stand in for the lower levels, and are compressed. Each table records
+  /// the name of its Compressor, and each block the id of its codec, so tables written with
+  /// different Compressors stay readable.
+  pub fn compaction_compression(mut self, compaction_compression: Arc<dyn Compressor>) -> Options {
+    self.compaction_compression = Some(compaction_compression);
+    self
+  }
+
+  /// Gets the Compressor of the tables written by compactions.
+  pub(crate) fn compaction_compressor(&self) -> Arc<dyn Compressor> {
+    self
+      .compaction_compression
+      .clone()
+      .unwrap_or_else(|| self.compression.clone())
+  }
+
+  /// Sets the size in bytes of the compression dictionary of each table. Defaults to 0, which
+  /// disables dictionaries.
+  ///
+  /// When a table is written, values are sampled to train a dictionary that is stored in the table
+  /// and used to compress every block of it. Dictionaries help small values that share structure,
+  /// which compress poorly one at a time.
+  pub fn compression_dictionary_size(mut self, compression_dictionary_size: usize) -> Options {
+    self.compression_dictionary_size = compression_dictionary_size;
+    self
+  }
+
+  /// Gets every Compressor that blocks may be decompressed with: the configured Compressors
+  /// followed by the built-in Compressors.
+  pub(crate) fn compressors(&self) -> Vec<Arc<dyn Compressor>> {
+    let mut compressors = vec![self.compression.clone()];
+    compressors.extend(self.compaction_compression.iter().cloned());
+    compressors.extend(builtin_compressors());
+    compressors
+  }
+
+  /// Sets the size in bytes of the uncompressed data blocks of tables. Defaults to 4 KiB.
+  pub fn block_size(mut self, block_size: usize) -> Options {
+    self.block_size = block_size;
+    self
+  }
+
+  /// Sets the size in bytes of the index partitions of tables. Defaults to 4 KiB.
+  ///
+  /// The index of a table is split into partitions, each with a bloom filter partition covering
+  /// the same keys. Only a small top-level index stays in memory, and partitions are read through
+  /// the block cache as they are needed, so huge tables do not need their whole index in memory.
+  pub fn partition_size(mut self, partition_size: usize) -> Options {
+    self.partition_size = partition_size;
+    self
+  }
+
+  /// Sets the number of bloom filter bits per key of tables, or `0` to disable bloom filters.
+  /// Defaults to `10`, which gives about 1% false positives.
+  pub fn bloom_bits_per_key(mut self, bloom_bits_per_key: usize) -> Options {
+    self.bloom_bits_per_key = bloom_bits_per_key;
+    self
+  }
+
+  /// Sets the capacity in bytes of the block cache. Defaults to 8 MiB.
+  ///
+  /// Ignored if a shared BlockCache is set.
+  pub fn block_cache_size(mut self, block_cache_size: usize) -> Options {
+    self.block_cache_size = block_cache_size;
+    self
+  }
+
+  /// Sets a BlockCache that is shared with other Databases, instead of creating a new BlockCache.
+  pub fn block_cache(mut self, block_cache: Arc<BlockCache>) -> Options {
+    self.block_cache = Some(block_cache);
+    self
+  }
+
+  /// Pins the index and filter partitions of every open table in the block cache. Defaults to
+  /// `false`.
+  ///
+  /// The partitions are read when a table is opened, so point lookups only read data blocks from
+  /// disk. Pinned blocks count towards the usage of the block cache, but are never evicted, and are
+  /// released when their table is closed.
+  pub fn pin_index_and_filter_blocks(mut self, pin_index_and_filter_blocks: bool) -> Options {
+    self.pin_index_and_filter_blocks = pin_index_and_filter_blocks;
+    self
+  }
+
+  /// Reads table files through a read-only memory mapping instead of buffered file reads. Defaults
+  /// to `false`.
+  ///
+  /// Blocks are checksummed straight from the mapped pages, without a read system call. Decoding
+  /// still copies each block into its own buffer, so reads are not zero-copy. Table files that
+  /// cannot be mapped fall back to buffered reads.
+  pub fn use_mmap_reads(mut self, use_mmap_reads: bool) -> Options {
+    self.use_mmap_reads = use_mmap_reads;
+    self
+  }
+
+  /// Sets the access pattern hint for memory-mapped table files. Defaults to `MmapAdvice::Random`.
+  pub fn mmap_advice(mut self, mmap_advice: MmapAdvice) -> Options {
+    self.mmap_advice = mmap_advice;
+    self
+  }
+
+  /// Reads table files with direct I/O(`O_DIRECT`), bypassing the page cache. Defaults to `false`.
+  ///
+  /// Blocks are read into aligned buffers, so they are only cached in the block cache. Cannot be
+  /// combined with `use_mmap_reads`. Table files that cannot be opened with direct I/O fall back to
+  /// buffered reads. The WAL always uses buffered I/O.
+  pub fn use_direct_reads(mut self, use_direct_reads: bool) -> Options {
+    self.use_direct_reads = use_direct_reads;
+    self
+  }
+
+  /// Writes the tables of flushes and compactions, and reads the input tables of compactions, with
+  /// direct I/O(`O_DIRECT`). Defaults to `false`.
+  ///
+  /// Background writes then do not evict hot data from the page cache. Table files are written
+  /// through an aligned buffer, and the last block is padded and truncated when the table is
+  /// finished.
+  pub fn use_direct_io_for_flush_and_compaction(
+    mut self,
+    use_direct_io_for_flush_and_compaction: bool,
+  ) -> Options {
+    self.use_direct_io_for_flush_and_compaction = use_direct_io_for_flush_and_compaction;
+    self
+  }
+
+  /// Separates values of at least `min_blob_size` bytes from their keys, and writes them to blob
+  /// files. Defaults to `false`.
+  ///
+  /// The MemTable and on-disk tables only hold a pointer to a separated value, so large values are
+  /// not rewritten as tables are compacted. Values with a time to live and values written by
+  /// Transactions are never separated.
+  pub fn enable_blob_files(mut self, enable_blob_files: bool) -> Options {
+    self.enable_blob_files = enable_blob_files;
+    self
+  }
+
+  /// Sets the size in bytes of the smallest value that is written to a blob file. Defaults to 4 KiB.
+  pub fn min_blob_size(mut self, min_blob_size: usize) -> Options {
+    self.min_blob_size = min_blob_size;
+    self
+  }
+
+  /// Sets the size in bytes a blob file may grow to before a new blob file is started. Defaults to
+  /// 256 MiB.
+  pub fn blob_file_size(mut self, blob_file_size: u64) -> Options {
+    self.blob_file_size = blob_file_size;
+    self
+  }
+
+  /// Sets the fraction of a blob file that must be garbage before garbage collection rewrites its
+  /// live values and deletes it. Defaults to 0.5.
+  pub fn blob_garbage_collection_ratio(mut self, blob_garbage_collection_ratio: f64) -> Options {
+    self.blob_garbage_collection_ratio = blob_garbage_collection_ratio;
+    self
+  }
+
+  /// Sets the Comparator that orders keys. Defaults to `BytewiseComparator`.
+  pub fn comparator(mut self, comparator: Arc<dyn Comparator>) -> Options {
+    self.comparator = comparator;
+    self
+  }
+
+  /// Converts the Options to the `name=value` pairs of an OPTIONS file.
+  pub(crate) fn to_options_file(&self) -> BTreeMap<String, String> {
+    let mut options = BTreeMap::new();
+    options.insert("comparator".to_owned(), self.comparator.name().to_owned());
+    options.insert(
+      "paranoid_checks".to_owned(),
+      self.paranoid_checks.to_string(),
+    );
+    options.insert("memtable_size".to_owned(), self.memtable_size.to_string());
+    options.insert("sync_mode".to_owned(), self.sync_mode.name().to_owned());
+    options.insert("compression".to_owned(), self.compression.name().to_owned());
+    options.insert(
+      "compaction_compression".to_owned(),
+      self.compaction_compressor().name().to_owned(),
+    );
+    options.insert(
+      "compression_dictionary_size".to_owned(),
+      self.compression_dictionary_size.to_string(),
+    );
+    options.insert("block_size".to_owned(), self.block_size.to_string());
+    options.insert("partition_size".to_owned(), self.partition_size.to_string());
+    options.insert(
+      "bloom_bits_per_key".to_owned(),
+      self.bloom_bits_per_key.to_string(),
+    );
+    options.insert(
+      "enable_blob_files".to_owned(),
+      self.enable_blob_files.to_string(),
+    );
+    options.insert("min_blob_size".to_owned(), self.min_blob_size.to_string());
+    options.insert("blob_file_size".to_owned(), self.blob_file_size.to_string());
+    options.insert(
+      "blob_garbage_collection_ratio".to_owned(),
+      self.blob_garbage_collection_ratio.to_string(),
+    );
+    options.insert(
+      "block_cache_size".to_owned(),
+      self.block_cache_size.to_string(),
+    );
+    options.insert(
+      "pin_index_and_filter_blocks".to_owned(),
+      self.pin_index_and_filter_blocks.to_string(),
+    );
+    options.insert("use_mmap_reads".to_owned(), self.use_mmap_reads.to_string());
+    options.insert(
+      "use_direct_reads".to_owned(),
+      self.use_direct_reads.to_string(),
+    );
+    options.insert(
+      "use_direct_io_for_flush_and_compaction".to_owned(),
+      self.use_direct_io_for_flush_and_compaction.to_string(),
+    );
+    options.insert("mmap_advice".to_owned(), self.mmap_advice.name().to_owned());
+    options
+  }
+
+  /// Validates the Options against the `name=value` pairs of an existing OPTIONS file.
+  ///
+  /// The Comparator cannot change once a Database is created, because the keys on disk are sorted
+  /// by it. The Compressors may change, as long as the Options can still decompress the blocks
+  /// written with the persisted ones. Tables record their own block, partition, filter and
+  /// dictionary layout, and values in blob files stay readable with blob files disabled, so every
+  /// other option may change between opens, but must be readable in the file.
+  pub(crate) fn validate(&self, persisted: &BTreeMap<String, String>) -> io::Result<()> {
+    if let Some(name) = persisted.get("comparator") {
+      if name != self.comparator.name() {
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidInput,
+          format!(
+            "Database was created with comparator {}, but opened with {}",
+            name,
+            self.comparator.name()
+          ),
+        ));
+      }
+    }
+
+    let compressors = self.compressors();
+    let persisted_compressors = ["compression", "compaction_compression"]
+      .iter()
+      .filter_map(|name| persisted.get(*name));
+    for name in persisted_compressors {
+      if !compressors.iter().any(|c| c.name() == name) {
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidInput,
+          format!(
+            "Database has tables compressed with {}, which the Options cannot decompress",
+            name
+          ),
+        ));
+      }
+    }
+
+    let valid = persisted.iter().all(|(name, value)| match name.as_str() {
+      "paranoid_checks"
+      | "enable_blob_files"
+      | "pin_index_and_filter_blocks"
+      | "use_mmap_reads"
+      | "use_direct_reads"
+      | "use_direct_io_for_flush_and_compaction" => value.parse::<bool>().is_ok(),
+      "memtable_size"
+      | "block_cache_size"
+      | "compression_dictionary_size"
+      | "block_size"
+      | "partition_size"
+      | "bloom_bits_per_key"
+      | "min_blob_size" => value.parse::<usize>().is_ok(),
+      "blob_file_size" => value.parse::<u64>().is_ok(),
+      "blob_garbage_collection_ratio" => value.parse::<f64>().is_ok(),
+      "sync_mode" => value.parse::<SyncMode>().is_ok(),
+      "mmap_advice" => value.parse::<MmapAdvice>().is_ok(),
+      _ => true,
+    });
+    if !valid {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "OPTIONS file is corrupted",
+      ));
+    }
+
+    Ok(())
+  }
+}
+
+/// Options for reading from a Database.
+#[derive(Clone)]
+pub struct ReadOptions {
+  pub(crate) verify_checksums: bool,
+  pub(crate) fill_cache: bool,
+  pub(crate) iterate_lower_bound: Option<Vec<u8>>,
+  pub(crate) iterate_upper_bound: Option<Vec<u8>>,
+  pub(crate) readahead_size: usize,
+  pub(crate) snapshot: Option<Snapshot>,
+}
+
+impl Default for ReadOptions {
+  fn default() -> ReadOptions {
+    ReadOptions {
+      verify_checksums: false,
+      fill_cache: true,
+      iterate_lower_bound: None,
+      iterate_upper_bound: None,
+      readahead_size: 0,
+      snapshot: None,
+    }
+  }
+}
+
+impl ReadOptions {
+  /// Creates new ReadOptions with the default values.
+  pub fn new() -> ReadOptions {
+    ReadOptions::default()
+  }
+
+  /// Verifies the checksums of the blocks read from disk. Defaults to `false`.
+  pub fn verify_checksums(mut self, verify_checksums: bool) -> ReadOptions {
+    self.verify_checksums = verify_checksums;
+    self
+  }
+
+  /// Adds the blocks read from disk to the block cache. Defaults to `true`.
+  pub fn fill_cache(mut self, fill_cache: bool) -> ReadOptions {
+    self.fill_cache = fill_cache;
+    self
+  }
+
+  /// Smallest key, inclusive, that a DBIterator yields. Defaults to no bound.
+  pub fn iterate_lower_bound(mut self, key: &[u8]) -> ReadOptions {
+    self.iterate_lower_bound = Some(key.to_vec());
+    self
+  }
+
+  /// Key, exclusive, that a DBIterator stops at. Defaults to no bound.
+  pub fn iterate_upper_bound(mut self, key: &[u8]) -> ReadOptions {
+    self.iterate_upper_bound = Some(key.to_vec());
+    self
+  }
+
+  /// Bytes of a table file that an iterator reads at once when it loads a data block that is not
+  /// cached, so the following data blocks of a scan are read from memory. Defaults to `0`, which
+  /// reads one block at a time.
+  ///
+  /// Pair with `fill_cache(false)` for bulk scans, to keep the scanned blocks out of the cache.
+  pub fn readahead_size(mut self, readahead_size: usize) -> ReadOptions {
+    self.readahead_size = readahead_size;
+    self
+  }
+
+  /// Reads the Database as of a Snapshot, instead of its current state. Defaults to no Snapshot.
+  pub fn snapshot(mut self, snapshot: &Snapshot) -> ReadOptions {
+    self.snapshot = Some(snapshot.clone());
+    self
+  }
+}
+
+/// Options for writing to a Database.
+#[derive(Clone, Default)]
+pub struct WriteOptions {
+  pub(crate) sync: bool,
+}
+
+impl WriteOptions {
+  /// Creates new WriteOptions with the default values.
+  pub fn new() -> WriteOptions {
+    WriteOptions::default()
+  }
+
+  /// Syncs the write to disk before it is acknowledged, whatever the Database's SyncMode is.
+  /// Defaults to `false`.
+  pub fn sync(mut self, sync: bool) -> WriteOptions {
+    self.sync = sync;
+    self
+  }
+}
+
+/// Options for ingesting external table files into a Database.
+#[derive(Clone, Default)]
+pub struct IngestOptions {
+  pub(crate) move_files: bool,
+}
+
+impl IngestOptions {
+  /// Creates new IngestOptions with the default values.
+  pub fn new() -> IngestOptions {
+    IngestOptions::default()
+  }
+
+  /// Moves the files into the Database directory instead of copying them. Defaults to `false`.
+  ///
+  /// Each file is hard linked into the Database directory, or copied if it cannot be linked, such
+  /// as across file systems, and its path is removed once the file is ingested. A linked file
+  /// shares its data with every other link to it, so it must not be rewritten in place through
+  /// another link, which would change the table that the Database reads.
+  pub fn move_files(mut self, move_files: bool) -> IngestOptions {
+    self.move_files = move_files;
+    self
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::comparator::ReverseBytewiseComparator;
+  use crate::compression::{Compressor, LzCompressor};
+  use crate::options::{Options, SyncMode};
+  use std::io;
+  use std::sync::Arc;
+
+  #[test]
+  fn test_options_file() {
+    let options = Options::new()
+      .memtable_size(1024)
+      .sync_mode(SyncMode::Fsync)
+      .compression(Arc::new(LzCompressor));
+    let persisted = options.to_options_file();
+    assert_eq!(persisted["comparator"], "BytewiseComparator");
+    assert_eq!(persisted["memtable_size"], "1024");
+    assert_eq!(persisted["sync_mode"], "fsync");
+    assert_eq!(persisted["compression"], "LzCompressor");
+
+    assert!(Options::new().validate(&persisted).is_ok());
+    assert!(Options::new()
+      .comparator(Arc::new(ReverseBytewiseComparator))
+      .validate(&persisted)
+      .is_err());
+
+    let mut corrupted = persisted.clone();
+    corrupted.insert("sync_mode".to_owned(), "sometimes".to_owned());
+    assert!(Options::new().validate(&corrupted).is_err());
+
+    // Every option but the Comparator and the Compressors may change.
+    let changed = Options::new()
+      .memtable_size(4096)
+      .block_size(1024)
+      .bloom_bits_per_key(0)
+      .enable_blob_files(true);
+    assert!(changed.validate(&persisted).is_ok());
+    let persisted = changed.to_options_file();
+    assert!(Options::new().validate(&persisted).is_ok());
+  }
+
+  #[test]
+  fn test_options_file_compressors() {
+    struct ReverseCompressor;
+
+    impl Compressor for ReverseCompressor {
+      fn id(&self) -> u8 {
+        100
+      }
+
+      fn name(&self) -> &str {
+        "ReverseCompressor"
+      }
+
+      fn compress(&self, data: &[u8]) -> Vec<u8> {
+        data.iter().rev().copied().collect()
+      }
+
+      fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(data.iter().rev().copied().collect())
+      }
+    }
+
+    let persisted = Options::new()
+      .compression(Arc::new(ReverseCompressor))
+      .to_options_file();
+    // The blocks of the existing tables cannot be decompressed without the custom Compressor.
+    assert!(Options::new().validate(&persisted).is_err());
+    assert!(Options::new()
+      .compression(Arc::new(ReverseCompressor))
+      .validate(&persisted)
+      .is_ok());
+
+    // Nor can the blocks of the compacted tables.
+    let persisted = Options::new()
+      .compaction_compression(Arc::new(ReverseCompressor))
+      .to_options_file();
+    assert_eq!(persisted["compression"], "NoCompressor");
+    assert_eq!(persisted["compaction_compression"], "ReverseCompressor");
+    assert!(Options::new().validate(&persisted).is_err());
+    assert!(Options::new()
+      .compaction_compression(Arc::new(ReverseCompressor))
+      .validate(&persisted)
+      .is_ok());
+
+    // The built-in Compressors can always be decompressed.
+    let persisted = Options::new()
+      .compression(Arc::new(LzCompressor))
+      .to_options_file();
+    assert!(Options::new().validate(&persisted).is_ok());
+  }
+}
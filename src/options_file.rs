@@ -0,0 +1,50 @@
+use std::collections::BTreeMap;
+use std::fs::{rename, File, OpenOptions};
+use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+/// Name of the file that persists the options of a Database in its directory.
+pub const OPTIONS_FILE: &str = "OPTIONS";
+
+/// Reads the OPTIONS file within a directory as `name=value` pairs.
+///
+/// Returns `None` if the directory does not have an OPTIONS file.
+pub fn read_options_file(dir: &Path) -> io::Result<Option<BTreeMap<String, String>>> {
+  let file = match File::open(dir.join(OPTIONS_FILE)) {
+    Ok(file) => file,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+    Err(e) => return Err(e),
+  };
+
+  let mut options = BTreeMap::new();
+  for line in BufReader::new(file).lines() {
+    let line = line?;
+    if let Some(idx) = line.find('=') {
+      options.insert(line[..idx].to_owned(), line[idx + 1..].to_owned());
+    }
+  }
+
+  Ok(Some(options))
+}
+
+/// Writes `name=value` pairs to the OPTIONS file within a directory.
+///
+/// The options are written to a temporary file first, which then replaces the OPTIONS file, so a
+/// crash never leaves a partially written OPTIONS file behind.
+pub fn write_options_file(dir: &Path, options: &BTreeMap<String, String>) -> io::Result<()> {
+  let tmp_path = dir.join(OPTIONS_FILE.to_owned() + ".tmp");
+  let file = OpenOptions::new()
+    .write(true)
+    .create(true)
+    .truncate(true)
+    .open(&tmp_path)?;
+  let mut file = BufWriter::new(file);
+  for (name, value) in options.iter() {
+    writeln!(file, "{}={}", name, value)?;
+  }
+  file.flush()?;
+  file.get_ref().sync_all()?;
+
+  rename(tmp_path, dir.join(OPTIONS_FILE))
+}
@@ -0,0 +1,161 @@
+use crate::column_family::ColumnFamily;
+use std::sync::Arc;
+
+/// Snapshot is a read-only view of a Database, as of the time it was taken.
+///
+/// Reads with `ReadOptions::snapshot` see the writes made before the Snapshot was taken, and none
+/// of the writes made after it, in every column family that existed when it was taken. Merge
+/// operands are combined with the MergeOperator the column family has when they are read.
+///
+/// Taking a Snapshot is cheap: it shares the MemTable and the tables of each column family. The
+/// next write to a shared MemTable copies it, and the tables that a compaction deletes stay
+/// readable, as the Snapshot keeps their files open. Flushes run on the calling thread, so there
+/// are no immutable MemTables to keep. Blob files are not garbage collected while any Snapshot is
+/// alive.
+#[derive(Clone)]
+pub struct Snapshot {
+  column_families: Arc<Vec<ColumnFamily>>,
+  _live: Arc<()>,
+}
+
+impl Snapshot {
+  /// Creates a new Snapshot of column families, which holds `live` to count the live Snapshots.
+  pub(crate) fn new(column_families: &[ColumnFamily], live: Arc<()>) -> Snapshot {
+    Snapshot {
+      column_families: Arc::new(column_families.iter().map(|cf| cf.snapshot()).collect()),
+      _live: live,
+    }
+  }
+
+  /// Gets the copy of a column family by id, or `None` if it did not exist when the Snapshot was
+  /// taken.
+  pub(crate) fn column_family(&self, id: u32) -> Option<&ColumnFamily> {
+    self.column_families.iter().find(|cf| cf.id == id)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::column_family::ColumnFamilyOptions;
+  use crate::database::Database;
+  use crate::merge_operator::U64AddOperator;
+  use crate::options::{Options, ReadOptions};
+  use crate::snapshot::Snapshot;
+  use rand::Rng;
+  use std::fs::remove_dir_all;
+  use std::path::PathBuf;
+
+  #[test]
+  fn test_snapshot() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let options = Options::new()
+      .enable_blob_files(true)
+      .min_blob_size(100)
+      .blob_file_size(100);
+
+    let mut db = Database::open(dir.to_str().unwrap(), options).unwrap();
+    db.set_merge_operator(Box::new(U64AddOperator));
+    db.set(b"Apple", b"Apple Pie").unwrap();
+    db.set(b"Lime", &[7; 200]).unwrap();
+    db.merge(b"Count", &1u64.to_le_bytes()).unwrap();
+    db.set(b"Kiwi", b"Kiwi Smoothie").unwrap();
+    db.flush(true).unwrap();
+    db.set(b"Cherry", b"Cherry Pie").unwrap();
+
+    let snapshot = db.snapshot();
+    db.set(b"Apple", b"Apple Juice").unwrap();
+    db.delete(b"Cherry").unwrap();
+    db.merge(b"Count", &2u64.to_le_bytes()).unwrap();
+    db.delete_range(b"Kiwi", b"Kiwj").unwrap();
+    db.delete(b"Lime").unwrap();
+    db.set(b"Mango", &[8; 200]).unwrap();
+    db.create_column_family("Fruits", ColumnFamilyOptions::default())
+      .unwrap();
+    db.set_cf("Fruits", b"Banana", b"Banana Bread").unwrap();
+    db.flush(true).unwrap();
+    db.compact_range(b"A", b"Z").unwrap();
+
+    let read_options = ReadOptions::new().snapshot(&snapshot);
+    let get = |db: &Database, key: &[u8]| {
+      db.get_opt(&read_options, key)
+        .unwrap()
+        .map(|e| e.value().to_vec())
+    };
+    assert_eq!(get(&db, b"Apple").unwrap(), b"Apple Pie");
+    assert_eq!(get(&db, b"Cherry").unwrap(), b"Cherry Pie");
+    assert_eq!(get(&db, b"Count").unwrap(), 1u64.to_le_bytes());
+    assert_eq!(get(&db, b"Kiwi").unwrap(), b"Kiwi Smoothie");
+    assert_eq!(get(&db, b"Lime").unwrap(), [7; 200]);
+    assert!(get(&db, b"Mango").is_none());
+
+    assert_eq!(db.get(b"Apple").unwrap().unwrap().value(), b"Apple Juice");
+    assert!(db.get(b"Cherry").unwrap().is_none());
+    assert_eq!(
+      db.get(b"Count").unwrap().unwrap().value(),
+      3u64.to_le_bytes()
+    );
+    assert!(db.get(b"Kiwi").unwrap().is_none());
+
+    let keys: [&[u8]; 3] = [b"Apple", b"Lime", b"Mango"];
+    let entries = db.multi_get_cf_opt(&read_options, "default", &keys);
+    assert_eq!(
+      entries[0].as_ref().unwrap().as_ref().unwrap().value(),
+      b"Apple Pie"
+    );
+    assert_eq!(
+      entries[1].as_ref().unwrap().as_ref().unwrap().value(),
+      [7; 200]
+    );
+    assert!(entries[2].as_ref().unwrap().is_none());
+
+    let mut iter = db.iter_cf_opt(&read_options, "default").unwrap();
+    let mut keys = Vec::new();
+    iter.seek_to_first();
+    while iter.valid() {
+      keys.push(iter.key().to_vec());
+      iter.next();
+    }
+    assert_eq!(
+      keys,
+      vec![
+        b"Apple".to_vec(),
+        b"Cherry".to_vec(),
+        b"Count".to_vec(),
+        b"Kiwi".to_vec(),
+        b"Lime".to_vec()
+      ]
+    );
+    iter.seek_to_last();
+    assert_eq!(iter.key(), b"Lime");
+    iter.prev();
+    assert_eq!(iter.key(), b"Kiwi");
+    assert!(iter.status().is_none());
+    drop(iter);
+
+    // The column family did not exist when the Snapshot was taken.
+    assert!(db.iter_cf_opt(&read_options, "Fruits").is_none());
+    assert!(db
+      .get_cf_opt(&read_options, "Fruits", b"Banana")
+      .unwrap()
+      .is_none());
+
+    // The blob file of Lime is garbage, but the Snapshot still reads it.
+    assert_eq!(db.blob_file_stats()[0].garbage_ratio(), 1.0);
+    assert_eq!(db.collect_blob_garbage(), Ok(0));
+    assert_eq!(get(&db, b"Lime").unwrap(), [7; 200]);
+
+    drop(read_options);
+    drop(snapshot);
+    assert_eq!(db.collect_blob_garbage(), Ok(1));
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_send() {
+    fn assert_send<T: Send>() {}
+    assert_send::<Database>();
+    assert_send::<Snapshot>();
+  }
+}
@@ -0,0 +1,1309 @@
+use crate::block::{Block, BlockBuilder, BlockIterator, DEFAULT_RESTART_INTERVAL};
+use crate::block_cache::{BlockCache, BlockKey};
+use crate::comparator::Comparator;
+use crate::compression::{
+  compress_block, decompress_block, train_dictionary, BlockCompressor, CompressionStats,
+  Compressor, NoCompressor,
+};
+use crate::file_reader::RandomAccessFile;
+use crate::file_writer::WritableFile;
+use crate::filter::{bloom_filter_may_contain, build_bloom_filter};
+use crate::options::{Options, ReadOptions};
+use crate::utils::{crc32, read_varint, write_varint};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Size of the footer at the end of every table file.
+const FOOTER_SIZE: u64 = 40;
+/// Magic number that ends every table file.
+const TABLE_MAGIC: u64 = 0x5441_424c_4544_4221;
+/// Size of the checksum that follows every block.
+const BLOCK_TRAILER_SIZE: u64 = 4;
+/// Name of the meta block holding the compression dictionary of a table.
+pub const DICTIONARY_META_BLOCK: &str = "dictionary";
+/// Name of the meta block holding the name of the Compressor of a table's data blocks.
+pub const COMPRESSION_META_BLOCK: &str = "compression";
+/// Name of the meta block holding the CompressionStats of a table's data blocks.
+pub const COMPRESSION_STATS_META_BLOCK: &str = "compression_stats";
+/// Bytes of values sampled to train a compression dictionary, per byte of dictionary.
+const DICTIONARY_SAMPLE_RATIO: usize = 100;
+
+/// Location of a block in a table file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockHandle {
+  pub offset: u64,
+  pub size: u64,
+}
+
+impl BlockHandle {
+  /// Appends the BlockHandle to a buffer as two varints.
+  fn encode_to(&self, buf: &mut Vec<u8>) {
+    write_varint(buf, self.offset);
+    write_varint(buf, self.size);
+  }
+
+  /// Reads a BlockHandle from a buffer at a position.
+  fn decode_from(buf: &[u8], pos: &mut usize) -> io::Result<BlockHandle> {
+    match (read_varint(buf, pos), read_varint(buf, pos)) {
+      (Some(offset), Some(size)) => Ok(BlockHandle { offset, size }),
+      _ => Err(corrupted()),
+    }
+  }
+}
+
+/// Gets the error of a table file that cannot be read.
+fn corrupted() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, "table is corrupted")
+}
+
+/// Summary of a table written by a TableBuilder.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TableProperties {
+  pub num_entries: u64,
+  pub file_size: u64,
+  pub compression: CompressionStats,
+}
+
+/// A data block that is waiting for the compression dictionary to be trained.
+struct BufferedBlock {
+  last_key: Vec<u8>,
+  data: Vec<u8>,
+  filter_keys: Vec<Vec<u8>>,
+}
+
+/// Writes a table file(SSTable) of sorted Key-Value pairs.
+///
+/// A table file is laid out as:
+///
+/// ```text
+/// [data blocks][filter and index partitions][meta blocks][meta index][top-level index][footer]
+/// ```
+///
+/// Data blocks are prefix compressed and then compressed with the Compressor of the table, whose
+/// name is stored in the compression meta block. Each block also stores the id of its codec, so it
+/// can be decompressed whatever Compressor the current Options write tables with.
+/// The index is partitioned: each index partition maps the last key of its data blocks to their
+/// handles, and the top-level index maps the last key of each index partition to the handles of
+/// the partition and of the bloom filter partition that covers the same keys. Only the top-level
+/// index needs to stay in memory, and the partitions are loaded on demand through the BlockCache.
+///
+/// Every block is followed by the CRC-32 of its stored bytes. The footer holds the handles of the
+/// meta index and the top-level index, and a magic number.
+///
+/// With a compression dictionary, data blocks are buffered until enough values have been sampled
+/// to train the dictionary, which is then stored in the dictionary meta block and used for every
+/// data block of the table.
+pub struct TableBuilder {
+  file: WritableFile,
+  offset: u64,
+  comparator: Arc<dyn Comparator>,
+  block_size: usize,
+  partition_size: usize,
+  bloom_bits_per_key: usize,
+  codec: Arc<dyn Compressor>,
+  compressor: Option<BlockCompressor>,
+  dictionary_size: usize,
+  samples: Vec<Vec<u8>>,
+  sampled_bytes: usize,
+  buffered: Vec<BufferedBlock>,
+  buffered_bytes: usize,
+  data_block: BlockBuilder,
+  block_filter_keys: Vec<Vec<u8>>,
+  index_partition: BlockBuilder,
+  partition_filter_keys: Vec<Vec<u8>>,
+  partition_last_key: Vec<u8>,
+  top_index: BlockBuilder,
+  meta_blocks: BTreeMap<String, Vec<u8>>,
+  last_key: Option<Vec<u8>>,
+  num_entries: u64,
+}
+
+impl TableBuilder {
+  /// Creates a new table file at a path, compressed with the `compression` Compressor of the
+  /// Options.
+  pub fn new(path: &Path, options: &Options) -> io::Result<TableBuilder> {
+    TableBuilder::with_compressor(path, options, options.compression.clone())
+  }
+
+  /// Creates a new table file at a path, compressed with a Compressor.
+  pub(crate) fn with_compressor(
+    path: &Path,
+    options: &Options,
+    codec: Arc<dyn Compressor>,
+  ) -> io::Result<TableBuilder> {
+    let file = WritableFile::create(path, options.use_direct_io_for_flush_and_compaction)?;
+    let compressor = if options.compression_dictionary_size == 0 {
+      Some(BlockCompressor::new(codec.clone(), None))
+    } else {
+      None
+    };
+
+    Ok(TableBuilder {
+      file,
+      offset: 0,
+      comparator: options.comparator.clone(),
+      block_size: options.block_size,
+      partition_size: options.partition_size,
+      bloom_bits_per_key: options.bloom_bits_per_key,
+      codec,
+      compressor,
+      dictionary_size: options.compression_dictionary_size,
+      samples: Vec::new(),
+      sampled_bytes: 0,
+      buffered: Vec::new(),
+      buffered_bytes: 0,
+      data_block: BlockBuilder::new(DEFAULT_RESTART_INTERVAL),
+      block_filter_keys: Vec::new(),
+      index_partition: BlockBuilder::new(1),
+      partition_filter_keys: Vec::new(),
+      partition_last_key: Vec::new(),
+      top_index: BlockBuilder::new(1),
+      meta_blocks: BTreeMap::new(),
+      last_key: None,
+      num_entries: 0,
+    })
+  }
+
+  /// Adds a Key-Value pair to the table.
+  ///
+  /// Keys must be added in increasing order of the Comparator, without duplicates.
+  pub fn add(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+    if let Some(last_key) = self.last_key.as_ref() {
+      if self.comparator.compare(last_key, key) != Ordering::Less {
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidInput,
+          "keys must be added to a table in increasing order",
+        ));
+      }
+    }
+
+    if self.compressor.is_none()
+      && self.sampled_bytes < self.dictionary_size * DICTIONARY_SAMPLE_RATIO
+    {
+      self.samples.push(value.to_vec());
+      self.sampled_bytes += value.len();
+    }
+
+    self
+      .block_filter_keys
+      .push(self.comparator.filter_key(key).into_owned());
+    self.data_block.add(key, value);
+    self.last_key = Some(key.to_vec());
+    self.num_entries += 1;
+
+    if self.data_block.estimated_size() >= self.block_size {
+      self.finish_data_block()?;
+    }
+    Ok(())
+  }
+
+  /// Adds a named meta block to the table.
+  pub fn add_meta_block(&mut self, name: &str, data: Vec<u8>) {
+    self.meta_blocks.insert(name.to_owned(), data);
+  }
+
+  /// Gets the number of Key-Value pairs added to the table.
+  pub fn num_entries(&self) -> u64 {
+    self.num_entries
+  }
+
+  /// Gets the approximate size of the table file so far.
+  pub fn file_size(&self) -> u64 {
+    self.offset + self.buffered_bytes as u64 + self.data_block.estimated_size() as u64
+  }
+
+  /// Finishes the table by writing the index, the meta blocks and the footer, and syncs the file.
+  pub fn finish(mut self) -> io::Result<TableProperties> {
+    self.finish_data_block()?;
+    if self.compressor.is_none() {
+      self.train_dictionary()?;
+    }
+    self.finish_partition()?;
+
+    let compression = self
+      .compressor
+      .as_ref()
+      .map(|c| c.stats())
+      .unwrap_or_default();
+    self.add_meta_block(COMPRESSION_STATS_META_BLOCK, compression.encode());
+    let name = self.codec.name().as_bytes().to_vec();
+    self.add_meta_block(COMPRESSION_META_BLOCK, name);
+    let mut meta_index = BlockBuilder::new(1);
+    for (name, data) in mem::take(&mut self.meta_blocks) {
+      let handle = self.write_raw_block(&data)?;
+      let mut buf = Vec::new();
+      handle.encode_to(&mut buf);
+      meta_index.add(name.as_bytes(), &buf);
+    }
+    let meta_index = self.write_raw_block(&meta_index.finish())?;
+    let top_index = mem::replace(&mut self.top_index, BlockBuilder::new(1)).finish();
+    let top_index = self.write_raw_block(&top_index)?;
+
+    for field in [
+      meta_index.offset,
+      meta_index.size,
+      top_index.offset,
+      top_index.size,
+      TABLE_MAGIC,
+    ]
+    .iter()
+    {
+      self.file.write_all(&field.to_le_bytes())?;
+    }
+    self.offset += FOOTER_SIZE;
+    self.file.sync()?;
+
+    Ok(TableProperties {
+      num_entries: self.num_entries,
+      file_size: self.offset,
+      compression,
+    })
+  }
+
+  /// Finishes the current data block, and writes it or buffers it until the dictionary is trained.
+  fn finish_data_block(&mut self) -> io::Result<()> {
+    if self.data_block.is_empty() {
+      return Ok(());
+    }
+    let data = mem::replace(
+      &mut self.data_block,
+      BlockBuilder::new(DEFAULT_RESTART_INTERVAL),
+    )
+    .finish();
+    let block = BufferedBlock {
+      last_key: self.last_key.clone().unwrap(),
+      data,
+      filter_keys: mem::take(&mut self.block_filter_keys),
+    };
+
+    if self.compressor.is_some() {
+      return self.write_data_block(block);
+    }
+    self.buffered_bytes += block.data.len();
+    self.buffered.push(block);
+    if self.sampled_bytes >= self.dictionary_size * DICTIONARY_SAMPLE_RATIO {
+      self.train_dictionary()?;
+    }
+    Ok(())
+  }
+
+  /// Trains the compression dictionary from the sampled values, and writes the buffered data blocks.
+  fn train_dictionary(&mut self) -> io::Result<()> {
+    let samples: Vec<&[u8]> = self.samples.iter().map(|s| s.as_slice()).collect();
+    let dictionary = train_dictionary(&samples, self.dictionary_size);
+    self.samples = Vec::new();
+
+    let dictionary = if dictionary.is_empty() {
+      None
+    } else {
+      self.add_meta_block(DICTIONARY_META_BLOCK, dictionary.clone());
+      Some(dictionary)
+    };
+    self.compressor = Some(BlockCompressor::new(self.codec.clone(), dictionary));
+
+    self.buffered_bytes = 0;
+    for block in mem::take(&mut self.buffered) {
+      self.write_data_block(block)?;
+    }
+    Ok(())
+  }
+
+  /// Writes a compressed data block, and adds it to the current index partition.
+  fn write_data_block(&mut self, block: BufferedBlock) -> io::Result<()> {
+    let stored = self.compressor.as_mut().unwrap().compress(&block.data);
+    let handle = self.write_block(&stored)?;
+
+    let mut buf = Vec::new();
+    handle.encode_to(&mut buf);
+    self.index_partition.add(&block.last_key, &buf);
+    self.partition_filter_keys.extend(block.filter_keys);
+    self.partition_last_key = block.last_key;
+
+    if self.index_partition.estimated_size() >= self.partition_size {
+      self.finish_partition()?;
+    }
+    Ok(())
+  }
+
+  /// Writes the current index partition and its filter partition, and adds them to the top-level
+  /// index.
+  fn finish_partition(&mut self) -> io::Result<()> {
+    if self.index_partition.is_empty() {
+      return Ok(());
+    }
+
+    let filter = if self.bloom_bits_per_key == 0 {
+      Vec::new()
+    } else {
+      build_bloom_filter(&self.partition_filter_keys, self.bloom_bits_per_key)
+    };
+    self.partition_filter_keys.clear();
+    let filter = self.write_raw_block(&filter)?;
+    let index = mem::replace(&mut self.index_partition, BlockBuilder::new(1)).finish();
+    let index = self.write_raw_block(&index)?;
+
+    let mut buf = Vec::new();
+    index.encode_to(&mut buf);
+    filter.encode_to(&mut buf);
+    self.top_index.add(&self.partition_last_key, &buf);
+    Ok(())
+  }
+
+  /// Writes an uncompressed block.
+  fn write_raw_block(&mut self, data: &[u8]) -> io::Result<BlockHandle> {
+    self.write_block(&compress_block(&NoCompressor, None, data))
+  }
+
+  /// Writes the stored bytes of a block, followed by their checksum.
+  fn write_block(&mut self, stored: &[u8]) -> io::Result<BlockHandle> {
+    let handle = BlockHandle {
+      offset: self.offset,
+      size: stored.len() as u64,
+    };
+    self.file.write_all(stored)?;
+    self.file.write_all(&crc32(stored).to_le_bytes())?;
+    self.offset += handle.size + BLOCK_TRAILER_SIZE;
+    Ok(handle)
+  }
+}
+
+/// Table file(SSTable) that is open for reading.
+///
+/// Blocks are read on demand and kept in the BlockCache, keyed by the id of the table. The file is
+/// read through a memory mapping if the Options ask for it, and with buffered reads otherwise.
+///
+/// If the Options pin index and filter blocks, the top-level index and every index and filter
+/// partition are read when the table is opened and pinned in the BlockCache, until the table is
+/// dropped.
+pub struct Table {
+  id: u64,
+  file: RandomAccessFile,
+  comparator: Arc<dyn Comparator>,
+  block_cache: Arc<BlockCache>,
+  compressors: Vec<Arc<dyn Compressor>>,
+  dictionary: Option<Vec<u8>>,
+  top_index: Arc<Block>,
+  meta_index: BTreeMap<String, BlockHandle>,
+  pinned: Vec<BlockKey>,
+}
+
+impl Table {
+  /// Opens a table file, with a new id from the BlockCache that its blocks are cached under.
+  pub fn open(path: &Path, options: &Options, block_cache: Arc<BlockCache>) -> io::Result<Table> {
+    let file = RandomAccessFile::open(
+      path,
+      options.use_mmap_reads,
+      options.use_direct_reads,
+      options.mmap_advice,
+    )?;
+    let file_size = file.len();
+    if file_size < FOOTER_SIZE {
+      return Err(corrupted());
+    }
+
+    let mut table = Table {
+      id: block_cache.new_id(),
+      file,
+      comparator: options.comparator.clone(),
+      block_cache,
+      compressors: options.compressors(),
+      dictionary: None,
+      top_index: Arc::new(Block::new(Arc::new(BlockBuilder::new(1).finish()))?),
+      meta_index: BTreeMap::new(),
+      pinned: Vec::new(),
+    };
+
+    let footer = table.read_raw(file_size - FOOTER_SIZE, FOOTER_SIZE as usize)?;
+    let field = |i: usize| {
+      let mut bytes = [0; 8];
+      bytes.copy_from_slice(&footer[i * 8..i * 8 + 8]);
+      u64::from_le_bytes(bytes)
+    };
+    if field(4) != TABLE_MAGIC {
+      return Err(corrupted());
+    }
+    let meta_index = BlockHandle {
+      offset: field(0),
+      size: field(1),
+    };
+    let top_index = BlockHandle {
+      offset: field(2),
+      size: field(3),
+    };
+
+    // The top-level index and meta index are always verified, as they are read once per open.
+    let verify = ReadOptions::new().verify_checksums(true).fill_cache(false);
+    table.top_index = Arc::new(Block::new(table.read_block(&top_index, &verify)?)?);
+
+    let meta_index = Arc::new(Block::new(table.read_block(&meta_index, &verify)?)?);
+    let mut iter = meta_index.iter(table.comparator.clone());
+    iter.seek_to_first();
+    while iter.valid() {
+      let name = String::from_utf8(iter.key().to_vec()).map_err(|_| corrupted())?;
+      let handle = BlockHandle::decode_from(iter.value(), &mut 0)?;
+      table.meta_index.insert(name, handle);
+      iter.next();
+    }
+
+    if let Some(handle) = table.meta_index.get(DICTIONARY_META_BLOCK).copied() {
+      table.dictionary = Some(table.read_block(&handle, &verify)?.to_vec());
+    }
+
+    if options.pin_index_and_filter_blocks {
+      table.pin(&top_index)?;
+      let mut top = table.top_index.iter(table.comparator.clone());
+      top.seek_to_first();
+      while top.valid() {
+        let mut pos = 0;
+        let index_handle = BlockHandle::decode_from(top.value(), &mut pos)?;
+        let filter_handle = BlockHandle::decode_from(top.value(), &mut pos)?;
+        table.pin(&index_handle)?;
+        table.pin(&filter_handle)?;
+        top.next();
+      }
+    }
+
+    Ok(table)
+  }
+
+  /// Reads a block, verifying its checksum, and pins it in the BlockCache.
+  fn pin(&mut self, handle: &BlockHandle) -> io::Result<()> {
+    let verify = ReadOptions::new().verify_checksums(true).fill_cache(false);
+    let buf = self.read_raw(handle.offset, (handle.size + BLOCK_TRAILER_SIZE) as usize)?;
+    let block = self.decode_block(handle, &buf, &verify)?;
+    let key = BlockKey {
+      table_id: self.id,
+      offset: handle.offset,
+    };
+    self.block_cache.insert_pinned(key, block);
+    self.pinned.push(key);
+    Ok(())
+  }
+
+  /// Gets the id of the table.
+  pub fn id(&self) -> u64 {
+    self.id
+  }
+
+  /// Gets the size of the table file in bytes.
+  pub fn file_size(&self) -> u64 {
+    self.file.len()
+  }
+
+  /// Gets if the table file is read through a memory mapping.
+  pub fn is_mmap(&self) -> bool {
+    self.file.is_mmap()
+  }
+
+  /// Gets if the table file is read with direct I/O.
+  pub fn is_direct(&self) -> bool {
+    self.file.is_direct()
+  }
+
+  /// Gets the value of a key in the table.
+  ///
+  /// The bloom filter partition that covers the key is checked before the index partition and data
+  /// block are read.
+  pub fn get(&self, key: &[u8], read_options: &ReadOptions) -> io::Result<Option<Vec<u8>>> {
+    let data_handle = match self.data_handle(key, read_options)? {
+      Some(handle) => handle,
+      None => return Ok(None),
+    };
+    let mut data = self.open_block(&data_handle, read_options)?;
+    data.seek(key);
+    if data.valid() && self.comparator.compare(data.key(), key) == Ordering::Equal {
+      return Ok(Some(data.value().to_vec()));
+    }
+    Ok(None)
+  }
+
+  /// Gets the handle of the data block that may hold a key, or `None` if the bloom filter rules
+  /// the key out.
+  fn data_handle(&self, key: &[u8], read_options: &ReadOptions) -> io::Result<Option<BlockHandle>> {
+    let mut top = self.top_index.iter(self.comparator.clone());
+    top.seek(key);
+    if !top.valid() {
+      return Ok(None);
+    }
+
+    let mut pos = 0;
+    let index_handle = BlockHandle::decode_from(top.value(), &mut pos)?;
+    let filter_handle = BlockHandle::decode_from(top.value(), &mut pos)?;
+    let filter = self.read_block(&filter_handle, read_options)?;
+    if !bloom_filter_may_contain(&filter, &self.comparator.filter_key(key)) {
+      return Ok(None);
+    }
+
+    let mut index = self.open_block(&index_handle, read_options)?;
+    index.seek(key);
+    if !index.valid() {
+      return Ok(None);
+    }
+    Ok(Some(BlockHandle::decode_from(index.value(), &mut 0)?))
+  }
+
+  /// Gets the values of keys in the table.
+  ///
+  /// The keys are looked up in sorted order, so the top-level index is walked once and each filter
+  /// and index partition is read once per batch, even if the blocks are not cached. Keys in the
+  /// same data block share one read of the block, and the data blocks that are not in the
+  /// BlockCache are read together in one batch, which goes through io_uring with the `io_uring`
+  /// feature.
+  pub fn multi_get(
+    &self,
+    keys: &[&[u8]],
+    read_options: &ReadOptions,
+  ) -> io::Result<Vec<Option<Vec<u8>>>> {
+    let mut order: Vec<usize> = (0..keys.len()).collect();
+    order.sort_by(|a, b| self.comparator.compare(keys[*a], keys[*b]));
+
+    // The keys of each data block that may hold them, by the offset of the block.
+    let mut groups: BTreeMap<u64, (BlockHandle, Vec<usize>)> = BTreeMap::new();
+    let mut partition: Option<(u64, Arc<Vec<u8>>, BlockIterator)> = None;
+    let mut top = self.top_index.iter(self.comparator.clone());
+    top.seek_to_first();
+    for i in order.into_iter() {
+      let key = keys[i];
+      if top.valid() && self.comparator.compare(top.key(), key) == Ordering::Less {
+        top.seek(key);
+      }
+      if !top.valid() {
+        break;
+      }
+
+      let mut pos = 0;
+      let index_handle = BlockHandle::decode_from(top.value(), &mut pos)?;
+      let filter_handle = BlockHandle::decode_from(top.value(), &mut pos)?;
+      if partition.as_ref().map(|p| p.0) != Some(index_handle.offset) {
+        let filter = self.read_block(&filter_handle, read_options)?;
+        let index = self.open_block(&index_handle, read_options)?;
+        partition = Some((index_handle.offset, filter, index));
+      }
+      let (_, filter, index) = partition.as_mut().unwrap();
+      if !bloom_filter_may_contain(filter, &self.comparator.filter_key(key)) {
+        continue;
+      }
+
+      index.seek(key);
+      if !index.valid() {
+        continue;
+      }
+      let handle = BlockHandle::decode_from(index.value(), &mut 0)?;
+      groups
+        .entry(handle.offset)
+        .or_insert_with(|| (handle, Vec::new()))
+        .1
+        .push(i);
+    }
+
+    let mut blocks = BTreeMap::new();
+    let mut missing = Vec::new();
+    for (offset, (handle, _)) in groups.iter() {
+      let key = BlockKey {
+        table_id: self.id,
+        offset: *offset,
+      };
+      match self.block_cache.get(&key) {
+        Some(block) => {
+          blocks.insert(*offset, block);
+        }
+        None => missing.push(*handle),
+      }
+    }
+
+    let ranges: Vec<(u64, usize)> = missing
+      .iter()
+      .map(|h| (h.offset, (h.size + BLOCK_TRAILER_SIZE) as usize))
+      .collect();
+    let bufs = self.file.read_batch(&ranges).map_err(|e| match e.kind() {
+      io::ErrorKind::UnexpectedEof => corrupted(),
+      _ => e,
+    })?;
+    for (handle, buf) in missing.iter().zip(bufs.iter()) {
+      blocks.insert(handle.offset, self.decode_block(handle, buf, read_options)?);
+    }
+
+    let mut values = vec![None; keys.len()];
+    for (offset, (_, indices)) in groups.into_iter() {
+      let block = Block::new(blocks.remove(&offset).unwrap())?;
+      let mut data = Arc::new(block).iter(self.comparator.clone());
+      for i in indices.into_iter() {
+        data.seek(keys[i]);
+        if data.valid() && self.comparator.compare(data.key(), keys[i]) == Ordering::Equal {
+          values[i] = Some(data.value().to_vec());
+        }
+      }
+    }
+    Ok(values)
+  }
+
+  /// Gets a meta block of the table by name.
+  pub fn meta_block(&self, name: &str, read_options: &ReadOptions) -> io::Result<Option<Vec<u8>>> {
+    match self.meta_index.get(name) {
+      Some(handle) => Ok(Some(self.read_block(handle, read_options)?.to_vec())),
+      None => Ok(None),
+    }
+  }
+
+  /// Creates an iterator over the Key-Value pairs of the table.
+  pub fn iter(self: &Arc<Self>, read_options: ReadOptions) -> TableIterator {
+    TableIterator {
+      top: self.top_index.iter(self.comparator.clone()),
+      table: self.clone(),
+      read_options,
+      index: None,
+      data: None,
+      readahead: Readahead::default(),
+      error: None,
+    }
+  }
+
+  /// Reads a block and creates an iterator over its entries.
+  fn open_block(
+    &self,
+    handle: &BlockHandle,
+    read_options: &ReadOptions,
+  ) -> io::Result<BlockIterator> {
+    let block = Block::new(self.read_block(handle, read_options)?)?;
+    Ok(Arc::new(block).iter(self.comparator.clone()))
+  }
+
+  /// Reads a data block and creates an iterator over its entries, like `open_block`, reading
+  /// `readahead_size` bytes of the file ahead into the readahead buffer of a TableIterator.
+  ///
+  /// A mapped file is not read ahead, as the mapping already lets the kernel read ahead.
+  fn open_block_readahead(
+    &self,
+    handle: &BlockHandle,
+    read_options: &ReadOptions,
+    readahead: &mut Readahead,
+  ) -> io::Result<BlockIterator> {
+    if read_options.readahead_size == 0 || self.file.is_mmap() {
+      return self.open_block(handle, read_options);
+    }
+
+    let key = BlockKey {
+      table_id: self.id,
+      offset: handle.offset,
+    };
+    let block = match self.block_cache.get(&key) {
+      Some(block) => block,
+      None => {
+        let len = (handle.size + BLOCK_TRAILER_SIZE) as usize;
+        if readahead.get(handle.offset, len).is_none() {
+          let remaining = self.file_size().saturating_sub(handle.offset) as usize;
+          let size = read_options.readahead_size.min(remaining).max(len);
+          readahead.buf = self.read_raw(handle.offset, size)?.into_owned();
+          readahead.offset = handle.offset;
+        }
+        self.decode_block(
+          handle,
+          readahead.get(handle.offset, len).unwrap(),
+          read_options,
+        )?
+      }
+    };
+    Ok(Arc::new(Block::new(block)?).iter(self.comparator.clone()))
+  }
+
+  /// Reads and decompresses a block, through the BlockCache.
+  fn read_block(
+    &self,
+    handle: &BlockHandle,
+    read_options: &ReadOptions,
+  ) -> io::Result<Arc<Vec<u8>>> {
+    let key = BlockKey {
+      table_id: self.id,
+      offset: handle.offset,
+    };
+    if let Some(block) = self.block_cache.get(&key) {
+      return Ok(block);
+    }
+
+    let buf = self.read_raw(handle.offset, (handle.size + BLOCK_TRAILER_SIZE) as usize)?;
+    self.decode_block(handle, &buf, read_options)
+  }
+
+  /// Verifies and decompresses a block read from the table file with its trailer, inserting it
+  /// into the BlockCache.
+  fn decode_block(
+    &self,
+    handle: &BlockHandle,
+    buf: &[u8],
+    read_options: &ReadOptions,
+  ) -> io::Result<Arc<Vec<u8>>> {
+    let (stored, trailer) = buf.split_at(handle.size as usize);
+    if read_options.verify_checksums
+      && crc32(stored).to_le_bytes() != [trailer[0], trailer[1], trailer[2], trailer[3]]
+    {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "block checksum mismatch",
+      ));
+    }
+
+    let block = Arc::new(decompress_block(
+      stored,
+      &self.compressors,
+      self.dictionary.as_deref(),
+    )?);
+    if read_options.fill_cache {
+      let key = BlockKey {
+        table_id: self.id,
+        offset: handle.offset,
+      };
+      self.block_cache.insert(key, block.clone());
+    }
+    Ok(block)
+  }
+
+  /// Reads bytes from the table file, borrowing them from the mapping if the file is mapped.
+  fn read_raw(&self, offset: u64, len: usize) -> io::Result<Cow<'_, [u8]>> {
+    self.file.read(offset, len).map_err(|e| match e.kind() {
+      io::ErrorKind::UnexpectedEof => corrupted(),
+      _ => e,
+    })
+  }
+}
+
+impl Drop for Table {
+  /// Releases the pinned blocks, which are not read again once the table is closed.
+  fn drop(&mut self) {
+    for key in self.pinned.iter() {
+      self.block_cache.erase(key);
+    }
+  }
+}
+
+/// Bytes of a table file read ahead of a TableIterator, starting at an offset.
+#[derive(Default)]
+struct Readahead {
+  offset: u64,
+  buf: Vec<u8>,
+}
+
+impl Readahead {
+  /// Gets bytes of the file from the buffer, if the buffer holds all of them.
+  fn get(&self, offset: u64, len: usize) -> Option<&[u8]> {
+    let start = offset.checked_sub(self.offset)? as usize;
+    self.buf.get(start..start.checked_add(len)?)
+  }
+}
+
+/// Iterator over the Key-Value pairs of a Table that can seek and move forward and backward.
+///
+/// The iterator walks the top-level index, the index partitions and the data blocks together. The
+/// iterator starts out invalid, and must be positioned with one of the seek methods. An error
+/// while reading a block invalidates the iterator, and is kept in its status.
+pub struct TableIterator {
+  table: Arc<Table>,
+  read_options: ReadOptions,
+  readahead: Readahead,
+  top: BlockIterator,
+  index: Option<BlockIterator>,
+  data: Option<BlockIterator>,
+  error: Option<io::Error>,
+}
+
+impl TableIterator {
+  /// Checks if the iterator is positioned at a Key-Value pair.
+  pub fn valid(&self) -> bool {
+    self.error.is_none() && self.data.as_ref().is_some_and(|d| d.valid())
+  }
+
+  /// Gets the key of the current Key-Value pair.
+  pub fn key(&self) -> &[u8] {
+    self.data.as_ref().unwrap().key()
+  }
+
+  /// Gets the value of the current Key-Value pair.
+  pub fn value(&self) -> &[u8] {
+    self.data.as_ref().unwrap().value()
+  }
+
+  /// Gets the error that invalidated the iterator, if any.
+  pub fn status(&self) -> Option<&io::Error> {
+    self.error.as_ref()
+  }
+
+  /// Positions the iterator at the first Key-Value pair.
+  pub fn seek_to_first(&mut self) {
+    self.top.seek_to_first();
+    self.load_index();
+    if let Some(index) = self.index.as_mut() {
+      index.seek_to_first();
+    }
+    self.load_data();
+    if let Some(data) = self.data.as_mut() {
+      data.seek_to_first();
+    }
+    self.skip_forward();
+  }
+
+  /// Positions the iterator at the last Key-Value pair.
+  pub fn seek_to_last(&mut self) {
+    self.top.seek_to_last();
+    self.load_index();
+    if let Some(index) = self.index.as_mut() {
+      index.seek_to_last();
+    }
+    self.load_data();
+    if let Some(data) = self.data.as_mut() {
+      data.seek_to_last();
+    }
+    self.skip_backward();
+  }
+
+  /// Positions the iterator at the first Key-Value pair with a key at or after the target.
+  pub fn seek(&mut self, target: &[u8]) {
+    self.top.seek(target);
+    self.load_index();
+    if let Some(index) = self.index.as_mut() {
+      index.seek(target);
+    }
+    self.load_data();
+    if let Some(data) = self.data.as_mut() {
+      data.seek(target);
+    }
+    self.skip_forward();
+  }
+
+  /// Positions the iterator at the last Key-Value pair with a key at or before the target.
+  pub fn seek_for_prev(&mut self, target: &[u8]) {
+    self.top.seek(target);
+    if !self.top.valid() {
+      self.top.seek_to_last();
+    }
+    self.load_index();
+    if let Some(index) = self.index.as_mut() {
+      index.seek(target);
+      if !index.valid() {
+        index.seek_to_last();
+      }
+    }
+    self.load_data();
+    if let Some(data) = self.data.as_mut() {
+      data.seek_for_prev(target);
+    }
+    self.skip_backward();
+  }
+
+  /// Moves the iterator to the next Key-Value pair.
+  pub fn next(&mut self) {
+    if let Some(data) = self.data.as_mut() {
+      data.next();
+    }
+    self.skip_forward();
+  }
+
+  /// Moves the iterator to the previous Key-Value pair.
+  pub fn prev(&mut self) {
+    if let Some(data) = self.data.as_mut() {
+      data.prev();
+    }
+    self.skip_backward();
+  }
+
+  /// Moves forward past the ends of data blocks and index partitions, until the iterator is at a
+  /// Key-Value pair or past the end of the table.
+  fn skip_forward(&mut self) {
+    while self.error.is_none() && !self.data.as_ref().is_some_and(|d| d.valid()) {
+      if let Some(index) = self.index.as_mut() {
+        if index.valid() {
+          index.next();
+        }
+      }
+      if !self.index.as_ref().is_some_and(|i| i.valid()) {
+        if !self.top.valid() {
+          self.data = None;
+          return;
+        }
+        self.top.next();
+        self.load_index();
+        match self.index.as_mut() {
+          Some(index) => index.seek_to_first(),
+          None => {
+            self.data = None;
+            return;
+          }
+        }
+      }
+      self.load_data();
+      match self.data.as_mut() {
+        Some(data) => data.seek_to_first(),
+        None => return,
+      }
+    }
+  }
+
+  /// Moves backward past the starts of data blocks and index partitions, until the iterator is at
+  /// a Key-Value pair or before the start of the table.
+  fn skip_backward(&mut self) {
+    while self.error.is_none() && !self.data.as_ref().is_some_and(|d| d.valid()) {
+      if let Some(index) = self.index.as_mut() {
+        if index.valid() {
+          index.prev();
+        }
+      }
+      if !self.index.as_ref().is_some_and(|i| i.valid()) {
+        if !self.top.valid() {
+          self.data = None;
+          return;
+        }
+        self.top.prev();
+        self.load_index();
+        match self.index.as_mut() {
+          Some(index) => index.seek_to_last(),
+          None => {
+            self.data = None;
+            return;
+          }
+        }
+      }
+      self.load_data();
+      match self.data.as_mut() {
+        Some(data) => data.seek_to_last(),
+        None => return,
+      }
+    }
+  }
+
+  /// Loads the index partition that the top-level index is positioned at.
+  fn load_index(&mut self) {
+    self.index = None;
+    if self.top.valid() {
+      let handle = BlockHandle::decode_from(self.top.value(), &mut 0);
+      self.index = self.open_block(handle);
+    }
+  }
+
+  /// Loads the data block that the index partition is positioned at.
+  fn load_data(&mut self) {
+    self.data = None;
+    if let Some(index) = self.index.as_ref().filter(|i| i.valid()) {
+      let handle = BlockHandle::decode_from(index.value(), &mut 0).and_then(|h| {
+        self
+          .table
+          .open_block_readahead(&h, &self.read_options, &mut self.readahead)
+      });
+      self.data = match handle {
+        Ok(iter) => Some(iter),
+        Err(e) => {
+          self.error = Some(e);
+          None
+        }
+      };
+    }
+  }
+
+  /// Opens a block, keeping the error of a block that cannot be read.
+  fn open_block(&mut self, handle: io::Result<BlockHandle>) -> Option<BlockIterator> {
+    match handle.and_then(|h| self.table.open_block(&h, &self.read_options)) {
+      Ok(iter) => Some(iter),
+      Err(e) => {
+        self.error = Some(e);
+        None
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::block_cache::BlockCache;
+  use crate::compression::LzCompressor;
+  use crate::options::{Options, ReadOptions};
+  use crate::table::{BlockHandle, Table, TableBuilder, DICTIONARY_META_BLOCK};
+  use rand::Rng;
+  use std::fs::{create_dir, remove_dir_all};
+  use std::path::{Path, PathBuf};
+  use std::sync::Arc;
+
+  fn key(i: usize) -> Vec<u8> {
+    format!("org/1234/project/{:06}", i * 2).into_bytes()
+  }
+
+  fn value(i: usize) -> Vec<u8> {
+    format!("{{\"id\":{},\"fruit\":\"Lime\",\"size\":\"large\"}}", i).into_bytes()
+  }
+
+  fn build_table(dir: &Path, options: &Options, n: usize) -> Arc<Table> {
+    let path = dir.join("0.sst");
+    let mut builder = TableBuilder::new(&path, options).unwrap();
+    for i in 0..n {
+      builder.add(&key(i), &value(i)).unwrap();
+    }
+    assert!(builder.add(&key(0), b"").is_err());
+    let properties = builder.finish().unwrap();
+    assert_eq!(properties.num_entries, n as u64);
+
+    let block_cache = Arc::new(BlockCache::new(1024 * 1024));
+    Arc::new(Table::open(&path, options, block_cache).unwrap())
+  }
+
+  #[test]
+  fn test_table_get() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let options = Options::new().block_size(256).partition_size(128);
+    let table = build_table(&dir, &options, 2000);
+    let read_options = ReadOptions::new();
+
+    for i in (0..2000).step_by(7) {
+      assert_eq!(table.get(&key(i), &read_options).unwrap(), Some(value(i)));
+    }
+    assert_eq!(
+      table
+        .get(b"org/1234/project/000001", &read_options)
+        .unwrap(),
+      None
+    );
+    assert_eq!(table.get(b"zzz", &read_options).unwrap(), None);
+
+    let misses = table.block_cache.stats().misses;
+    table.get(&key(14), &read_options).unwrap();
+    assert_eq!(table.block_cache.stats().misses, misses);
+
+    // Missing keys are mostly rejected by the bloom filter, without reading the data block.
+    let misses = table.block_cache.stats().misses;
+    for i in 0..200 {
+      let missing = format!("org/1234/project/{:06}", i * 2 + 1).into_bytes();
+      assert_eq!(table.get(&missing, &read_options).unwrap(), None);
+    }
+    assert!(table.block_cache.stats().misses - misses < 40);
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_table_multi_get() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let options = Options::new().block_size(256).partition_size(128);
+    let table = build_table(&dir, &options, 2000);
+    let read_options = ReadOptions::new();
+
+    let mut keys: Vec<Vec<u8>> = (0..2000).step_by(13).map(key).collect();
+    keys.push(b"org/1234/project/000001".to_vec());
+    keys.push(key(13));
+    let keys: Vec<&[u8]> = keys.iter().map(|k| &k[..]).collect();
+    let values = table.multi_get(&keys, &read_options).unwrap();
+    assert_eq!(values.len(), keys.len());
+    for (i, got) in (0..2000).step_by(13).zip(values.iter()) {
+      assert_eq!(got, &Some(value(i)));
+    }
+    assert_eq!(values[values.len() - 2], None);
+    assert_eq!(values[values.len() - 1], Some(value(13)));
+
+    // The data blocks read by multi_get are cached for later reads.
+    let misses = table.block_cache.stats().misses;
+    table.get(&key(26), &read_options).unwrap();
+    assert_eq!(table.block_cache.stats().misses, misses);
+
+    // Without filling the cache, each partition and data block is still read once per batch.
+    create_dir(dir.join("uncached")).unwrap();
+    let table = build_table(&dir.join("uncached"), &options, 2000);
+    let read_options = ReadOptions::new().fill_cache(false);
+    let mut partitions = 0;
+    let mut data_blocks = 0;
+    let mut top = table.top_index.iter(table.comparator.clone());
+    top.seek_to_first();
+    while top.valid() {
+      let index_handle = BlockHandle::decode_from(top.value(), &mut 0).unwrap();
+      let mut index = table.open_block(&index_handle, &read_options).unwrap();
+      index.seek_to_first();
+      while index.valid() {
+        data_blocks += 1;
+        index.next();
+      }
+      partitions += 1;
+      top.next();
+    }
+    assert!(partitions > 1);
+
+    let keys: Vec<Vec<u8>> = (0..2000).rev().map(key).collect();
+    let keys: Vec<&[u8]> = keys.iter().map(|k| &k[..]).collect();
+    let misses = table.block_cache.stats().misses;
+    let values = table.multi_get(&keys, &read_options).unwrap();
+    for (i, got) in (0..2000).rev().zip(values.iter()) {
+      assert_eq!(got, &Some(value(i)));
+    }
+    assert_eq!(
+      table.block_cache.stats().misses - misses,
+      2 * partitions + data_blocks
+    );
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_table_iterator() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let options = Options::new().block_size(256).partition_size(128);
+    let table = build_table(&dir, &options, 500);
+
+    let mut iter = table.iter(ReadOptions::new());
+    iter.seek_to_first();
+    for i in 0..500 {
+      assert!(iter.valid());
+      assert_eq!(iter.key(), &key(i)[..]);
+      assert_eq!(iter.value(), &value(i)[..]);
+      iter.next();
+    }
+    assert!(!iter.valid());
+
+    iter.seek_to_last();
+    for i in (0..500).rev() {
+      assert_eq!(iter.key(), &key(i)[..]);
+      iter.prev();
+    }
+    assert!(!iter.valid());
+
+    iter.seek(b"org/1234/project/000301");
+    assert_eq!(iter.key(), &key(151)[..]);
+    iter.seek_for_prev(b"org/1234/project/000301");
+    assert_eq!(iter.key(), &key(150)[..]);
+    iter.seek_for_prev(b"zzz");
+    assert_eq!(iter.key(), &key(499)[..]);
+    iter.seek(b"zzz");
+    assert!(!iter.valid());
+    assert!(iter.status().is_none());
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_table_dictionary() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let options = Options::new()
+      .block_size(256)
+      .compression(Arc::new(LzCompressor))
+      .compression_dictionary_size(256);
+    let table = build_table(&dir, &options, 1000);
+    let read_options = ReadOptions::new().verify_checksums(true);
+
+    assert!(table
+      .meta_block(DICTIONARY_META_BLOCK, &read_options)
+      .unwrap()
+      .is_some());
+    for i in (0..1000).step_by(3) {
+      assert_eq!(table.get(&key(i), &read_options).unwrap(), Some(value(i)));
+    }
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_table_direct_io() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let options = Options::new()
+      .block_size(256)
+      .use_direct_reads(true)
+      .use_direct_io_for_flush_and_compaction(true);
+    let table = build_table(&dir, &options, 3000);
+    let read_options = ReadOptions::new().verify_checksums(true);
+
+    for i in (0..3000).step_by(11) {
+      assert_eq!(table.get(&key(i), &read_options).unwrap(), Some(value(i)));
+    }
+    let mut iter = table.iter(ReadOptions::new().fill_cache(false));
+    iter.seek_to_last();
+    assert_eq!(iter.key(), &key(2999)[..]);
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_table_pinned_blocks() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let options = Options::new()
+      .block_size(256)
+      .partition_size(128)
+      .pin_index_and_filter_blocks(true);
+    build_table(&dir, &options, 2000);
+
+    // The cache is too small to hold any block that is not pinned.
+    let block_cache = Arc::new(BlockCache::with_shards(256, 1));
+    let table = Table::open(&dir.join("0.sst"), &options, block_cache.clone()).unwrap();
+    let usage = block_cache.usage();
+    assert!(usage > 256);
+
+    // Only the data block of each key is read from disk.
+    let read_options = ReadOptions::new();
+    let misses = block_cache.stats().misses;
+    for i in (0..2000).step_by(7) {
+      assert_eq!(table.get(&key(i), &read_options).unwrap(), Some(value(i)));
+    }
+    assert_eq!(block_cache.stats().misses - misses, 286);
+    assert_eq!(block_cache.usage(), usage);
+
+    drop(table);
+    assert_eq!(block_cache.usage(), 0);
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_table_readahead() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let options = Options::new().block_size(256).partition_size(128);
+    let table = build_table(&dir, &options, 2000);
+
+    // A bulk scan reads 16 KiB at a time and leaves the data blocks out of the cache.
+    let read_options = ReadOptions::new()
+      .readahead_size(16 * 1024)
+      .fill_cache(false)
+      .verify_checksums(true);
+    let usage = table.block_cache.usage();
+    let mut iter = table.iter(read_options);
+    iter.seek_to_first();
+    for i in 0..2000 {
+      assert!(iter.valid());
+      assert_eq!(iter.key(), &key(i)[..]);
+      assert_eq!(iter.value(), &value(i)[..]);
+      iter.next();
+    }
+    assert!(!iter.valid());
+    assert!(iter.status().is_none());
+    assert_eq!(table.block_cache.usage(), usage);
+
+    iter.seek_to_last();
+    for i in (0..2000).rev() {
+      assert_eq!(iter.key(), &key(i)[..]);
+      iter.prev();
+    }
+    assert!(!iter.valid());
+
+    // A readahead smaller than a block still reads whole blocks.
+    let mut iter = table.iter(ReadOptions::new().readahead_size(1));
+    iter.seek(&key(1000));
+    assert_eq!(iter.value(), &value(1000)[..]);
+
+    remove_dir_all(&dir).unwrap();
+  }
+}
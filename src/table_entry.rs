@@ -0,0 +1,162 @@
+use crate::mem_table::{MemTableEntry, RangeTombstone};
+use crate::utils::{read_varint, write_varint};
+
+/// Name of the meta block holding the range tombstones of a table flushed from a MemTable.
+pub const RANGE_TOMBSTONES_META_BLOCK: &str = "range_tombstones";
+
+const DELETED_FLAG: u8 = 1;
+const VALUE_FLAG: u8 = 2;
+const EXPIRY_FLAG: u8 = 4;
+const BLOB_INDEX_FLAG: u8 = 8;
+
+/// Encodes a MemTable entry as the value of a table entry.
+///
+/// The value is `[flags][timestamp][expiry][value][operands]`. The timestamp and expiry are
+/// little-endian u128s, the expiry and value are only present if their flag is set, and the value
+/// and each operand are prefixed by their length as a varint, as is the number of operands.
+pub fn encode_entry(entry: &MemTableEntry) -> Vec<u8> {
+  let mut flags = 0;
+  if entry.deleted {
+    flags |= DELETED_FLAG;
+  }
+  if entry.value.is_some() {
+    flags |= VALUE_FLAG;
+  }
+  if entry.expires_at.is_some() {
+    flags |= EXPIRY_FLAG;
+  }
+  if entry.blob_index {
+    flags |= BLOB_INDEX_FLAG;
+  }
+
+  let mut buf = vec![flags];
+  buf.extend_from_slice(&entry.timestamp.to_le_bytes());
+  if let Some(expires_at) = entry.expires_at {
+    buf.extend_from_slice(&expires_at.to_le_bytes());
+  }
+  if let Some(value) = entry.value.as_ref() {
+    write_varint(&mut buf, value.len() as u64);
+    buf.extend_from_slice(value);
+  }
+  write_varint(&mut buf, entry.operands.len() as u64);
+  for operand in entry.operands.iter() {
+    write_varint(&mut buf, operand.len() as u64);
+    buf.extend_from_slice(operand);
+  }
+  buf
+}
+
+/// Decodes the value of a table entry as a MemTable entry.
+pub fn decode_entry(key: &[u8], buf: &[u8]) -> Option<MemTableEntry> {
+  let flags = *buf.first()?;
+  let mut pos = 1;
+  let timestamp = read_u128(buf, &mut pos)?;
+  let expires_at = if flags & EXPIRY_FLAG != 0 {
+    Some(read_u128(buf, &mut pos)?)
+  } else {
+    None
+  };
+  let value = if flags & VALUE_FLAG != 0 {
+    Some(read_bytes(buf, &mut pos)?)
+  } else {
+    None
+  };
+  let num_operands = read_varint(buf, &mut pos)?;
+  let mut operands = Vec::new();
+  for _ in 0..num_operands {
+    operands.push(read_bytes(buf, &mut pos)?);
+  }
+
+  Some(MemTableEntry {
+    key: key.to_vec(),
+    value,
+    timestamp,
+    expires_at,
+    deleted: flags & DELETED_FLAG != 0,
+    operands,
+    blob_index: flags & BLOB_INDEX_FLAG != 0,
+  })
+}
+
+/// Encodes range tombstones as a sequence of `[start][end][timestamp]`, with the keys prefixed by
+/// their length as a varint.
+pub fn encode_range_tombstones(range_tombstones: &[RangeTombstone]) -> Vec<u8> {
+  let mut buf = Vec::new();
+  for tombstone in range_tombstones.iter() {
+    write_varint(&mut buf, tombstone.start.len() as u64);
+    buf.extend_from_slice(&tombstone.start);
+    write_varint(&mut buf, tombstone.end.len() as u64);
+    buf.extend_from_slice(&tombstone.end);
+    buf.extend_from_slice(&tombstone.timestamp.to_le_bytes());
+  }
+  buf
+}
+
+/// Decodes range tombstones encoded by `encode_range_tombstones`.
+pub fn decode_range_tombstones(buf: &[u8]) -> Option<Vec<RangeTombstone>> {
+  let mut range_tombstones = Vec::new();
+  let mut pos = 0;
+  while pos < buf.len() {
+    range_tombstones.push(RangeTombstone {
+      start: read_bytes(buf, &mut pos)?,
+      end: read_bytes(buf, &mut pos)?,
+      timestamp: read_u128(buf, &mut pos)?,
+    });
+  }
+  Some(range_tombstones)
+}
+
+/// Reads a little-endian u128 from a buffer at a position.
+fn read_u128(buf: &[u8], pos: &mut usize) -> Option<u128> {
+  let mut bytes = [0; 16];
+  bytes.copy_from_slice(buf.get(*pos..*pos + 16)?);
+  *pos += 16;
+  Some(u128::from_le_bytes(bytes))
+}
+
+/// Reads bytes prefixed by their length as a varint from a buffer at a position.
+fn read_bytes(buf: &[u8], pos: &mut usize) -> Option<Vec<u8>> {
+  let len = read_varint(buf, pos)? as usize;
+  let bytes = buf.get(*pos..pos.checked_add(len)?)?.to_vec();
+  *pos += len;
+  Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::mem_table::{MemTableEntry, RangeTombstone};
+  use crate::table_entry::{
+    decode_entry, decode_range_tombstones, encode_entry, encode_range_tombstones,
+  };
+
+  #[test]
+  fn test_table_entry() {
+    let entry = MemTableEntry {
+      key: b"Lime".to_vec(),
+      value: Some(b"Lime Smoothie".to_vec()),
+      timestamp: 20,
+      expires_at: Some(40),
+      deleted: false,
+      operands: vec![b"1".to_vec(), b"23".to_vec()],
+      blob_index: false,
+    };
+    let decoded = decode_entry(b"Lime", &encode_entry(&entry)).unwrap();
+    assert_eq!(decoded.value, entry.value);
+    assert_eq!(decoded.timestamp, 20);
+    assert_eq!(decoded.expires_at, Some(40));
+    assert_eq!(decoded.operands, entry.operands);
+    assert!(!decoded.deleted);
+    assert!(decode_entry(b"Lime", &encode_entry(&entry)[..20]).is_none());
+
+    let tombstones = vec![RangeTombstone {
+      start: b"Apple".to_vec(),
+      end: b"Lime".to_vec(),
+      timestamp: 30,
+    }];
+    let decoded = decode_range_tombstones(&encode_range_tombstones(&tombstones)).unwrap();
+    assert_eq!(decoded.len(), 1);
+    assert_eq!(decoded[0].start, b"Apple");
+    assert_eq!(decoded[0].end, b"Lime");
+    assert_eq!(decoded[0].timestamp, 30);
+  }
+}
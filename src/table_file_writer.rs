@@ -0,0 +1,73 @@
+use crate::mem_table::MemTableEntry;
+use crate::options::Options;
+use crate::table::{TableBuilder, TableProperties};
+use crate::table_entry::encode_entry;
+use std::io;
+use std::path::Path;
+
+/// Name of the meta block holding the name of the Comparator a TableFileWriter sorted its keys
+/// with.
+pub(crate) const COMPARATOR_META_BLOCK: &str = "comparator";
+
+/// Writes a table file outside of a Database, to be ingested with
+/// `Database::ingest_external_files`.
+///
+/// Keys must be added in increasing order of the Comparator of the Options, which must be the
+/// Comparator of the Database the file is ingested into. The entries are given a timestamp when
+/// they are ingested.
+pub struct TableFileWriter {
+  builder: TableBuilder,
+}
+
+impl TableFileWriter {
+  /// Creates a new table file at a path, truncating an existing file.
+  ///
+  /// The Database copies ingested files, or removes their paths if it moves them, so a path can be
+  /// reused for the next file once the previous one is ingested.
+  pub fn create(path: &str, options: &Options) -> io::Result<TableFileWriter> {
+    let mut builder = TableBuilder::new(Path::new(path), options)?;
+    builder.add_meta_block(
+      COMPARATOR_META_BLOCK,
+      options.comparator.name().as_bytes().to_vec(),
+    );
+    Ok(TableFileWriter { builder })
+  }
+
+  /// Adds a Key-Value pair to the table file.
+  pub fn put(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+    self.add(MemTableEntry {
+      value: Some(value.to_vec()),
+      deleted: false,
+      ..MemTableEntry::tombstone(key, 0)
+    })
+  }
+
+  /// Adds a tombstone that deletes a key from the Database when the table file is ingested.
+  pub fn delete(&mut self, key: &[u8]) -> io::Result<()> {
+    self.add(MemTableEntry::tombstone(key, 0))
+  }
+
+  /// Adds a merge operand of a key, which is combined with the value of the key in the Database
+  /// when it is read.
+  pub fn merge(&mut self, key: &[u8], operand: &[u8]) -> io::Result<()> {
+    self.add(MemTableEntry {
+      deleted: false,
+      operands: vec![operand.to_vec()],
+      ..MemTableEntry::tombstone(key, 0)
+    })
+  }
+
+  /// Gets the number of entries added to the table file.
+  pub fn num_entries(&self) -> u64 {
+    self.builder.num_entries()
+  }
+
+  /// Finishes the table file and syncs it to disk.
+  pub fn finish(self) -> io::Result<TableProperties> {
+    self.builder.finish()
+  }
+
+  fn add(&mut self, entry: MemTableEntry) -> io::Result<()> {
+    self.builder.add(&entry.key, &encode_entry(&entry))
+  }
+}
@@ -0,0 +1,246 @@
+use crate::column_family::DEFAULT_COLUMN_FAMILY_ID;
+use crate::database::Database;
+use crate::utils::now;
+use crate::wal_iterator::{WALEntry, WALRecord};
+
+/// Transaction holds a batch of writes that is applied to the Database with two-phase commit.
+///
+/// Preparing a Transaction writes a prepare marker and its batch to the WAL, after which the
+/// Transaction is decided with a commit or rollback marker. The batch is only applied to the
+/// MemTable once it is committed.
+///
+/// Transactions that were prepared but not decided before a restart are recovered from the WAL
+/// and returned by `Database::prepared_transactions` so the application can decide their fate.
+pub struct Transaction {
+  id: u64,
+  entries: Vec<WALEntry>,
+  prepared: bool,
+}
+
+impl Transaction {
+  /// Creates a new empty Transaction.
+  pub(crate) fn new(id: u64) -> Transaction {
+    Transaction {
+      id,
+      entries: Vec::new(),
+      prepared: false,
+    }
+  }
+
+  /// Creates a prepared Transaction recovered from the WAL.
+  pub(crate) fn recovered(id: u64, entries: Vec<WALEntry>) -> Transaction {
+    Transaction {
+      id,
+      entries,
+      prepared: true,
+    }
+  }
+
+  /// Gets the id of the Transaction.
+  pub fn id(&self) -> u64 {
+    self.id
+  }
+
+  /// Gets if the Transaction has been prepared.
+  pub fn is_prepared(&self) -> bool {
+    self.prepared
+  }
+
+  /// Gets the writes of the Transaction.
+  pub(crate) fn entries(&self) -> &[WALEntry] {
+    &self.entries
+  }
+
+  /// Gets the number of writes in the Transaction.
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  /// Gets if the Transaction has no writes.
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  /// Sets a Key-Value pair in the Transaction.
+  ///
+  /// Panics if the Transaction has already been prepared.
+  pub fn set(&mut self, key: &[u8], value: &[u8]) {
+    assert!(!self.prepared, "Transaction has already been prepared");
+
+    self.entries.push(WALEntry {
+      key: key.to_owned(),
+      value: Some(value.to_owned()),
+      timestamp: now(),
+      expires_at: None,
+      deleted: false,
+      column_family: DEFAULT_COLUMN_FAMILY_ID,
+    });
+  }
+
+  /// Deletes a Key-Value pair in the Transaction.
+  ///
+  /// Panics if the Transaction has already been prepared.
+  pub fn delete(&mut self, key: &[u8]) {
+    assert!(!self.prepared, "Transaction has already been prepared");
+
+    self.entries.push(WALEntry {
+      key: key.to_owned(),
+      value: None,
+      timestamp: now(),
+      expires_at: None,
+      deleted: true,
+      column_family: DEFAULT_COLUMN_FAMILY_ID,
+    });
+  }
+
+  /// Prepares the Transaction by writing a prepare marker and its batch to the WAL.
+  ///
+  /// Preparing a Transaction more than once has no effect.
+  pub fn prepare(&mut self, db: &mut Database) -> Result<usize, usize> {
+    if self.prepared {
+      return Ok(0);
+    }
+
+    let wal = db.wal_mut();
+    if wal.prepare(self.id, &self.entries, now()).is_err() {
+      return Err(0);
+    }
+    if wal.flush().is_err() {
+      return Err(0);
+    }
+    db.track_prepared(self.id, &self.entries);
+    self.prepared = true;
+
+    Ok(self.entries.len())
+  }
+
+  /// Commits the Transaction, applying its batch to the Database.
+  ///
+  /// A Transaction that has not been prepared is prepared first.
+  pub fn commit(mut self, db: &mut Database) -> Result<usize, usize> {
+    self.prepare(db)?;
+
+    let wal = db.wal_mut();
+    if wal.commit(self.id, now()).is_err() {
+      return Err(0);
+    }
+    if wal.flush().is_err() {
+      return Err(0);
+    }
+
+    db.untrack_prepared(self.id);
+
+    let len = self.entries.len();
+    for entry in self.entries.into_iter() {
+      db.apply(&WALRecord::Entry(entry));
+    }
+    if db.flush_if_full().is_err() {
+      return Err(0);
+    }
+
+    Ok(len)
+  }
+
+  /// Rolls back the Transaction, discarding its batch.
+  ///
+  /// A rollback marker is only written if the Transaction was prepared.
+  pub fn rollback(self, db: &mut Database) -> Result<usize, usize> {
+    if !self.prepared {
+      return Ok(0);
+    }
+
+    let wal = db.wal_mut();
+    if wal.rollback(self.id, now()).is_err() {
+      return Err(0);
+    }
+    if wal.flush().is_err() {
+      return Err(0);
+    }
+    db.untrack_prepared(self.id);
+
+    Ok(self.entries.len())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::database::Database;
+  use crate::options::Options;
+  use rand::Rng;
+  use std::fs::remove_dir_all;
+  use std::path::PathBuf;
+
+  #[test]
+  fn test_transaction() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    let path = dir.to_str().unwrap();
+
+    let mut db = Database::open(path, Options::new()).unwrap();
+    db.set(b"Lime", b"Lime Smoothie").unwrap();
+
+    let mut committed = db.begin_transaction();
+    committed.set(b"Apple", b"Apple Smoothie");
+    committed.delete(b"Lime");
+    assert_eq!(committed.prepare(&mut db), Ok(2));
+    assert_eq!(committed.prepare(&mut db), Ok(0));
+    assert!(db.get(b"Apple").unwrap().is_none());
+    assert_eq!(committed.commit(&mut db), Ok(2));
+    assert_eq!(
+      db.get(b"Apple").unwrap().unwrap().value(),
+      b"Apple Smoothie"
+    );
+    assert!(db.get(b"Lime").unwrap().is_none());
+
+    let mut rolled_back = db.begin_transaction();
+    rolled_back.set(b"Orange", b"Orange Smoothie");
+    rolled_back.prepare(&mut db).unwrap();
+    assert_eq!(rolled_back.rollback(&mut db), Ok(1));
+    assert!(db.get(b"Orange").unwrap().is_none());
+
+    let mut unprepared = db.begin_transaction();
+    unprepared.set(b"Cherry", b"Cherry Smoothie");
+    assert_eq!(unprepared.rollback(&mut db), Ok(0));
+
+    // A prepared Transaction outlives a flush and a crash.
+    let mut undecided = db.begin_transaction();
+    undecided.set(b"Mango", b"Mango Smoothie");
+    undecided.prepare(&mut db).unwrap();
+    let id = undecided.id();
+    let mut abandoned = db.begin_transaction();
+    abandoned.set(b"Banana", b"Banana Smoothie");
+    db.flush(true).unwrap();
+    drop(db);
+
+    let mut db = Database::open(path, Options::new()).unwrap();
+    assert_eq!(
+      db.get(b"Apple").unwrap().unwrap().value(),
+      b"Apple Smoothie"
+    );
+    assert!(db.get(b"Lime").unwrap().is_none());
+    assert!(db.get(b"Orange").unwrap().is_none());
+    assert!(db.get(b"Mango").unwrap().is_none());
+    assert!(db.get(b"Banana").unwrap().is_none());
+    let mut prepared = db.prepared_transactions();
+    assert_eq!(prepared.len(), 1);
+    assert_eq!(prepared[0].id(), id);
+    assert!(prepared[0].is_prepared());
+    assert_eq!(prepared[0].len(), 1);
+    assert!(db.begin_transaction().id() > id);
+    assert_eq!(prepared.remove(0).commit(&mut db), Ok(1));
+    assert_eq!(
+      db.get(b"Mango").unwrap().unwrap().value(),
+      b"Mango Smoothie"
+    );
+    drop(db);
+
+    let mut db = Database::open(path, Options::new()).unwrap();
+    assert_eq!(
+      db.get(b"Mango").unwrap().unwrap().value(),
+      b"Mango Smoothie"
+    );
+    assert!(db.prepared_transactions().is_empty());
+
+    remove_dir_all(&dir).unwrap();
+  }
+}
@@ -1,15 +1,98 @@
-use std::fs::read_dir;
+use std::fs::{copy, hard_link, read_dir, File};
+use std::io;
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Gets the set of files with an extension for a given directory.
 pub fn files_with_ext(dir: &Path, ext: &str) -> Vec<PathBuf> {
   let mut files = Vec::new();
   for file in read_dir(dir).unwrap() {
     let path = file.unwrap().path();
-    if path.extension().unwrap() == ext {
+    if path.extension().is_some_and(|e| e == ext) {
       files.push(path);
     }
   }
 
   files
 }
+
+/// Copies a file to a new path and syncs the copy to disk.
+pub fn copy_file(from: &Path, to: &Path) -> io::Result<()> {
+  copy(from, to)?;
+  File::open(to)?.sync_all()
+}
+
+/// Syncs the entries of a directory to disk, so that files created in or renamed into it survive a
+/// crash. Has no effect on platforms where directories cannot be opened.
+pub fn sync_dir(dir: &Path) -> io::Result<()> {
+  #[cfg(unix)]
+  return File::open(dir)?.sync_all();
+  #[cfg(not(unix))]
+  {
+    let _ = dir;
+    Ok(())
+  }
+}
+
+/// Hard links a file to a new path, or copies it if it cannot be linked, such as across file
+/// systems.
+pub fn link_or_copy(from: &Path, to: &Path) -> io::Result<()> {
+  match hard_link(from, to) {
+    Ok(()) => Ok(()),
+    Err(_) => copy_file(from, to),
+  }
+}
+
+/// Gets the current time in microseconds since the UNIX epoch.
+pub fn now() -> u128 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap()
+    .as_micros()
+}
+
+/// Appends an unsigned integer to a buffer as a LEB128 varint.
+pub fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+  while value >= 0x80 {
+    buf.push((value as u8) | 0x80);
+    value >>= 7;
+  }
+  buf.push(value as u8);
+}
+
+/// Reads a LEB128 varint from a buffer at a position, advancing the position past it.
+pub fn read_varint(buf: &[u8], pos: &mut usize) -> Option<u64> {
+  let mut value = 0u64;
+  let mut shift = 0;
+  loop {
+    let byte = *buf.get(*pos)?;
+    *pos += 1;
+    if shift > 63 {
+      return None;
+    }
+    value |= ((byte & 0x7f) as u64) << shift;
+    if byte < 0x80 {
+      return Some(value);
+    }
+    shift += 7;
+  }
+}
+
+/// Computes the CRC-32 (IEEE) checksum of a buffer.
+pub fn crc32(buf: &[u8]) -> u32 {
+  crc32_extend(0, buf)
+}
+
+/// Extends the CRC-32 (IEEE) checksum of the bytes before a buffer with the buffer, so a checksum
+/// can be computed a chunk at a time.
+pub fn crc32_extend(crc: u32, buf: &[u8]) -> u32 {
+  let mut crc = !crc;
+  for byte in buf.iter() {
+    crc ^= *byte as u32;
+    for _ in 0..8 {
+      let mask = (crc & 1).wrapping_neg();
+      crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+    }
+  }
+  !crc
+}
@@ -1,41 +1,79 @@
-use crate::mem_table::MemTable;
-use crate::utils::files_with_ext;
-use crate::wal_iterator::WALEntry;
+use crate::column_family::{
+  ColumnFamily, ColumnFamilyOptions, DEFAULT_COLUMN_FAMILY_ID, DEFAULT_COLUMN_FAMILY_NAME,
+};
+use crate::file_writer::AppendFile;
+use crate::options::Options;
+use crate::transaction::Transaction;
+use crate::utils::{files_with_ext, now};
 use crate::wal_iterator::WALIterator;
-use std::fs::{remove_file, File, OpenOptions};
+use crate::wal_iterator::{WALEntry, WALRecord};
+use std::collections::BTreeMap;
+use std::fs::remove_file;
 use std::io::prelude::*;
 use std::io::{self, BufWriter};
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Record type of a Key-Value pair that was set.
+pub const SET_RECORD: u8 = 0;
+/// Record type of a Key-Value pair that was deleted.
+pub const DELETE_RECORD: u8 = 1;
+/// Record type of a prepared Transaction, followed by the Transaction's batch.
+pub const PREPARE_RECORD: u8 = 2;
+/// Record type of a committed Transaction.
+pub const COMMIT_RECORD: u8 = 3;
+/// Record type of a rolled back Transaction.
+pub const ROLLBACK_RECORD: u8 = 4;
+/// Record type of a merge operand for a key.
+pub const MERGE_RECORD: u8 = 5;
+/// Record type of a range of keys that was deleted.
+pub const RANGE_DELETE_RECORD: u8 = 6;
+/// Record type of a Key-Value pair that was set with an expiry time.
+pub const SET_WITH_EXPIRY_RECORD: u8 = 7;
+/// Record type of a column family id, followed by a Key-Value operation on the column family.
+pub const COLUMN_FAMILY_RECORD: u8 = 8;
+/// Record type of a column family that was created.
+pub const CREATE_COLUMN_FAMILY_RECORD: u8 = 9;
+/// Record type of a column family that was dropped.
+pub const DROP_COLUMN_FAMILY_RECORD: u8 = 10;
+/// Record type of an atomic batch, followed by the batch's Key-Value operations.
+pub const BATCH_RECORD: u8 = 11;
+/// Record type of a Key-Value pair whose value was separated into a blob file.
+pub const BLOB_INDEX_RECORD: u8 = 12;
+
 /// Write Ahead Log(WAL)
 ///
 /// An append-only file that holds the operations performed on the MemTable.
 /// The WAL is intended for recovery of the MemTable when the server is shutdown.
+#[allow(clippy::upper_case_acronyms)]
 pub struct WAL {
   path: PathBuf,
-  file: BufWriter<File>,
+  file: BufWriter<AppendFile>,
 }
 
 impl WAL {
   /// Creates a new WAL in a given directory.
+  ///
+  /// The WAL is named after the current time, so it sorts after every existing WAL.
   pub fn new(dir: &Path) -> io::Result<WAL> {
-    let timestamp = SystemTime::now()
+    let mut timestamp = SystemTime::now()
       .duration_since(UNIX_EPOCH)
       .unwrap()
       .as_micros();
 
-    let path = Path::new(dir).join(timestamp.to_string() + ".wal");
-    let file = OpenOptions::new().append(true).create(true).open(&path)?;
-    let file = BufWriter::new(file);
+    let mut path = Path::new(dir).join(timestamp.to_string() + ".wal");
+    while path.exists() {
+      timestamp += 1;
+      path = Path::new(dir).join(timestamp.to_string() + ".wal");
+    }
+    let file = BufWriter::new(AppendFile::open(&path)?);
 
     Ok(WAL { path, file })
   }
 
   /// Creates a WAL from an existing file path.
   pub fn from_path(path: &Path) -> io::Result<WAL> {
-    let file = OpenOptions::new().append(true).create(true).open(path)?;
-    let file = BufWriter::new(file);
+    let file = BufWriter::new(AppendFile::open(path)?);
 
     Ok(WAL {
       path: path.to_owned(),
@@ -43,46 +81,236 @@ impl WAL {
     })
   }
 
-  /// Loads the WAL(s) within a directory, returning a new WAL and the recovered MemTable.
+  /// Gets the path of the WAL file.
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Gets the number of the WAL, which is the time it was created at.
+  pub fn number(&self) -> u128 {
+    wal_number(&self.path).unwrap_or_default()
+  }
+
+  /// Loads the WAL(s) within a directory, returning a new WAL, the recovered column families and
+  /// the Transactions that were prepared but never committed or rolled back.
+  ///
+  /// If multiple WALs exist in a directory, they are merged by file date. Values that have expired
+  /// are dropped and replaced by a tombstone. Committed Transactions
+  /// are applied to the MemTable at the point of their commit marker, and undecided Transactions
+  /// are written to the new WAL again so they survive another restart.
   ///
-  /// If multiple WALs exist in a directory, they are merged by file date.
-  pub fn load_from_dir(dir: &Path) -> io::Result<(WAL, MemTable)> {
+  /// The default column family is always the first column family returned. The MemTable of every
+  /// column family is sorted with the Comparator of the Options. With paranoid checks, a WAL that
+  /// cannot be read to its end fails the load instead of recovering the records before the
+  /// corruption.
+  pub fn load_from_dir(
+    dir: &Path,
+    options: &Options,
+  ) -> io::Result<(WAL, Vec<ColumnFamily>, Vec<Transaction>)> {
+    let comparator = &options.comparator;
     let mut wal_files = files_with_ext(dir, "wal");
     wal_files.sort();
 
-    let mut new_mem_table = MemTable::new();
+    let mut column_families = vec![ColumnFamily::new(
+      DEFAULT_COLUMN_FAMILY_ID,
+      DEFAULT_COLUMN_FAMILY_NAME,
+      ColumnFamilyOptions::default(),
+      comparator.clone(),
+    )];
     let mut new_wal = WAL::new(dir)?;
+    let mut prepared = BTreeMap::new();
+    let now = now();
     for wal_file in wal_files.iter() {
       if let Ok(wal) = WAL::from_path(wal_file) {
-        for entry in wal.into_iter() {
-          if entry.deleted {
-            new_mem_table.delete(entry.key.as_slice(), entry.timestamp);
-            new_wal.delete(entry.key.as_slice(), entry.timestamp)?;
-          } else {
-            new_mem_table.set(
-              entry.key.as_slice(),
-              entry.value.as_ref().unwrap().as_slice(),
-              entry.timestamp,
-            );
-            new_wal.set(
-              entry.key.as_slice(),
-              entry.value.unwrap().as_slice(),
-              entry.timestamp,
-            )?;
+        let mut records = wal.into_iter();
+        for record in records.by_ref() {
+          match record {
+            WALRecord::Batch(records) => {
+              for record in records {
+                new_wal.recover_record(&mut column_families, record, now)?;
+              }
+            }
+            WALRecord::Prepare {
+              id,
+              entries,
+              timestamp,
+            } => {
+              prepared.insert(id, (entries, timestamp));
+            }
+            WALRecord::Commit { id } => {
+              if let Some((entries, _)) = prepared.remove(&id) {
+                for entry in entries {
+                  new_wal.recover_record(&mut column_families, WALRecord::Entry(entry), now)?;
+                }
+              }
+            }
+            WALRecord::Rollback { id } => {
+              prepared.remove(&id);
+            }
+            WALRecord::CreateColumnFamily { id, name } => {
+              if column_families
+                .iter()
+                .all(|cf| cf.id != id && cf.name != name)
+              {
+                column_families.push(ColumnFamily::new(
+                  id,
+                  &name,
+                  ColumnFamilyOptions::default(),
+                  comparator.clone(),
+                ));
+                new_wal.create_column_family(id, &name)?;
+              }
+            }
+            WALRecord::DropColumnFamily { id } => {
+              if id != DEFAULT_COLUMN_FAMILY_ID {
+                column_families.retain(|cf| cf.id != id);
+                new_wal.drop_column_family(id)?;
+              }
+            }
+            record => {
+              new_wal.recover_record(&mut column_families, record, now)?;
+            }
           }
         }
+        if options.paranoid_checks && records.is_corrupted() {
+          remove_file(&new_wal.path)?;
+          return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("WAL file {} is corrupted", wal_file.display()),
+          ));
+        }
       }
     }
+
+    let mut transactions = Vec::new();
+    for (id, (entries, timestamp)) in prepared.into_iter() {
+      new_wal.prepare(id, &entries, timestamp)?;
+      transactions.push(Transaction::recovered(id, entries));
+    }
+
     new_wal.flush().unwrap();
     wal_files.into_iter().for_each(|f| remove_file(f).unwrap());
 
-    Ok((new_wal, new_mem_table))
+    Ok((new_wal, column_families, transactions))
+  }
+
+  /// Applies a recovered Key-Value operation to the MemTable of its column family and appends it
+  /// to the WAL.
+  ///
+  /// An entry whose value has expired is applied as a tombstone, so it still hides older values.
+  /// Operations on column families that were dropped are discarded.
+  fn recover_record(
+    &mut self,
+    column_families: &mut [ColumnFamily],
+    mut record: WALRecord,
+    now: u128,
+  ) -> io::Result<()> {
+    let column_family = match record
+      .column_family()
+      .and_then(|id| column_families.iter_mut().find(|cf| cf.id == id))
+    {
+      Some(cf) => cf,
+      None => return Ok(()),
+    };
+
+    if let WALRecord::Entry(entry) = &mut record {
+      if entry.expires_at.is_some_and(|e| e <= now) {
+        entry.value = None;
+        entry.expires_at = None;
+        entry.deleted = true;
+      }
+    }
+
+    column_family.apply(&record);
+    self.write_record(&record)
+  }
+
+  /// Appends a Key-Value operation to the WAL.
+  ///
+  /// Operations on a column family other than the default column family are prefixed by the id
+  /// of the column family.
+  pub fn write_record(&mut self, record: &WALRecord) -> io::Result<()> {
+    match record.column_family() {
+      Some(DEFAULT_COLUMN_FAMILY_ID) => {}
+      Some(id) => {
+        self.file.write_all(&(id as u64).to_le_bytes())?;
+        self.file.write_all(&COLUMN_FAMILY_RECORD.to_le_bytes())?;
+      }
+      None => {
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidInput,
+          "record is not a Key-Value operation",
+        ))
+      }
+    }
+
+    match record {
+      WALRecord::Entry(entry) => match (entry.value.as_ref(), entry.expires_at) {
+        (Some(value), Some(expires_at)) if !entry.deleted => {
+          self.set_with_expiry(&entry.key, value, entry.timestamp, expires_at)
+        }
+        (Some(value), None) if !entry.deleted => self.set(&entry.key, value, entry.timestamp),
+        _ => self.delete(&entry.key, entry.timestamp),
+      },
+      WALRecord::Merge(entry) => {
+        let operand = entry.value.as_deref().unwrap_or_default();
+        self.merge(&entry.key, operand, entry.timestamp)
+      }
+      WALRecord::BlobIndex(entry) => {
+        let blob_index = entry.value.as_deref().unwrap_or_default();
+        self.set_blob_index(&entry.key, blob_index, entry.timestamp)
+      }
+      WALRecord::RangeDelete {
+        start,
+        end,
+        timestamp,
+        ..
+      } => self.delete_range(start, end, *timestamp),
+      _ => Ok(()),
+    }
+  }
+
+  /// Appends a batch of Key-Value operations to the WAL.
+  ///
+  /// The batch is applied atomically on recovery, either all of the operations are recovered or
+  /// none of them are.
+  pub fn write_batch(&mut self, records: &[WALRecord], timestamp: u128) -> io::Result<()> {
+    self.file.write_all(&(records.len() as u64).to_le_bytes())?;
+    self.file.write_all(&BATCH_RECORD.to_le_bytes())?;
+    self.file.write_all(&timestamp.to_le_bytes())?;
+    for record in records {
+      self.write_record(record)?;
+    }
+
+    Ok(())
+  }
+
+  /// Creates a column family and the operation is appended to the WAL.
+  pub fn create_column_family(&mut self, id: u32, name: &str) -> io::Result<()> {
+    self.file.write_all(&(id as u64).to_le_bytes())?;
+    self
+      .file
+      .write_all(&CREATE_COLUMN_FAMILY_RECORD.to_le_bytes())?;
+    self.file.write_all(&name.len().to_le_bytes())?;
+    self.file.write_all(name.as_bytes())?;
+
+    Ok(())
+  }
+
+  /// Drops a column family and the operation is appended to the WAL.
+  pub fn drop_column_family(&mut self, id: u32) -> io::Result<()> {
+    self.file.write_all(&(id as u64).to_le_bytes())?;
+    self
+      .file
+      .write_all(&DROP_COLUMN_FAMILY_RECORD.to_le_bytes())?;
+
+    Ok(())
   }
 
   /// Sets a Key-Value pair and the operation is appended to the WAL.
   pub fn set(&mut self, key: &[u8], value: &[u8], timestamp: u128) -> io::Result<()> {
     self.file.write_all(&key.len().to_le_bytes())?;
-    self.file.write_all(&(false as u8).to_le_bytes())?;
+    self.file.write_all(&SET_RECORD.to_le_bytes())?;
     self.file.write_all(&value.len().to_le_bytes())?;
     self.file.write_all(key)?;
     self.file.write_all(value)?;
@@ -91,13 +319,110 @@ impl WAL {
     Ok(())
   }
 
+  /// Sets a Key-Value pair that expires at a time in microseconds since the UNIX epoch and the
+  /// operation is appended to the WAL.
+  pub fn set_with_expiry(
+    &mut self,
+    key: &[u8],
+    value: &[u8],
+    timestamp: u128,
+    expires_at: u128,
+  ) -> io::Result<()> {
+    self.file.write_all(&key.len().to_le_bytes())?;
+    self.file.write_all(&SET_WITH_EXPIRY_RECORD.to_le_bytes())?;
+    self.file.write_all(&value.len().to_le_bytes())?;
+    self.file.write_all(key)?;
+    self.file.write_all(value)?;
+    self.file.write_all(&timestamp.to_le_bytes())?;
+    self.file.write_all(&expires_at.to_le_bytes())?;
+
+    Ok(())
+  }
+
   /// Deletes a Key-Value pair and the operation is appended to the WAL.
   ///
   /// This is achieved using tombstones.
   pub fn delete(&mut self, key: &[u8], timestamp: u128) -> io::Result<()> {
     self.file.write_all(&key.len().to_le_bytes())?;
-    self.file.write_all(&(true as u8).to_le_bytes())?;
+    self.file.write_all(&DELETE_RECORD.to_le_bytes())?;
+    self.file.write_all(key)?;
+    self.file.write_all(&timestamp.to_le_bytes())?;
+
+    Ok(())
+  }
+
+  /// Deletes every Key-Value pair from `start` (inclusive) to `end` (exclusive) and the operation
+  /// is appended to the WAL.
+  ///
+  /// This is achieved using a single range tombstone.
+  pub fn delete_range(&mut self, start: &[u8], end: &[u8], timestamp: u128) -> io::Result<()> {
+    self.file.write_all(&start.len().to_le_bytes())?;
+    self.file.write_all(&RANGE_DELETE_RECORD.to_le_bytes())?;
+    self.file.write_all(&end.len().to_le_bytes())?;
+    self.file.write_all(start)?;
+    self.file.write_all(end)?;
+    self.file.write_all(&timestamp.to_le_bytes())?;
+
+    Ok(())
+  }
+
+  /// Merges an operand into a Key-Value pair and the operation is appended to the WAL.
+  pub fn merge(&mut self, key: &[u8], operand: &[u8], timestamp: u128) -> io::Result<()> {
+    self.file.write_all(&key.len().to_le_bytes())?;
+    self.file.write_all(&MERGE_RECORD.to_le_bytes())?;
+    self.file.write_all(&operand.len().to_le_bytes())?;
+    self.file.write_all(key)?;
+    self.file.write_all(operand)?;
+    self.file.write_all(&timestamp.to_le_bytes())?;
+
+    Ok(())
+  }
+
+  /// Sets a Key-Value pair whose value is in a blob file and the operation is appended to the WAL.
+  pub fn set_blob_index(
+    &mut self,
+    key: &[u8],
+    blob_index: &[u8],
+    timestamp: u128,
+  ) -> io::Result<()> {
+    self.file.write_all(&key.len().to_le_bytes())?;
+    self.file.write_all(&BLOB_INDEX_RECORD.to_le_bytes())?;
+    self.file.write_all(&blob_index.len().to_le_bytes())?;
     self.file.write_all(key)?;
+    self.file.write_all(blob_index)?;
+    self.file.write_all(&timestamp.to_le_bytes())?;
+
+    Ok(())
+  }
+
+  /// Prepares a Transaction by appending a prepare marker followed by the Transaction's batch.
+  ///
+  /// The batch is not applied on recovery until a commit marker with the same id is found.
+  pub fn prepare(&mut self, id: u64, entries: &[WALEntry], timestamp: u128) -> io::Result<()> {
+    self.file.write_all(&id.to_le_bytes())?;
+    self.file.write_all(&PREPARE_RECORD.to_le_bytes())?;
+    self.file.write_all(&(entries.len() as u64).to_le_bytes())?;
+    self.file.write_all(&timestamp.to_le_bytes())?;
+    for entry in entries {
+      self.write_record(&WALRecord::Entry(entry.clone()))?;
+    }
+
+    Ok(())
+  }
+
+  /// Appends a commit marker for a prepared Transaction.
+  pub fn commit(&mut self, id: u64, timestamp: u128) -> io::Result<()> {
+    self.file.write_all(&id.to_le_bytes())?;
+    self.file.write_all(&COMMIT_RECORD.to_le_bytes())?;
+    self.file.write_all(&timestamp.to_le_bytes())?;
+
+    Ok(())
+  }
+
+  /// Appends a rollback marker for a prepared Transaction.
+  pub fn rollback(&mut self, id: u64, timestamp: u128) -> io::Result<()> {
+    self.file.write_all(&id.to_le_bytes())?;
+    self.file.write_all(&ROLLBACK_RECORD.to_le_bytes())?;
     self.file.write_all(&timestamp.to_le_bytes())?;
 
     Ok(())
@@ -111,11 +436,22 @@ impl WAL {
   pub fn flush(&mut self) -> io::Result<()> {
     self.file.flush()
   }
+
+  /// Flushes the WAL and syncs it to disk.
+  pub fn sync(&mut self) -> io::Result<()> {
+    self.file.flush()?;
+    self.file.get_ref().sync_all()
+  }
+}
+
+/// Gets the number of a WAL from its path.
+pub fn wal_number(path: &Path) -> Option<u128> {
+  path.file_stem()?.to_str()?.parse().ok()
 }
 
 impl IntoIterator for WAL {
   type IntoIter = WALIterator;
-  type Item = WALEntry;
+  type Item = WALRecord;
 
   /// Converts a WAL into a `WALIterator` to iterate over the entries.
   fn into_iter(self) -> WALIterator {
@@ -125,12 +461,15 @@ impl IntoIterator for WAL {
 
 #[cfg(test)]
 mod tests {
-  use crate::wal::WAL;
+  use crate::column_family::DEFAULT_COLUMN_FAMILY_ID;
+  use crate::options::Options;
+  use crate::wal::{BATCH_RECORD, CREATE_COLUMN_FAMILY_RECORD, SET_RECORD, WAL};
+  use crate::wal_iterator::{WALEntry, WALRecord};
   use rand::Rng;
   use std::fs::{create_dir, remove_dir_all};
   use std::fs::{metadata, File, OpenOptions};
   use std::io::prelude::*;
-  use std::io::BufReader;
+  use std::io::{self, BufReader};
   use std::path::PathBuf;
   use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -283,7 +622,8 @@ mod tests {
     let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
     create_dir(&dir).unwrap();
 
-    let (new_wal, new_mem_table) = WAL::load_from_dir(&dir).unwrap();
+    let (new_wal, column_families, _) = WAL::load_from_dir(&dir, &Options::default()).unwrap();
+    let new_mem_table = &column_families[0].mem_table;
     assert_eq!(new_mem_table.len(), 0);
 
     let m = metadata(new_wal.path).unwrap();
@@ -311,7 +651,8 @@ mod tests {
     }
     wal.flush().unwrap();
 
-    let (new_wal, new_mem_table) = WAL::load_from_dir(&dir).unwrap();
+    let (new_wal, column_families, _) = WAL::load_from_dir(&dir, &Options::default()).unwrap();
+    let new_mem_table = &column_families[0].mem_table;
 
     let file = OpenOptions::new().read(true).open(&new_wal.path).unwrap();
     let mut reader = BufReader::new(file);
@@ -356,7 +697,8 @@ mod tests {
     }
     wal_2.flush().unwrap();
 
-    let (new_wal, new_mem_table) = WAL::load_from_dir(&dir).unwrap();
+    let (new_wal, column_families, _) = WAL::load_from_dir(&dir, &Options::default()).unwrap();
+    let new_mem_table = &column_families[0].mem_table;
 
     let file = OpenOptions::new().read(true).open(&new_wal.path).unwrap();
     let mut reader = BufReader::new(file);
@@ -386,4 +728,266 @@ mod tests {
 
     remove_dir_all(&dir).unwrap();
   }
+
+  #[test]
+  fn test_read_wal_transactions() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let batch = |key: &[u8], value: &[u8], timestamp: u128| {
+      vec![WALEntry {
+        key: key.to_owned(),
+        value: Some(value.to_owned()),
+        timestamp,
+        expires_at: None,
+        deleted: false,
+        column_family: DEFAULT_COLUMN_FAMILY_ID,
+      }]
+    };
+
+    let mut wal = WAL::new(&dir).unwrap();
+    wal.set(b"Apple", b"Apple Smoothie", 0).unwrap();
+    wal
+      .prepare(0, &batch(b"Lime", b"Lime Smoothie", 1), 1)
+      .unwrap();
+    wal
+      .prepare(1, &batch(b"Orange", b"Orange Smoothie", 2), 2)
+      .unwrap();
+    wal
+      .prepare(2, &batch(b"Mango", b"Mango Smoothie", 3), 3)
+      .unwrap();
+    wal.commit(0, 4).unwrap();
+    wal.rollback(1, 5).unwrap();
+    wal.flush().unwrap();
+
+    let (new_wal, column_families, prepared) =
+      WAL::load_from_dir(&dir, &Options::default()).unwrap();
+    let new_mem_table = &column_families[0].mem_table;
+
+    assert_eq!(new_mem_table.len(), 2);
+    assert_eq!(new_mem_table.get(b"Apple").unwrap().timestamp, 0);
+    assert_eq!(new_mem_table.get(b"Lime").unwrap().timestamp, 1);
+    assert!(new_mem_table.get(b"Orange").is_none());
+    assert!(new_mem_table.get(b"Mango").is_none());
+
+    assert_eq!(prepared.len(), 1);
+    assert_eq!(prepared[0].id(), 2);
+    assert!(prepared[0].is_prepared());
+    assert_eq!(prepared[0].len(), 1);
+    drop(new_wal);
+
+    // The undecided Transaction must survive another restart.
+    let (_, column_families, prepared) = WAL::load_from_dir(&dir, &Options::default()).unwrap();
+    let new_mem_table = &column_families[0].mem_table;
+    assert_eq!(new_mem_table.len(), 2);
+    assert_eq!(prepared.len(), 1);
+    assert_eq!(prepared[0].id(), 2);
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_read_wal_merge() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let mut wal = WAL::new(&dir).unwrap();
+    wal.set(b"Fruits", b"Apple", 0).unwrap();
+    wal.merge(b"Fruits", b"Lime", 1).unwrap();
+    wal.merge(b"Fruits", b"Orange", 2).unwrap();
+    wal.flush().unwrap();
+
+    let (_, column_families, _) = WAL::load_from_dir(&dir, &Options::default()).unwrap();
+    let new_mem_table = &column_families[0].mem_table;
+
+    let mem_e = new_mem_table.get(b"Fruits").unwrap();
+    assert_eq!(mem_e.value.as_ref().unwrap(), b"Apple");
+    assert_eq!(mem_e.operands, vec![b"Lime".to_vec(), b"Orange".to_vec()]);
+    assert_eq!(mem_e.timestamp, 2);
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_read_wal_delete_range() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let mut wal = WAL::new(&dir).unwrap();
+    wal.set(b"Apple", b"Apple Smoothie", 0).unwrap();
+    wal.set(b"Lime", b"Lime Smoothie", 1).unwrap();
+    wal.delete_range(b"Banana", b"Orange", 2).unwrap();
+    wal.set(b"Mango", b"Mango Smoothie", 3).unwrap();
+    wal.flush().unwrap();
+
+    let (_, column_families, _) = WAL::load_from_dir(&dir, &Options::default()).unwrap();
+    let new_mem_table = &column_families[0].mem_table;
+
+    assert_eq!(new_mem_table.len(), 2);
+    assert!(new_mem_table.get(b"Apple").is_some());
+    assert!(new_mem_table.get(b"Lime").is_none());
+    assert_eq!(new_mem_table.get(b"Mango").unwrap().timestamp, 3);
+    assert_eq!(new_mem_table.range_tombstones().len(), 1);
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_read_wal_expiry() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let mut wal = WAL::new(&dir).unwrap();
+    wal.set(b"Apple", b"Apple Smoothie", 0).unwrap();
+    wal.set_with_expiry(b"Apple", b"Apple Pie", 1, 2).unwrap();
+    wal
+      .set_with_expiry(b"Lime", b"Lime Smoothie", 3, u128::MAX)
+      .unwrap();
+    wal.flush().unwrap();
+
+    let (_, column_families, _) = WAL::load_from_dir(&dir, &Options::default()).unwrap();
+    let new_mem_table = &column_families[0].mem_table;
+
+    let mem_e = new_mem_table.get(b"Apple").unwrap();
+    assert!(mem_e.deleted);
+    assert_eq!(mem_e.value, None);
+    assert_eq!(mem_e.timestamp, 1);
+
+    let mem_e = new_mem_table.get(b"Lime").unwrap();
+    assert_eq!(mem_e.value.as_ref().unwrap(), b"Lime Smoothie");
+    assert_eq!(mem_e.expires_at, Some(u128::MAX));
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_read_wal_column_families() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let entry = |key: &[u8], column_family: u32| {
+      WALRecord::Entry(WALEntry {
+        key: key.to_owned(),
+        value: Some(b"Smoothie".to_vec()),
+        timestamp: 0,
+        expires_at: None,
+        deleted: false,
+        column_family,
+      })
+    };
+
+    let mut wal = WAL::new(&dir).unwrap();
+    wal.create_column_family(1, "fruits").unwrap();
+    wal.create_column_family(2, "vegetables").unwrap();
+    wal.write_record(&entry(b"Apple", 1)).unwrap();
+    wal.write_record(&entry(b"Carrot", 2)).unwrap();
+    wal
+      .write_batch(&[entry(b"Lime", 0), entry(b"Orange", 1)], 0)
+      .unwrap();
+    wal.drop_column_family(2).unwrap();
+    wal.flush().unwrap();
+
+    let path = wal.path.clone();
+    let (_, column_families, _) = WAL::load_from_dir(&dir, &Options::default()).unwrap();
+
+    assert_eq!(column_families.len(), 2);
+    assert_eq!(column_families[0].name, "default");
+    assert_eq!(column_families[0].mem_table.len(), 1);
+    assert!(column_families[0].mem_table.get(b"Lime").is_some());
+    assert_eq!(column_families[1].name, "fruits");
+    assert_eq!(column_families[1].mem_table.len(), 2);
+    assert!(column_families[1].mem_table.get(b"Apple").is_some());
+    assert!(column_families[1].mem_table.get(b"Orange").is_some());
+    assert!(!path.exists());
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_read_wal_partial_batch() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let entry = |key: &[u8]| {
+      WALRecord::Entry(WALEntry {
+        key: key.to_owned(),
+        value: Some(b"Smoothie".to_vec()),
+        timestamp: 0,
+        expires_at: None,
+        deleted: false,
+        column_family: DEFAULT_COLUMN_FAMILY_ID,
+      })
+    };
+
+    let mut wal = WAL::new(&dir).unwrap();
+    wal.write_record(&entry(b"Apple")).unwrap();
+    wal
+      .write_batch(&[entry(b"Lime"), entry(b"Orange")], 0)
+      .unwrap();
+    wal.flush().unwrap();
+
+    // Cut off the end of the last operation in the batch.
+    let len = metadata(&wal.path).unwrap().len();
+    let file = OpenOptions::new().write(true).open(&wal.path).unwrap();
+    file.set_len(len - 4).unwrap();
+
+    let (_, column_families, _) = WAL::load_from_dir(&dir, &Options::default()).unwrap();
+    let new_mem_table = &column_families[0].mem_table;
+
+    assert_eq!(new_mem_table.len(), 1);
+    assert!(new_mem_table.get(b"Apple").is_some());
+
+    remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_read_wal_corrupted_lengths() {
+    let mut rng = rand::thread_rng();
+    let dir = PathBuf::from(format!("./{}/", rng.gen::<u32>()));
+    create_dir(&dir).unwrap();
+
+    let entry = WALRecord::Entry(WALEntry {
+      key: b"Apple".to_vec(),
+      value: Some(b"Smoothie".to_vec()),
+      timestamp: 0,
+      expires_at: None,
+      deleted: false,
+      column_family: DEFAULT_COLUMN_FAMILY_ID,
+    });
+    let huge = (1u64 << 62).to_le_bytes();
+    let records: [Vec<u8>; 4] = [
+      [&huge[..], &[SET_RECORD], &4u64.to_le_bytes()].concat(),
+      [&5u64.to_le_bytes()[..], &[SET_RECORD], &huge].concat(),
+      [&huge[..], &[BATCH_RECORD], &[0; 16]].concat(),
+      [
+        &1u64.to_le_bytes()[..],
+        &[CREATE_COLUMN_FAMILY_RECORD],
+        &huge,
+      ]
+      .concat(),
+    ];
+
+    // A length that runs past the end of the file is corrupted, and nothing is allocated for it.
+    for record in records.iter() {
+      let mut wal = WAL::new(&dir).unwrap();
+      wal.write_record(&entry).unwrap();
+      wal.flush().unwrap();
+      let mut file = OpenOptions::new().append(true).open(&wal.path).unwrap();
+      file.write_all(record).unwrap();
+
+      let options = Options::new().paranoid_checks(true);
+      let err = WAL::load_from_dir(&dir, &options).err().unwrap();
+      assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+      let (_, column_families, _) = WAL::load_from_dir(&dir, &Options::default()).unwrap();
+      assert_eq!(column_families[0].mem_table.len(), 1);
+    }
+
+    remove_dir_all(&dir).unwrap();
+  }
 }
@@ -1,78 +1,301 @@
+use crate::column_family::DEFAULT_COLUMN_FAMILY_ID;
+use crate::wal::BLOB_INDEX_RECORD;
+use crate::wal::{
+  BATCH_RECORD, COLUMN_FAMILY_RECORD, COMMIT_RECORD, CREATE_COLUMN_FAMILY_RECORD, DELETE_RECORD,
+  DROP_COLUMN_FAMILY_RECORD, MERGE_RECORD, PREPARE_RECORD, RANGE_DELETE_RECORD, ROLLBACK_RECORD,
+  SET_RECORD, SET_WITH_EXPIRY_RECORD,
+};
 use std::fs::{File, OpenOptions};
 use std::io::prelude::*;
 use std::io::{self, BufReader};
 use std::path::PathBuf;
 
+#[derive(Clone)]
 pub struct WALEntry {
   pub key: Vec<u8>,
   pub value: Option<Vec<u8>>,
   pub timestamp: u128,
+  pub expires_at: Option<u128>,
   pub deleted: bool,
+  pub column_family: u32,
+}
+
+/// A record in a WAL file.
+///
+/// Most records are single Key-Value operations, and merge records carry their operand as the
+/// value of the entry. Two-phase commit adds a prepare record,
+/// which carries the batch of a Transaction, and the commit and rollback markers that decide
+/// the fate of a prepared Transaction.
+///
+/// Key-Value operations belong to a column family. Operations on the default column family are
+/// written as is, while operations on any other column family are prefixed by its id.
+///
+/// Large values that were separated into blob files are written as blob index records, whose value
+/// is the encoded BlobIndex pointing to the value.
+pub enum WALRecord {
+  Entry(WALEntry),
+  Merge(WALEntry),
+  BlobIndex(WALEntry),
+  RangeDelete {
+    start: Vec<u8>,
+    end: Vec<u8>,
+    timestamp: u128,
+    column_family: u32,
+  },
+  Batch(Vec<WALRecord>),
+  Prepare {
+    id: u64,
+    entries: Vec<WALEntry>,
+    timestamp: u128,
+  },
+  Commit {
+    id: u64,
+  },
+  Rollback {
+    id: u64,
+  },
+  CreateColumnFamily {
+    id: u32,
+    name: String,
+  },
+  DropColumnFamily {
+    id: u32,
+  },
+}
+
+impl WALRecord {
+  /// Gets the column family of a Key-Value operation.
+  ///
+  /// Returns `None` for records that are not Key-Value operations.
+  pub fn column_family(&self) -> Option<u32> {
+    match self {
+      WALRecord::Entry(entry) | WALRecord::Merge(entry) | WALRecord::BlobIndex(entry) => {
+        Some(entry.column_family)
+      }
+      WALRecord::RangeDelete { column_family, .. } => Some(*column_family),
+      _ => None,
+    }
+  }
+
+  /// Sets the column family of a Key-Value operation.
+  ///
+  /// Returns `None` for records that are not Key-Value operations.
+  pub fn with_column_family(mut self, id: u32) -> Option<WALRecord> {
+    match &mut self {
+      WALRecord::Entry(entry) | WALRecord::Merge(entry) | WALRecord::BlobIndex(entry) => {
+        entry.column_family = id
+      }
+      WALRecord::RangeDelete { column_family, .. } => *column_family = id,
+      _ => return None,
+    }
+    Some(self)
+  }
 }
 
 /// WAL iterator to iterate over the items in a WAL file.
+///
+/// Lengths read from the file are checked against the bytes left in it before anything is
+/// allocated for them, so a corrupted length stops the iteration like any other corrupted record.
 pub struct WALIterator {
   reader: BufReader<File>,
+  position: u64,
+  len: u64,
+  corrupted: bool,
 }
 
 impl WALIterator {
   /// Creates a new WALIterator from a path to a WAL file.
   pub fn new(path: PathBuf) -> io::Result<WALIterator> {
     let file = OpenOptions::new().read(true).open(path)?;
+    let len = file.metadata()?.len();
     let reader = BufReader::new(file);
-    Ok(WALIterator { reader })
+    Ok(WALIterator {
+      reader,
+      position: 0,
+      len,
+      corrupted: false,
+    })
   }
-}
 
-impl Iterator for WALIterator {
-  type Item = WALEntry;
+  /// Checks if iteration stopped at a record that could not be read, rather than at the end of
+  /// the WAL file.
+  pub fn is_corrupted(&self) -> bool {
+    self.corrupted
+  }
 
-  /// Gets the next entry in the WAL file.
-  fn next(&mut self) -> Option<WALEntry> {
-    let mut len_buffer = [0; 8];
-    if self.reader.read_exact(&mut len_buffer).is_err() {
-      return None;
-    }
-    let key_len = usize::from_le_bytes(len_buffer);
+  /// Reads exactly enough bytes from the WAL file to fill a buffer.
+  fn read_exact(&mut self, buffer: &mut [u8]) -> Option<()> {
+    self.reader.read_exact(buffer).ok()?;
+    self.position += buffer.len() as u64;
+    Some(())
+  }
 
-    let mut bool_buffer = [0; 1];
-    if self.reader.read_exact(&mut bool_buffer).is_err() {
-      return None;
+  /// Checks that a length read from the WAL file fits in the bytes left in the file.
+  ///
+  /// The file may have grown since its length was last read, so the length is read again before a
+  /// length is rejected.
+  fn check_len(&mut self, len: u64) -> Option<usize> {
+    if len > self.len.saturating_sub(self.position) {
+      self.len = self.reader.get_ref().metadata().ok()?.len();
+      if len > self.len.saturating_sub(self.position) {
+        return None;
+      }
     }
-    let deleted = bool_buffer[0] != 0;
+    Some(len as usize)
+  }
+
+  /// Reads a length-prefixed number of bytes from the WAL file.
+  fn read_bytes(&mut self, len: u64) -> Option<Vec<u8>> {
+    let mut buffer = vec![0; self.check_len(len)?];
+    self.read_exact(&mut buffer)?;
+    Some(buffer)
+  }
+
+  /// Reads a little-endian u64 from the WAL file.
+  fn read_u64(&mut self) -> Option<u64> {
+    let mut buffer = [0; 8];
+    self.read_exact(&mut buffer)?;
+    Some(u64::from_le_bytes(buffer))
+  }
+
+  /// Reads a little-endian u128 timestamp from the WAL file.
+  fn read_timestamp(&mut self) -> Option<u128> {
+    let mut buffer = [0; 16];
+    self.read_exact(&mut buffer)?;
+    Some(u128::from_le_bytes(buffer))
+  }
+
+  /// Reads the record type that follows the leading length/id field of every record.
+  fn read_record_type(&mut self) -> Option<u8> {
+    let mut buffer = [0; 1];
+    self.read_exact(&mut buffer)?;
+    Some(buffer[0])
+  }
+
+  /// Reads a Key-Value operation from the WAL file.
+  fn read_operation(&mut self) -> Option<WALRecord> {
+    self.next().filter(|r| r.column_family().is_some())
+  }
 
-    let mut key = vec![0; key_len];
+  /// Reads the remainder of a Key-Value entry with an expiry time after the key length and record type.
+  fn read_entry_with_expiry(&mut self, key_len: u64) -> Option<WALEntry> {
+    let mut entry = self.read_entry_body(key_len, false)?;
+    entry.expires_at = Some(self.read_timestamp()?);
+    Some(entry)
+  }
+
+  /// Reads the remainder of a Key-Value entry after the key length and record type.
+  fn read_entry_body(&mut self, key_len: u64, deleted: bool) -> Option<WALEntry> {
+    let key;
     let mut value = None;
     if deleted {
-      if self.reader.read_exact(&mut key).is_err() {
-        return None;
-      }
+      key = self.read_bytes(key_len)?;
     } else {
-      if self.reader.read_exact(&mut len_buffer).is_err() {
-        return None;
-      }
-      let value_len = usize::from_le_bytes(len_buffer);
-      if self.reader.read_exact(&mut key).is_err() {
-        return None;
-      }
-      let mut value_buf = vec![0; value_len];
-      if self.reader.read_exact(&mut value_buf).is_err() {
-        return None;
-      }
-      value = Some(value_buf);
+      let value_len = self.read_u64()?;
+      key = self.read_bytes(key_len)?;
+      value = Some(self.read_bytes(value_len)?);
     }
 
-    let mut timestamp_buffer = [0; 16];
-    if self.reader.read_exact(&mut timestamp_buffer).is_err() {
-      return None;
-    }
-    let timestamp = u128::from_le_bytes(timestamp_buffer);
+    let timestamp = self.read_timestamp()?;
 
     Some(WALEntry {
       key,
       value,
       timestamp,
+      expires_at: None,
       deleted,
+      column_family: DEFAULT_COLUMN_FAMILY_ID,
     })
   }
 }
+
+impl Iterator for WALIterator {
+  type Item = WALRecord;
+
+  /// Gets the next record in the WAL file.
+  ///
+  /// Prepare and batch records are only returned if all of their operations were written to the file.
+  fn next(&mut self) -> Option<WALRecord> {
+    if self.reader.fill_buf().ok()?.is_empty() {
+      return None;
+    }
+
+    let record = self.read_record();
+    if record.is_none() {
+      self.corrupted = true;
+    }
+    record
+  }
+}
+
+impl WALIterator {
+  /// Reads the next record in the WAL file, returning `None` if it is incomplete or unknown.
+  fn read_record(&mut self) -> Option<WALRecord> {
+    let len_or_id = self.read_u64()?;
+
+    match self.read_record_type()? {
+      SET_RECORD => Some(WALRecord::Entry(self.read_entry_body(len_or_id, false)?)),
+      DELETE_RECORD => Some(WALRecord::Entry(self.read_entry_body(len_or_id, true)?)),
+      SET_WITH_EXPIRY_RECORD => Some(WALRecord::Entry(self.read_entry_with_expiry(len_or_id)?)),
+      MERGE_RECORD => Some(WALRecord::Merge(self.read_entry_body(len_or_id, false)?)),
+      BLOB_INDEX_RECORD => Some(WALRecord::BlobIndex(
+        self.read_entry_body(len_or_id, false)?,
+      )),
+      RANGE_DELETE_RECORD => {
+        let entry = self.read_entry_body(len_or_id, false)?;
+        Some(WALRecord::RangeDelete {
+          start: entry.key,
+          end: entry.value.unwrap(),
+          timestamp: entry.timestamp,
+          column_family: DEFAULT_COLUMN_FAMILY_ID,
+        })
+      }
+      COLUMN_FAMILY_RECORD => self.read_operation()?.with_column_family(len_or_id as u32),
+      BATCH_RECORD => {
+        self.read_timestamp()?;
+        // Every operation takes at least a byte of the file.
+        let mut records = Vec::with_capacity(self.check_len(len_or_id)?);
+        for _ in 0..len_or_id {
+          records.push(self.read_operation()?);
+        }
+        Some(WALRecord::Batch(records))
+      }
+      PREPARE_RECORD => {
+        let count = self.read_u64()?;
+        let timestamp = self.read_timestamp()?;
+        let mut entries = Vec::with_capacity(self.check_len(count)?);
+        for _ in 0..count {
+          match self.read_operation()? {
+            WALRecord::Entry(entry) => entries.push(entry),
+            _ => return None,
+          }
+        }
+        Some(WALRecord::Prepare {
+          id: len_or_id,
+          entries,
+          timestamp,
+        })
+      }
+      COMMIT_RECORD => {
+        self.read_timestamp()?;
+        Some(WALRecord::Commit { id: len_or_id })
+      }
+      ROLLBACK_RECORD => {
+        self.read_timestamp()?;
+        Some(WALRecord::Rollback { id: len_or_id })
+      }
+      CREATE_COLUMN_FAMILY_RECORD => {
+        let name_len = self.read_u64()?;
+        let name = self.read_bytes(name_len)?;
+        Some(WALRecord::CreateColumnFamily {
+          id: len_or_id as u32,
+          name: String::from_utf8(name).ok()?,
+        })
+      }
+      DROP_COLUMN_FAMILY_RECORD => Some(WALRecord::DropColumnFamily {
+        id: len_or_id as u32,
+      }),
+      _ => None,
+    }
+  }
+}
@@ -0,0 +1,86 @@
+use crate::column_family::{DEFAULT_COLUMN_FAMILY_ID, DEFAULT_COLUMN_FAMILY_NAME};
+use crate::utils::now;
+use crate::wal_iterator::{WALEntry, WALRecord};
+
+/// WriteBatch holds a batch of writes that is applied to the Database atomically.
+///
+/// The writes in a WriteBatch may span column families, and are written to the WAL as a single
+/// batch record so that either all of them are recovered or none of them are.
+#[derive(Default)]
+pub struct WriteBatch {
+  records: Vec<(String, WALRecord)>,
+}
+
+impl WriteBatch {
+  /// Creates a new empty WriteBatch.
+  pub fn new() -> WriteBatch {
+    WriteBatch {
+      records: Vec::new(),
+    }
+  }
+
+  /// Gets the number of writes in the WriteBatch.
+  pub fn len(&self) -> usize {
+    self.records.len()
+  }
+
+  /// Gets if the WriteBatch has no writes.
+  pub fn is_empty(&self) -> bool {
+    self.records.is_empty()
+  }
+
+  /// Sets a Key-Value pair in the default column family.
+  pub fn set(&mut self, key: &[u8], value: &[u8]) {
+    self.set_cf(DEFAULT_COLUMN_FAMILY_NAME, key, value);
+  }
+
+  /// Sets a Key-Value pair in a column family.
+  pub fn set_cf(&mut self, column_family: &str, key: &[u8], value: &[u8]) {
+    self.push(column_family, key, Some(value), false);
+  }
+
+  /// Deletes a Key-Value pair in the default column family.
+  pub fn delete(&mut self, key: &[u8]) {
+    self.delete_cf(DEFAULT_COLUMN_FAMILY_NAME, key);
+  }
+
+  /// Deletes a Key-Value pair in a column family.
+  pub fn delete_cf(&mut self, column_family: &str, key: &[u8]) {
+    self.push(column_family, key, None, false);
+  }
+
+  /// Merges an operand into a Key-Value pair in the default column family.
+  pub fn merge(&mut self, key: &[u8], operand: &[u8]) {
+    self.merge_cf(DEFAULT_COLUMN_FAMILY_NAME, key, operand);
+  }
+
+  /// Merges an operand into a Key-Value pair in a column family.
+  pub fn merge_cf(&mut self, column_family: &str, key: &[u8], operand: &[u8]) {
+    self.push(column_family, key, Some(operand), true);
+  }
+
+  /// Adds a write to the WriteBatch.
+  ///
+  /// The column family id of the write is resolved when the WriteBatch is applied.
+  fn push(&mut self, column_family: &str, key: &[u8], value: Option<&[u8]>, merge: bool) {
+    let entry = WALEntry {
+      key: key.to_owned(),
+      value: value.map(|v| v.to_owned()),
+      timestamp: now(),
+      expires_at: None,
+      deleted: value.is_none(),
+      column_family: DEFAULT_COLUMN_FAMILY_ID,
+    };
+    let record = if merge {
+      WALRecord::Merge(entry)
+    } else {
+      WALRecord::Entry(entry)
+    };
+    self.records.push((column_family.to_owned(), record));
+  }
+
+  /// Converts the WriteBatch into its writes and the name of each write's column family.
+  pub(crate) fn into_records(self) -> Vec<(String, WALRecord)> {
+    self.records
+  }
+}